@@ -0,0 +1,421 @@
+use wide::*;
+
+/// Matrix amount applied to the modulations of an [`Algorithm`].
+pub const MODULATION_AMOUNT: f32 = 0.3;
+/// Matrix amount applied to the feedback connections of an [`Algorithm`].
+pub const FEEDBACK_AMOUNT: f32 = 0.1;
+
+/// A named routing for the modulation matrix. Operators are numbered from 1 like on the DX7, the
+/// DX7 algorithms leave Osc7 and Osc8 unused.
+#[derive(Debug, Clone, Copy)]
+pub struct Algorithm {
+    pub name: &'static str,
+    /// `(source, target)` pairs, where `source` modulates `target`.
+    pub modulations: &'static [(usize, usize)],
+    /// Operators that are heard directly.
+    pub carriers: &'static [usize],
+    /// `(source, target)` pairs for feedback, which is usually an operator modulating itself but
+    /// a few DX7 algorithms feed back across a stack.
+    pub feedback: &'static [(usize, usize)],
+}
+impl Algorithm {
+    /// Matrix amounts indexed by `[target][source]`, like [`crate::voice::ModMatrix::new`].
+    pub fn amounts(&self) -> [[f32; 8]; 8] {
+        let mut amounts = [[0.0; 8]; 8];
+        for &(source, target) in self.modulations {
+            amounts[target - 1][source - 1] = MODULATION_AMOUNT;
+        }
+        for &(source, target) in self.feedback {
+            amounts[target - 1][source - 1] = FEEDBACK_AMOUNT;
+        }
+        amounts
+    }
+    pub fn is_carrier(&self, operator: usize) -> bool {
+        self.carriers.contains(&(operator + 1))
+    }
+}
+
+pub const ALGORITHMS: [Algorithm; 40] = [
+    Algorithm {
+        name: "DX7 1",
+        modulations: &[(6, 5), (5, 4), (4, 3), (2, 1)],
+        carriers: &[1, 3],
+        feedback: &[(6, 6)],
+    },
+    Algorithm {
+        name: "DX7 2",
+        modulations: &[(6, 5), (5, 4), (4, 3), (2, 1)],
+        carriers: &[1, 3],
+        feedback: &[(2, 2)],
+    },
+    Algorithm {
+        name: "DX7 3",
+        modulations: &[(3, 2), (2, 1), (6, 5), (5, 4)],
+        carriers: &[1, 4],
+        feedback: &[(6, 6)],
+    },
+    Algorithm {
+        name: "DX7 4",
+        modulations: &[(3, 2), (2, 1), (6, 5), (5, 4)],
+        carriers: &[1, 4],
+        feedback: &[(4, 6)],
+    },
+    Algorithm {
+        name: "DX7 5",
+        modulations: &[(2, 1), (4, 3), (6, 5)],
+        carriers: &[1, 3, 5],
+        feedback: &[(6, 6)],
+    },
+    Algorithm {
+        name: "DX7 6",
+        modulations: &[(2, 1), (4, 3), (6, 5)],
+        carriers: &[1, 3, 5],
+        feedback: &[(5, 6)],
+    },
+    Algorithm {
+        name: "DX7 7",
+        modulations: &[(2, 1), (4, 3), (5, 3), (6, 5)],
+        carriers: &[1, 3],
+        feedback: &[(6, 6)],
+    },
+    Algorithm {
+        name: "DX7 8",
+        modulations: &[(2, 1), (4, 3), (5, 3), (6, 5)],
+        carriers: &[1, 3],
+        feedback: &[(4, 4)],
+    },
+    Algorithm {
+        name: "DX7 9",
+        modulations: &[(2, 1), (4, 3), (5, 3), (6, 5)],
+        carriers: &[1, 3],
+        feedback: &[(2, 2)],
+    },
+    Algorithm {
+        name: "DX7 10",
+        modulations: &[(3, 2), (2, 1), (5, 4), (6, 4)],
+        carriers: &[1, 4],
+        feedback: &[(3, 3)],
+    },
+    Algorithm {
+        name: "DX7 11",
+        modulations: &[(3, 2), (2, 1), (5, 4), (6, 4)],
+        carriers: &[1, 4],
+        feedback: &[(6, 6)],
+    },
+    Algorithm {
+        name: "DX7 12",
+        modulations: &[(2, 1), (4, 3), (5, 3), (6, 3)],
+        carriers: &[1, 3],
+        feedback: &[(2, 2)],
+    },
+    Algorithm {
+        name: "DX7 13",
+        modulations: &[(2, 1), (4, 3), (5, 3), (6, 3)],
+        carriers: &[1, 3],
+        feedback: &[(6, 6)],
+    },
+    Algorithm {
+        name: "DX7 14",
+        modulations: &[(2, 1), (4, 3), (5, 4), (6, 4)],
+        carriers: &[1, 3],
+        feedback: &[(6, 6)],
+    },
+    Algorithm {
+        name: "DX7 15",
+        modulations: &[(2, 1), (4, 3), (5, 4), (6, 4)],
+        carriers: &[1, 3],
+        feedback: &[(2, 2)],
+    },
+    Algorithm {
+        name: "DX7 16",
+        modulations: &[(2, 1), (4, 3), (3, 1), (6, 5), (5, 1)],
+        carriers: &[1],
+        feedback: &[(6, 6)],
+    },
+    Algorithm {
+        name: "DX7 17",
+        modulations: &[(2, 1), (4, 3), (3, 1), (6, 5), (5, 1)],
+        carriers: &[1],
+        feedback: &[(2, 2)],
+    },
+    Algorithm {
+        name: "DX7 18",
+        modulations: &[(2, 1), (3, 1), (6, 5), (5, 4), (4, 1)],
+        carriers: &[1],
+        feedback: &[(3, 3)],
+    },
+    Algorithm {
+        name: "DX7 19",
+        modulations: &[(3, 2), (2, 1), (6, 4), (6, 5)],
+        carriers: &[1, 4, 5],
+        feedback: &[(6, 6)],
+    },
+    Algorithm {
+        name: "DX7 20",
+        modulations: &[(3, 1), (3, 2), (5, 4), (6, 4)],
+        carriers: &[1, 2, 4],
+        feedback: &[(3, 3)],
+    },
+    Algorithm {
+        name: "DX7 21",
+        modulations: &[(3, 1), (3, 2), (6, 4), (6, 5)],
+        carriers: &[1, 2, 4, 5],
+        feedback: &[(3, 3)],
+    },
+    Algorithm {
+        name: "DX7 22",
+        modulations: &[(2, 1), (6, 3), (6, 4), (6, 5)],
+        carriers: &[1, 3, 4, 5],
+        feedback: &[(6, 6)],
+    },
+    Algorithm {
+        name: "DX7 23",
+        modulations: &[(3, 2), (6, 4), (6, 5)],
+        carriers: &[1, 2, 4, 5],
+        feedback: &[(6, 6)],
+    },
+    Algorithm {
+        name: "DX7 24",
+        modulations: &[(6, 3), (6, 4), (6, 5)],
+        carriers: &[1, 2, 3, 4, 5],
+        feedback: &[(6, 6)],
+    },
+    Algorithm {
+        name: "DX7 25",
+        modulations: &[(6, 4), (6, 5)],
+        carriers: &[1, 2, 3, 4, 5],
+        feedback: &[(6, 6)],
+    },
+    Algorithm {
+        name: "DX7 26",
+        modulations: &[(3, 2), (5, 4), (6, 4)],
+        carriers: &[1, 2, 4],
+        feedback: &[(6, 6)],
+    },
+    Algorithm {
+        name: "DX7 27",
+        modulations: &[(3, 2), (5, 4), (6, 4)],
+        carriers: &[1, 2, 4],
+        feedback: &[(3, 3)],
+    },
+    Algorithm {
+        name: "DX7 28",
+        modulations: &[(2, 1), (5, 4), (4, 3)],
+        carriers: &[1, 3, 6],
+        feedback: &[(5, 5)],
+    },
+    Algorithm {
+        name: "DX7 29",
+        modulations: &[(4, 3), (6, 5)],
+        carriers: &[1, 2, 3, 5],
+        feedback: &[(6, 6)],
+    },
+    Algorithm {
+        name: "DX7 30",
+        modulations: &[(5, 4), (4, 3)],
+        carriers: &[1, 2, 3, 6],
+        feedback: &[(5, 5)],
+    },
+    Algorithm {
+        name: "DX7 31",
+        modulations: &[(6, 5)],
+        carriers: &[1, 2, 3, 4, 5],
+        feedback: &[(6, 6)],
+    },
+    Algorithm {
+        name: "DX7 32",
+        modulations: &[],
+        carriers: &[1, 2, 3, 4, 5, 6],
+        feedback: &[(6, 6)],
+    },
+    Algorithm {
+        name: "8-Op Stack",
+        modulations: &[(8, 7), (7, 6), (6, 5), (5, 4), (4, 3), (3, 2), (2, 1)],
+        carriers: &[1],
+        feedback: &[(8, 8)],
+    },
+    Algorithm {
+        name: "Two 4-Op Stacks",
+        modulations: &[(4, 3), (3, 2), (2, 1), (8, 7), (7, 6), (6, 5)],
+        carriers: &[1, 5],
+        feedback: &[(4, 4), (8, 8)],
+    },
+    Algorithm {
+        name: "Four Pairs",
+        modulations: &[(2, 1), (4, 3), (6, 5), (8, 7)],
+        carriers: &[1, 3, 5, 7],
+        feedback: &[(2, 2), (4, 4), (6, 6), (8, 8)],
+    },
+    Algorithm {
+        name: "Seven Into One",
+        modulations: &[(2, 1), (3, 1), (4, 1), (5, 1), (6, 1), (7, 1), (8, 1)],
+        carriers: &[1],
+        feedback: &[(8, 8)],
+    },
+    Algorithm {
+        name: "One Into Seven",
+        modulations: &[(8, 1), (8, 2), (8, 3), (8, 4), (8, 5), (8, 6), (8, 7)],
+        carriers: &[1, 2, 3, 4, 5, 6, 7],
+        feedback: &[(8, 8)],
+    },
+    Algorithm {
+        name: "Stacks and Pair",
+        modulations: &[(3, 2), (2, 1), (6, 5), (5, 4), (8, 7)],
+        carriers: &[1, 4, 7],
+        feedback: &[(3, 3), (6, 6), (8, 8)],
+    },
+    Algorithm {
+        name: "Twin Diamonds",
+        modulations: &[
+            (4, 3),
+            (4, 2),
+            (3, 1),
+            (2, 1),
+            (8, 7),
+            (8, 6),
+            (7, 5),
+            (6, 5),
+        ],
+        carriers: &[1, 5],
+        feedback: &[(4, 4), (8, 8)],
+    },
+    Algorithm {
+        name: "Additive",
+        modulations: &[],
+        carriers: &[1, 2, 3, 4, 5, 6, 7, 8],
+        feedback: &[],
+    },
+];
+
+/// Order to evaluate the oscillators in so that modulators run before the oscillators they
+/// modulate, letting the matrix work without a 1-sample delay.
+#[derive(Debug, Clone, Copy)]
+pub struct EvaluationOrder {
+    /// Masks of the oscillators in each level. Oscillators only depend on lower levels, apart
+    /// from connections that are part of a cycle.
+    levels: [f32x8; 8],
+    level_count: usize,
+    /// Masks of the sources that each target reads in order, the ones outside a cycle with it.
+    ordered: [f32x8; 8],
+}
+impl EvaluationOrder {
+    /// `connections[target][source]` is true when `source` modulates `target`.
+    pub fn new(connections: [[bool; 8]; 8]) -> Self {
+        let levels = levels(connections);
+        let level_count = levels.iter().max().unwrap() + 1;
+        let reaches = reaches(connections);
+        let mask =
+            |lanes: [bool; 8]| f32x8::from(lanes.map(|l| if l { 1.0 } else { 0.0 })).cmp_eq(1.0);
+        Self {
+            levels: std::array::from_fn(|level| mask(levels.map(|l| l == level))),
+            level_count,
+            ordered: std::array::from_fn(|target| {
+                mask(std::array::from_fn(|source| {
+                    connections[target][source] && !reaches[target][source]
+                }))
+            }),
+        }
+    }
+    pub fn levels(&self) -> &[f32x8] {
+        &self.levels[..self.level_count]
+    }
+    /// The source outputs each target reads, `current` for sources ordered before it and
+    /// `previous` for the ones in a cycle with it.
+    pub fn sources(&self, previous: f32x8, current: f32x8) -> [f32x8; 8] {
+        self.ordered.map(|ordered| ordered.blend(current, previous))
+    }
+}
+
+/// `reaches[a][b]` is true when there's a path from `a` to `b`.
+fn reaches(connections: [[bool; 8]; 8]) -> [[bool; 8]; 8] {
+    let mut reaches = [[false; 8]; 8];
+    for target in 0..8 {
+        for source in 0..8 {
+            reaches[source][target] = connections[target][source];
+        }
+    }
+    for via in 0..8 {
+        for a in 0..8 {
+            for b in 0..8 {
+                reaches[a][b] |= reaches[a][via] && reaches[via][b];
+            }
+        }
+    }
+    reaches
+}
+
+/// Topologically sorts the matrix into levels, where each oscillator's level is one more than the
+/// highest level modulating it. Connections inside a cycle (including self-modulation) are left
+/// out since they can't be ordered, those keep the 1-sample delay.
+pub fn levels(connections: [[bool; 8]; 8]) -> [usize; 8] {
+    let reaches = reaches(connections);
+    let mut levels = [0; 8];
+    // The longest path in the remaining graph is at most 7 connections
+    for _ in 0..8 {
+        for target in 0..8 {
+            for source in 0..8 {
+                let in_cycle = reaches[target][source];
+                if connections[target][source] && !in_cycle {
+                    levels[target] = levels[target].max(levels[source] + 1);
+                }
+            }
+        }
+    }
+    levels
+}
+
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_levels() {
+        let mut connections = [[false; 8]; 8];
+        // Osc3 -> Osc2 -> Osc1, with Osc3 also modulating itself
+        connections[1][2] = true;
+        connections[0][1] = true;
+        connections[2][2] = true;
+        // Osc6 -> Osc5 -> Osc4 -> Osc6 cycle, with Osc4 also modulating Osc7
+        connections[4][5] = true;
+        connections[3][4] = true;
+        connections[5][3] = true;
+        connections[6][3] = true;
+        assert_eq!(levels(connections), [2, 1, 0, 0, 0, 0, 1, 0]);
+    }
+
+    #[test]
+    fn test_cycle_reads_previous() {
+        let mut connections = [[false; 8]; 8];
+        // Osc3 -> Osc1, with Osc1 and Osc2 modulating each other
+        connections[0][2] = true;
+        connections[0][1] = true;
+        connections[1][0] = true;
+        let order = EvaluationOrder::new(connections);
+        let sources = order.sources(f32x8::splat(0.0), f32x8::splat(1.0));
+        // Osc1 reads Osc3's current output but Osc2's previous one
+        assert_eq!(sources[0].to_array()[2], 1.0);
+        assert_eq!(sources[0].to_array()[1], 0.0);
+        assert_eq!(sources[1].to_array()[0], 0.0);
+    }
+
+    #[test]
+    fn test_algorithms() {
+        for algorithm in ALGORITHMS {
+            let operators = algorithm.modulations.iter().chain(algorithm.feedback);
+            for &(source, target) in operators {
+                assert!((1..=8).contains(&source) && (1..=8).contains(&target));
+            }
+            // Every modulator should end up being heard through a carrier
+            for &(source, _) in algorithm.modulations {
+                let mut operator = source;
+                while !algorithm.carriers.contains(&operator) {
+                    operator = algorithm
+                        .modulations
+                        .iter()
+                        .find(|(source, _)| *source == operator)
+                        .unwrap_or_else(|| panic!("{}: Osc{operator} isn't heard", algorithm.name))
+                        .1;
+                }
+            }
+        }
+    }
+}
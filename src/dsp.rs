@@ -115,6 +115,220 @@ pub mod approximation {
     }
 }
 
+/// Polynomial corrections for band-limiting naive waveforms at their discontinuities.
+pub mod bandlimit {
+    /// PolyBLEP residual for a step discontinuity at phase 0. `phase` is 0 to 1 and
+    /// `phase_delta` is the phase increment per sample.
+    pub fn poly_blep(phase: f32, phase_delta: f32) -> f32 {
+        if phase < phase_delta {
+            let t = phase / phase_delta;
+            t + t - t * t - 1.0
+        } else if phase > 1.0 - phase_delta {
+            let t = (phase - 1.0) / phase_delta;
+            t * t + t + t + 1.0
+        } else {
+            0.0
+        }
+    }
+
+    /// PolyBLAMP residual for a slope discontinuity at phase 0, the integral of [`poly_blep`].
+    pub fn poly_blamp(phase: f32, phase_delta: f32) -> f32 {
+        if phase < phase_delta {
+            let t = phase / phase_delta - 1.0;
+            -t * t * t / 3.0
+        } else if phase > 1.0 - phase_delta {
+            let t = (phase - 1.0) / phase_delta + 1.0;
+            t * t * t / 3.0
+        } else {
+            0.0
+        }
+    }
+
+    /// Band-limited sawtooth rising from -1 to 1.
+    pub fn saw(phase: f32, phase_delta: f32) -> f32 {
+        phase * 2.0 - 1.0 - poly_blep(phase, phase_delta)
+    }
+
+    /// Band-limited square, high for the first half of the cycle.
+    pub fn square(phase: f32, phase_delta: f32) -> f32 {
+        let naive = if phase < 0.5 { 1.0 } else { -1.0 };
+        naive + poly_blep(phase, phase_delta) - poly_blep((phase + 0.5).fract(), phase_delta)
+    }
+
+    /// Band-limited triangle, at -1 on phase 0 and 1 on phase 0.5.
+    pub fn triangle(phase: f32, phase_delta: f32) -> f32 {
+        let naive = 1.0 - 4.0 * (phase - 0.5).abs();
+        naive
+            + 4.0 * phase_delta
+                * (poly_blamp(phase, phase_delta) - poly_blamp((phase + 0.5).fract(), phase_delta))
+    }
+
+    mod test {
+        use super::*;
+
+        #[test]
+        fn test_poly_blep() {
+            // No correction away from the discontinuity
+            assert_eq!(poly_blep(0.5, 0.01), 0.0);
+            // The correction is continuous across the wrap and cancels the jump of the saw
+            assert!((poly_blep(0.0, 0.01) + 1.0).abs() < 1e-6);
+            assert!((poly_blep(0.999999, 0.01) - 1.0).abs() < 1e-3);
+            assert!((saw(0.0, 0.01) - saw(0.999999, 0.01)).abs() < 1e-3);
+        }
+    }
+}
+
+pub mod noise {
+    use std::f32::consts::TAU;
+
+    use super::approximation::exp2_taylor5;
+
+    /// Xorshift32 random number generator, cheap enough to run every sample for every oscillator.
+    #[derive(Debug, Clone, Copy)]
+    pub struct Xorshift32(u32);
+    impl Xorshift32 {
+        pub fn new(seed: u32) -> Self {
+            // Xorshift gets stuck at 0
+            Self(seed.max(1))
+        }
+        pub fn next_u32(&mut self) -> u32 {
+            let mut x = self.0;
+            x ^= x << 13;
+            x ^= x >> 17;
+            x ^= x << 5;
+            self.0 = x;
+            x
+        }
+        /// Uniform value from -1 to 1.
+        pub fn next_f32(&mut self) -> f32 {
+            (self.next_u32() >> 8) as f32 / (1 << 23) as f32 - 1.0
+        }
+    }
+
+    /// Per-oscillator noise state for the different noise colours and the tone filter.
+    #[derive(Debug, Clone, Copy)]
+    pub struct NoiseGenerator {
+        rng: Xorshift32,
+        pink: [f32; 3],
+        brown: f32,
+        digital_phase: f32,
+        digital_value: f32,
+        tone: f32,
+    }
+    impl NoiseGenerator {
+        pub fn new(seed: u32) -> Self {
+            Self {
+                rng: Xorshift32::new(seed),
+                pink: [0.0; 3],
+                brown: 0.0,
+                digital_phase: 0.0,
+                digital_value: 0.0,
+                tone: 0.0,
+            }
+        }
+        pub fn white(&mut self) -> f32 {
+            self.rng.next_f32()
+        }
+        /// Paul Kellet's economy pink noise filter, -3dB per octave.
+        pub fn pink(&mut self) -> f32 {
+            let white = self.white();
+            self.pink[0] = 0.99765 * self.pink[0] + white * 0.0990460;
+            self.pink[1] = 0.96300 * self.pink[1] + white * 0.2965164;
+            self.pink[2] = 0.57000 * self.pink[2] + white * 1.0526913;
+            (self.pink[0] + self.pink[1] + self.pink[2] + white * 0.1848) * PINK_GAIN
+        }
+        /// Leaky integrated white noise, -6dB per octave.
+        pub fn brown(&mut self) -> f32 {
+            self.brown = (self.brown + self.white() * 0.02) / 1.02;
+            self.brown * BROWN_GAIN
+        }
+        /// 1-bit noise held for 1/16th of the oscillator's cycle, so it follows the oscillator's
+        /// pitch like the noise channel of old sound chips.
+        pub fn digital(&mut self, phase_delta: f32) -> f32 {
+            self.digital_phase += phase_delta.abs() * 16.0;
+            if self.digital_phase >= 1.0 {
+                self.digital_phase = self.digital_phase.fract();
+                self.digital_value = if self.rng.next_u32() & 1 == 0 { -1.0 } else { 1.0 };
+            }
+            self.digital_value
+        }
+        /// One-pole tone filter. Negative `tone` lowpasses and positive `tone` highpasses, with
+        /// the cutoff sweeping exponentially between 20Hz and 20kHz. 0 leaves the noise as is.
+        pub fn tone(&mut self, input: f32, tone: f32, sample_rate: f32) -> f32 {
+            if tone == 0.0 {
+                return input;
+            }
+            let cutoff = if tone < 0.0 {
+                20000.0 * exp2_taylor5(tone * 10.0)
+            } else {
+                20.0 * exp2_taylor5(tone * 10.0)
+            };
+            let coefficient = 1.0 - (-TAU * (cutoff / sample_rate).min(0.5)).exp();
+            self.tone += (input - self.tone) * coefficient;
+            if tone < 0.0 {
+                self.tone
+            } else {
+                input - self.tone
+            }
+        }
+    }
+
+    /// Keeps the peaks of pink and brown noise around +/-1, like white noise.
+    const PINK_GAIN: f32 = 0.11;
+    const BROWN_GAIN: f32 = 3.5;
+
+    mod test {
+        use super::*;
+
+        #[test]
+        fn test_noise_range() {
+            let mut noise = NoiseGenerator::new(1);
+            for _ in 0..100000 {
+                let white = noise.white();
+                assert!((-1.0..=1.0).contains(&white));
+                assert!(noise.brown().abs() <= 1.0);
+            }
+        }
+    }
+}
+
+pub mod follower {
+    /// Peak envelope follower with separate attack and release times.
+    #[derive(Debug, Clone, Copy, Default)]
+    pub struct EnvelopeFollower {
+        level: f32,
+    }
+    impl EnvelopeFollower {
+        /// `attack` and `release` are in seconds.
+        pub fn process(&mut self, input: f32, attack: f32, release: f32, sample_rate: f32) -> f32 {
+            let input = input.abs();
+            let time = if input > self.level { attack } else { release };
+            let coefficient = (-1.0 / (time.max(0.0001) * sample_rate)).exp();
+            self.level = input + (self.level - input) * coefficient;
+            self.level
+        }
+    }
+
+    mod test {
+        use super::*;
+
+        #[test]
+        fn test_follower() {
+            let mut follower = EnvelopeFollower::default();
+            let mut level = 0.0;
+            for i in 0..4800 {
+                // Full-scale square wave, the follower should rise to its peak
+                let input = if i % 100 < 50 { 1.0 } else { -1.0 };
+                level = follower.process(input, 0.001, 0.1, 48000.0);
+            }
+            assert!(level > 0.99);
+            for _ in 0..48000 {
+                level = follower.process(0.0, 0.001, 0.1, 48000.0);
+            }
+            assert!(level < 0.001);
+        }
+    }
+}
 
 pub mod oscillators {
     use std::f32::consts::TAU;
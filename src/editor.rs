@@ -1,239 +1,88 @@
+use nih_plug::params::persist::PersistentField;
 use nih_plug::prelude::FloatParam;
-use nih_plug::prelude::{util, Editor, GuiContext};
-use nih_plug_iced::canvas::{Cache, Fill};
+use nih_plug::prelude::{util, Editor, GuiContext, Param, ParamPtr, Params};
+use nih_plug_iced::canvas::Cache;
 use nih_plug_iced::renderer::Renderer;
 use nih_plug_iced::widget::image;
 use nih_plug_iced::widgets as nih_widgets;
 use nih_plug_iced::IcedState;
 use nih_plug_iced::*;
+use realfft::{RealFftPlanner, RealToComplex};
 use std::default;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use wide::f32x8;
 
-use crate::parameters::{OscMod, OscillatorParams, SynthPluginParams};
-use crate::voice::{OscParams, OscParamsBatch, OscillatorBatch};
+use crate::algorithm::{Algorithm, ALGORITHMS, FEEDBACK_AMOUNT, MODULATION_AMOUNT};
+use crate::macros::{MacroMapping, MacroOffsets, MacroShape, MACRO_COUNT, MAX_MAPPINGS};
+use crate::metering::{Meters, SCOPE_SIZE};
+use crate::midi_learn::{CcMapping, CcMode, CcValues, MAX_CC_MAPPINGS};
+use crate::parameters::{MultiStageParams, OscMod, OscillatorParams, SynthPluginParams};
+use crate::voice::{
+    EnvelopeMode, MatrixInput, MatrixMode, OscParams, OscParamsBatch, OscillatorBatch,
+};
+use crate::wavetable::{WavetableSource, Wavetables};
 
+use self::algorithm_graph::AlgorithmGraph;
+use self::envelope::{EnvelopeEditor, EnvelopeParams};
+use self::history::History;
 use self::param_slider::ParamSlider;
+use self::randomiser::Gene;
+use self::scope::Scope;
+use self::voice_preview::VoicePreview;
 
+mod algorithm_graph;
 mod envelope;
+mod history;
 mod param_slider;
+mod randomiser;
+mod scope;
+mod voice_preview;
+
+/// Size of the editor at a scale of 100%.
+const WIDTH: u32 = 1150;
+const HEIGHT: u32 = 650;
+const MIN_SCALE: f32 = 0.75;
+const MAX_SCALE: f32 = 2.0;
+/// Scales the UI scale buttons step through.
+const SCALE_STEPS: [f32; 6] = [0.75, 1.0, 1.25, 1.5, 1.75, 2.0];
+/// A MIDI controller's gesture ends once it hasn't moved for this long.
+const CC_GESTURE_TIMEOUT: Duration = Duration::from_millis(300);
 
 pub(crate) fn default_state() -> Arc<IcedState> {
-    IcedState::from_size(1150, 650)
+    IcedState::from_size(WIDTH, HEIGHT)
 }
 pub(crate) fn editor_state_with_scale(scale: f32) -> Arc<IcedState> {
-    IcedState::from_size((1150 as f32 * scale) as u32, (650 as f32 * scale) as u32)
+    IcedState::from_size(
+        (WIDTH as f32 * scale).round() as u32,
+        (HEIGHT as f32 * scale).round() as u32,
+    )
+}
+
+/// The user's UI scale. It isn't stored separately, it's the persisted window size relative to
+/// the default size. Every size in the layout goes through it.
+#[derive(Debug, Clone, Copy)]
+struct Scale(f32);
+impl Scale {
+    fn from_state(state: &IcedState) -> Self {
+        Self((state.size().0 as f32 / WIDTH as f32).clamp(MIN_SCALE, MAX_SCALE))
+    }
+    fn px(self, pixels: u16) -> u16 {
+        (pixels as f32 * self.0).round() as u16
+    }
+    fn len(self, pixels: u16) -> Length {
+        Length::Units(self.px(pixels))
+    }
 }
 
 pub(crate) fn create(
     params: Arc<SynthPluginParams>,
+    meters: Arc<Meters>,
+    cc_values: Arc<CcValues>,
     editor_state: Arc<IcedState>,
 ) -> Option<Box<dyn Editor>> {
-    create_iced_editor::<SynthPluginEditor>(editor_state, params)
-}
-
-#[derive(Debug, Clone)]
-struct EnvelopeWidget {
-    size: f32,
-    delay: f32,
-    attack: f32,
-    attack_height: f32,
-    hold: f32,
-    decay: f32,
-    sustain: f32,
-    release: f32,
-    release_height: f32,
-}
-impl EnvelopeWidget {
-    fn new(
-        delay: f32,
-        attack: f32,
-        attack_height: f32,
-        hold: f32,
-        decay: f32,
-        sustain: f32,
-        release: f32,
-        release_height: f32,
-    ) -> Self {
-        Self {
-            size: 5.0,
-            delay,
-            attack,
-            hold,
-            attack_height,
-            decay,
-            sustain,
-            release,
-            release_height,
-        }
-    }
-}
-impl canvas::Program<Message> for EnvelopeWidget {
-    fn draw(&self, bounds: Rectangle, _cursor: canvas::Cursor) -> Vec<canvas::Geometry> {
-        // We prepare a new `Frame`
-        let mut frame = canvas::Frame::new(bounds.size());
-        let y_offset = 2.0;
-        let y_margin = 0.8;
-        let x_offset = 2.0;
-        let x_margin = 0.9;
-
-        let sustain_length = 1.0;
-        let total_length =
-            self.delay + self.attack + self.hold + self.decay + self.release + sustain_length;
-        let (
-            delay_length,
-            attack_length,
-            hold_length,
-            decay_length,
-            release_length,
-            sustain_length,
-        ) = (
-            self.delay / total_length,
-            self.attack / total_length,
-            self.hold / total_length,
-            self.decay / total_length,
-            self.release / total_length,
-            sustain_length / total_length,
-        );
-        let (attack_height, sustain, release_height) = (
-            self.attack_height * y_margin,
-            self.sustain * y_margin,
-            self.release_height * y_margin,
-        );
-
-        let bottom = y_offset + bounds.height * y_margin;
-        let top = y_offset;
-        let points = vec![
-            Point::new(
-                x_offset,
-                y_offset + bounds.height * (y_margin - attack_height),
-            ),
-            Point::new(
-                x_offset + bounds.width * delay_length * x_margin,
-                y_offset + bounds.height * (y_margin - attack_height),
-            ),
-            Point::new(
-                x_offset + bounds.width * (delay_length + attack_length) * x_margin,
-                top,
-            ),
-            Point::new(
-                x_offset + bounds.width * (delay_length + attack_length + hold_length) * x_margin,
-                top,
-            ),
-            Point::new(
-                x_offset
-                    + bounds.width
-                        * (delay_length + attack_length + hold_length + decay_length)
-                        * x_margin,
-                y_offset + bounds.height * (y_margin - sustain),
-            ),
-            Point::new(
-                x_offset
-                    + bounds.width
-                        * (delay_length
-                            + attack_length
-                            + hold_length
-                            + decay_length
-                            + sustain_length)
-                        * x_margin,
-                y_offset + bounds.height * (y_margin - sustain),
-            ),
-            Point::new(
-                x_offset
-                    + bounds.width
-                        * (delay_length
-                            + attack_length
-                            + hold_length
-                            + decay_length
-                            + sustain_length
-                            + release_length)
-                        * x_margin,
-                y_offset + bounds.height * (y_margin - release_height),
-            ),
-        ];
-        let mut time_marks = Vec::new();
-        for i in 0..=((total_length / 0.5) as usize) {
-            time_marks.push(x_offset + bounds.width * (i as f32 / (total_length / 0.5)) * x_margin);
-        }
-        let time_path = canvas::Path::new(|p| {
-            for i in time_marks.iter() {
-                p.move_to(Point::new(*i, top));
-                p.line_to(Point::new(*i, bottom));
-            }
-        });
-        let bg_path = canvas::Path::new(|p| {
-            p.move_to(Point::new(
-                points[4].x,
-                y_offset + bounds.height * (y_margin - sustain),
-            ));
-            p.line_to(Point::new(points[4].x, bottom));
-            p.move_to(Point::new(
-                points[5].x,
-                y_offset + bounds.height * (y_margin - sustain),
-            ));
-            p.line_to(Point::new(points[5].x, bottom));
-        });
-        let line_path = canvas::Path::new(|p| {
-            // for i in points.windows(2) {
-            //     p.move_to(i[0]);
-            //     p.line_to(i[1]);
-            // }
-            p.move_to(points[0]);
-            for i in points.iter() {
-                p.line_to(i.clone());
-            }
-        });
-        let fill_path = canvas::Path::new(|p| {
-            // for i in points.windows(2) {
-            //     p.move_to(i[0]);
-            //     p.line_to(i[1]);
-            // }
-            p.move_to(points[0]);
-            for i in points.iter() {
-                p.line_to(i.clone());
-            }
-            p.line_to(Point::new(
-                x_offset
-                    + bounds.width
-                        * (delay_length
-                            + attack_length
-                            + hold_length
-                            + decay_length
-                            + sustain_length
-                            + release_length)
-                        * x_margin,
-                bottom,
-            ));
-            p.line_to(Point::new(x_offset, bottom));
-            p.close();
-        });
-
-        frame.stroke(
-            &time_path,
-            canvas::Stroke::default().with_color(Color::from_rgba8(0, 0, 0, 0.3)),
-        );
-        frame.fill(
-            &fill_path,
-            Fill {
-                color: Color::from_rgb8(230, 230, 230),
-                rule: canvas::FillRule::NonZero,
-            },
-        );
-        frame.stroke(
-            &bg_path,
-            canvas::Stroke::default().with_color(Color::from_rgb8(150, 150, 150)),
-        );
-        frame.stroke(&line_path, canvas::Stroke::default());
-
-        for i in 0..7 {
-            let radius = 2.0;
-            let point = Point::new(points[i].x - radius, points[i].y - radius);
-            frame.fill_rectangle(point, Size::new(radius * 2.0, radius * 2.0), Color::BLACK);
-        }
-
-        // Finally, we produce the geometry
-        vec![frame.into_geometry()]
-    }
+    create_iced_editor::<SynthPluginEditor>(editor_state, (params, meters, cc_values))
 }
 
 #[derive(Debug, Clone)]
@@ -241,11 +90,18 @@ struct OscilloscopeWidget {
     oscillator: OscillatorBatch,
     osc_params: OscParamsBatch,
     osc_index: usize,
+    wavetables: Wavetables,
     border: bool,
     margin: f32,
 }
 impl OscilloscopeWidget {
-    fn new(freq: f32, mut osc_params: OscParamsBatch, sample_rate: f32, osc_index: usize) -> Self {
+    fn new(
+        freq: f32,
+        mut osc_params: OscParamsBatch,
+        sample_rate: f32,
+        osc_index: usize,
+        wavetables: Wavetables,
+    ) -> Self {
         osc_params.sample_rate = f32x8::splat(sample_rate);
         let mut oscillator = OscillatorBatch::new(0, &osc_params, 1.0);
         oscillator.frequency = f32x8::splat(freq);
@@ -255,6 +111,7 @@ impl OscilloscopeWidget {
             oscillator,
             osc_params,
             osc_index,
+            wavetables,
             border: false,
             margin: 0.1,
         }
@@ -271,7 +128,7 @@ impl canvas::Program<Message> for OscilloscopeWidget {
                 let x = i as f32 / 100.0;
                 let x = x * bounds.width;
                 let y = (oscillator
-                    .step(&self.osc_params, f32x8::splat(0.0))
+                    .step(&self.osc_params, &MatrixInput::default(), &self.wavetables)
                     .as_array_ref()[self.osc_index]
                     - 1.0)
                     .abs()
@@ -307,7 +164,12 @@ impl canvas::Program<Message> for OscilloscopeWidget {
 
 struct SynthPluginEditor {
     params: Arc<SynthPluginParams>,
+    meters: Arc<Meters>,
+    cc_values: Arc<CcValues>,
     context: Arc<dyn GuiContext>,
+    /// Every parameter's ID, pointer and name, for finding the parameters that MIDI controllers
+    /// are mapped to.
+    param_names: Vec<(String, ParamPtr, String)>,
 
     scrollable: widget::scrollable::State,
 
@@ -325,25 +187,153 @@ struct SynthPluginEditor {
     osc_params_8: OscillatorWidget,
 
     matrix: MatrixWidget,
+    wavetable_loader: WavetableWidget,
+    sidechain: SidechainWidget,
+    macros: MacroWidget,
+    output_meter: MeterWidget,
+    scope: ScopeWidget,
+    preview: PreviewWidget,
+    randomiser: RandomiserWidget,
+    midi_learn: MidiLearnWidget,
+
+    /// Normalized parameter values of the copied oscillator, see [`OscillatorParams::param_ptrs`].
+    operator_clipboard: Option<Vec<f32>>,
+    /// Oscillator waiting for a second one to be swapped with.
+    swapping: Option<usize>,
+    history: History,
+    history_buttons: [widget::button::State; 2],
+    /// Parameters that MIDI controllers are moving, with their gestures held open until the
+    /// controller stops, so hosts writing touch automation see one continuous gesture.
+    cc_gestures: Vec<(ParamPtr, Instant)>,
+
+    scale: Scale,
+    scale_buttons: [widget::button::State; 2],
+    resize_corner: ResizeCorner,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
 enum Message {
     /// Update a parameter's value.
     ParamUpdate(nih_widgets::ParamMessage),
+    /// Update several parameters at once, e.g. dragging an envelope breakpoint that moves both a
+    /// time and a level.
+    ParamUpdates(Vec<nih_widgets::ParamMessage>),
+    /// Sent every frame while the editor is open, to redraw the meters and apply the MIDI
+    /// controllers received since the last frame.
+    Frame,
+    /// A slider was right-clicked, which starts or cancels MIDI learn.
+    MidiLearn,
+    CancelMidiLearn,
+    /// Edit the range of a MIDI controller mapping, in percent.
+    CcMin(usize, String),
+    CcMax(usize, String),
+    SetCcRange(usize),
+    /// Switch a MIDI controller mapping between absolute and relative.
+    ToggleCcMode(usize),
+    RemoveCcMapping(usize),
+    /// Take back the last parameter edit made in the editor.
+    Undo,
+    Redo,
+    /// Edit the path of the wavetable file to load.
+    WavetablePath(String),
+    /// Choose which oscillator a wavetable gets loaded into.
+    WavetableTarget(usize),
+    LoadWavetable,
+    ClearWavetable,
+    /// Save the selected oscillator's wavetable samples with the plugin state, rather than just
+    /// the file's path.
+    EmbedWavetable(bool),
+    /// Switch the modulation matrix between showing amounts and modes.
+    ToggleMatrixModes,
+    /// Switch the modulation matrix between the grid and the algorithm graph.
+    ToggleMatrixGraph,
+    PreviousAlgorithm,
+    NextAlgorithm,
+    /// Overwrite the matrix and output levels with the selected algorithm.
+    ApplyAlgorithm,
+    /// Turn a matrix cell on or off, `source == target` for feedback.
+    ToggleModulation {
+        source: usize,
+        target: usize,
+    },
+    /// Turn an oscillator's output on or off.
+    ToggleOutput(usize),
+    /// Choose which macro the mapping editor works on.
+    SelectMacro(usize),
+    /// Wait for a parameter to be moved, which becomes the target of the next mapping. Sent again
+    /// it stops waiting.
+    LearnMacroTarget,
+    MacroMin(String),
+    MacroMax(String),
+    MacroCurve(String),
+    AddMacroMapping,
+    /// Remove a mapping, indexed into the whole mapping list.
+    RemoveMacroMapping(usize),
+    /// Switch the scope between the waveform and the spectrum.
+    ToggleScopeSpectrum,
+    ToggleScopeTrigger,
+    ToggleScopeFreeze,
+    /// Choose what the scope shows, 0 for the output or `n` for oscillator `n`.
+    ScopeSource(usize),
+    /// Choose the MIDI note the voice preview plays.
+    PreviewNote(u8),
+    /// Choose how far into the note the voice preview starts, an index into [`PREVIEW_OFFSETS`].
+    PreviewOffset(usize),
+    /// Change the UI scale the editor opens with next time.
+    SetScale(f32),
+    /// Choose the randomiser's amount, an index into [`RANDOMISER_AMOUNTS`].
+    RandomiserAmount(usize),
+    /// Lock or unlock a group of parameters, indexed into [`randomiser::Group::ALL`].
+    LockGroup(usize, bool),
+    /// Replace every unlocked parameter with a random value.
+    RandomisePatch,
+    /// Move every unlocked parameter towards a random value by the randomiser's amount.
+    MutatePatch,
+    /// Remember the current patch as the first or second parent for breeding.
+    StoreParent(usize),
+    /// Cross the two stored patches.
+    BreedPatch,
+    /// Copy, paste, swap, reset or randomise a whole oscillator.
+    Operator {
+        index: usize,
+        action: OperatorAction,
+    },
+}
+
+#[derive(Debug, Clone, Copy)]
+enum OperatorAction {
+    Copy,
+    /// Overwrite the oscillator with the copied one.
+    Paste,
+    /// Pick the oscillator to swap, or swap it with the one picked before.
+    Swap,
+    Reset,
+    Randomise,
 }
 
 impl IcedEditor for SynthPluginEditor {
     type Executor = executor::Default;
     type Message = Message;
-    type InitializationFlags = Arc<SynthPluginParams>;
+    type InitializationFlags = (Arc<SynthPluginParams>, Arc<Meters>, Arc<CcValues>);
 
     fn new(
-        params: Self::InitializationFlags,
+        (params, meters, cc_values): Self::InitializationFlags,
         context: Arc<dyn GuiContext>,
     ) -> (Self, Command<Self::Message>) {
         let editor = SynthPluginEditor {
+            scope: ScopeWidget::new(meters.scope_source.load(Ordering::Relaxed)),
+            scale: Scale::from_state(&params.editor_state),
+            scale_buttons: Default::default(),
+            resize_corner: Default::default(),
+            param_names: params
+                .param_map()
+                .into_iter()
+                // SAFETY: The parameters are owned by `params`, which outlives the editor
+                .map(|(id, param, _)| (id, param, unsafe { param.name() }.to_string()))
+                .collect(),
             params,
+            meters,
+            cc_values,
             context,
 
             scrollable: Default::default(),
@@ -362,6 +352,19 @@ impl IcedEditor for SynthPluginEditor {
             osc_params_8: OscillatorWidget::new(7),
 
             matrix: Default::default(),
+            wavetable_loader: Default::default(),
+            sidechain: Default::default(),
+            macros: MacroWidget::new(),
+            output_meter: Default::default(),
+            preview: PreviewWidget::new(),
+            randomiser: RandomiserWidget::new(),
+            midi_learn: Default::default(),
+
+            operator_clipboard: None,
+            swapping: None,
+            history: Default::default(),
+            history_buttons: Default::default(),
+            cc_gestures: Vec::new(),
         };
 
         (editor, Command::none())
@@ -371,30 +374,218 @@ impl IcedEditor for SynthPluginEditor {
         self.context.as_ref()
     }
 
+    fn subscription(
+        &self,
+        window_subs: &mut WindowSubs<Self::Message>,
+    ) -> Subscription<Self::Message> {
+        // The meters, voice count and oscillator LEDs show what the audio thread is doing, so the
+        // editor redraws every frame
+        window_subs.on_frame = Some(Message::Frame);
+
+        // Undo with Ctrl+Z (Cmd+Z on macOS), redo with Ctrl+Shift+Z or Ctrl+Y. Keys typed into a
+        // text field are captured by it and don't get here.
+        subscription::events_with(|event, status| match (event, status) {
+            (
+                Event::Keyboard(keyboard::Event::KeyPressed {
+                    key_code,
+                    modifiers,
+                }),
+                event::Status::Ignored,
+            ) if modifiers.command() => match key_code {
+                keyboard::KeyCode::Z if modifiers.shift() => Some(Message::Redo),
+                keyboard::KeyCode::Z => Some(Message::Undo),
+                keyboard::KeyCode::Y => Some(Message::Redo),
+                _ => None,
+            },
+            (
+                Event::Mouse(mouse::Event::ButtonPressed(mouse::Button::Right)),
+                event::Status::Captured,
+            ) => Some(Message::MidiLearn),
+            _ => None,
+        })
+    }
+
     fn update(
         &mut self,
         _window: &mut WindowQueue,
         message: Self::Message,
     ) -> Command<Self::Message> {
         match message {
-            Message::ParamUpdate(message) => self.handle_param_message(message),
+            Message::ParamUpdate(message) => self.param_message(message),
+            Message::ParamUpdates(messages) => {
+                for message in messages {
+                    self.param_message(message);
+                }
+            }
+            Message::Frame => {
+                self.apply_cc_changes();
+                self.scope.refresh(&self.meters);
+            }
+            // The slider already changed what's being learned, only the parameter clicked last
+            // keeps waiting
+            Message::MidiLearn => {
+                if let Some(param) = self.learning() {
+                    param_slider::start_learning(param, |param| self.owns(param));
+                }
+            }
+            Message::CancelMidiLearn => param_slider::stop_learning(|param| self.owns(param)),
+            Message::CcMin(index, text) => {
+                if let Some(row) = self.midi_learn.rows.get_mut(index) {
+                    row.min = text;
+                }
+            }
+            Message::CcMax(index, text) => {
+                if let Some(row) = self.midi_learn.rows.get_mut(index) {
+                    row.max = text;
+                }
+            }
+            Message::SetCcRange(index) => self.midi_learn.set_range(&self.params, index),
+            Message::ToggleCcMode(index) => {
+                if let Ok(mut mappings) = self.params.cc_mappings.write() {
+                    if let Some(mapping) = mappings.get_mut(index) {
+                        mapping.range.mode = match mapping.range.mode {
+                            CcMode::Absolute => CcMode::Relative,
+                            CcMode::Relative => CcMode::Absolute,
+                        };
+                    }
+                }
+            }
+            Message::RemoveCcMapping(index) => {
+                if let Ok(mut mappings) = self.params.cc_mappings.write() {
+                    if index < mappings.len() {
+                        mappings.remove(index);
+                    }
+                }
+            }
+            Message::Undo => self.undo(),
+            Message::Redo => self.redo(),
+            Message::WavetablePath(path) => self.wavetable_loader.path = path,
+            Message::WavetableTarget(target) => self.wavetable_loader.target = target,
+            Message::LoadWavetable => self.wavetable_loader.load(&self.params),
+            Message::ClearWavetable => self.wavetable_loader.clear(&self.params),
+            Message::EmbedWavetable(embed) => {
+                if let Ok(mut wavetables) = self.params.wavetables.write() {
+                    if let Some(source) = &mut wavetables.sources[self.wavetable_loader.target] {
+                        source.embed = embed;
+                    }
+                }
+            }
+            Message::ToggleMatrixModes => self.matrix.show_modes = !self.matrix.show_modes,
+            Message::ToggleMatrixGraph => self.matrix.show_graph = !self.matrix.show_graph,
+            Message::PreviousAlgorithm => {
+                self.matrix.algorithm =
+                    (self.matrix.algorithm + ALGORITHMS.len() - 1) % ALGORITHMS.len()
+            }
+            Message::NextAlgorithm => {
+                self.matrix.algorithm = (self.matrix.algorithm + 1) % ALGORITHMS.len()
+            }
+            Message::ApplyAlgorithm => self.apply_algorithm(&ALGORITHMS[self.matrix.algorithm]),
+            Message::ToggleModulation { source, target } => {
+                let params = self.params.clone();
+                let param = params.fm_mods()[target].amount_params()[source];
+                let amount = if param.value() > 0.0 {
+                    0.0
+                } else if source == target {
+                    FEEDBACK_AMOUNT
+                } else {
+                    MODULATION_AMOUNT
+                };
+                self.set_parameter(param, amount);
+            }
+            Message::ToggleOutput(index) => {
+                let params = self.params.clone();
+                let amp = &params.oscillators()[index].amp;
+                self.set_parameter(amp, if amp.value() > 0.0 { 0.0 } else { 100.0 });
+            }
+            Message::SelectMacro(index) => self.macros.selected = index,
+            Message::LearnMacroTarget => self.macros.learning = !self.macros.learning,
+            Message::MacroMin(text) => self.macros.min = text,
+            Message::MacroMax(text) => self.macros.max = text,
+            Message::MacroCurve(text) => self.macros.curve = text,
+            Message::AddMacroMapping => self.macros.add(&self.params, &self.param_names),
+            Message::ToggleScopeSpectrum => {
+                self.scope.spectrum = !self.scope.spectrum;
+                self.scope.refresh(&self.meters);
+            }
+            Message::ToggleScopeTrigger => self.scope.trigger = !self.scope.trigger,
+            Message::ToggleScopeFreeze => self.scope.frozen = !self.scope.frozen,
+            Message::SetScale(scale) => {
+                // NIH-plug's iced adapter only reads the size when it opens the window and can't
+                // resize it afterwards, so the layout keeps its scale until the editor is opened
+                // again. The window size is what gets saved, so this persists the scale.
+                let scale = scale.clamp(MIN_SCALE, MAX_SCALE);
+                if let Ok(state) = Arc::try_unwrap(editor_state_with_scale(scale)) {
+                    self.params.editor_state.set(state);
+                }
+            }
+            Message::PreviewNote(note) => self.preview.note = note,
+            Message::PreviewOffset(offset) => self.preview.offset = offset,
+            Message::ScopeSource(source) => {
+                self.scope.source = source;
+                self.meters.scope_source.store(source, Ordering::Relaxed);
+            }
+            Message::RandomiserAmount(amount) => self.randomiser.amount = amount,
+            Message::LockGroup(group, locked) => self.randomiser.locks[group] = locked,
+            Message::RandomisePatch => self.mutate_patch(1.0),
+            Message::MutatePatch => self.mutate_patch(RANDOMISER_AMOUNTS[self.randomiser.amount]),
+            Message::StoreParent(parent) => {
+                self.randomiser.parents[parent] = Some(self.patch_values())
+            }
+            Message::BreedPatch => self.breed_patch(),
+            Message::Operator { index, action } => match action {
+                OperatorAction::Copy => self.operator_clipboard = Some(self.operator_values(index)),
+                OperatorAction::Paste => {
+                    if let Some(values) = self.operator_clipboard.clone() {
+                        self.paste_operator(index, &values);
+                    }
+                }
+                OperatorAction::Swap => match self.swapping.take() {
+                    Some(other) if other != index => self.swap_operators(other, index),
+                    // Pressing it again on the same oscillator cancels
+                    Some(_) => {}
+                    None => self.swapping = Some(index),
+                },
+                OperatorAction::Reset => self.reset_operator(index),
+                OperatorAction::Randomise => self.randomise_operator(index),
+            },
+            Message::RemoveMacroMapping(index) => {
+                if let Ok(mut mappings) = self.params.macro_mappings.write() {
+                    if index < mappings.len() {
+                        mappings.remove(index);
+                    }
+                }
+            }
         }
 
         Command::none()
     }
 
     fn view(&mut self) -> Element<'_, Self::Message> {
+        let scale = self.scale;
+        let wavetables = self
+            .params
+            .wavetables
+            .read()
+            .map(|wavetables| wavetables.tables.clone())
+            .unwrap_or_default();
         Scrollable::new(&mut self.scrollable)
             .width(Length::Fill)
             .align_items(Alignment::Center)
             .push(
                 Row::new()
-                    .padding(Padding::from(10))
-                    .spacing(26)
-                    .push(self.matrix.fm_matrix(&self.params))
-                    .push(self.filter_params.ui(&self.params))
-                    .push(self.global_envelope.ui(&self.params))
-                    .push(self.global_params.ui(&self.params)),
+                    .padding(Padding::from(scale.px(10)))
+                    .spacing(scale.px(26))
+                    .push(self.matrix.fm_matrix(&self.params, scale))
+                    .push(self.filter_params.ui(&self.params, scale))
+                    .push(self.global_envelope.ui(&self.params, scale))
+                    .push(
+                        Column::new()
+                            .spacing(scale.px(10))
+                            .push(self.output_meter.ui(&self.meters, scale))
+                            .push(self.global_params.ui(&self.params, scale))
+                            .push(self.wavetable_loader.ui(&self.params, scale))
+                            .push(self.sidechain.ui(&self.params, scale)),
+                    ),
             )
             .push(
                 Row::new().push(
@@ -402,32 +593,112 @@ impl IcedEditor for SynthPluginEditor {
                         .push(
                             // Layout oscillators horizontally
                             Row::new()
-                                .padding(Padding::from(5))
-                                .spacing(20)
-                                .push(self.osc_params_1.content(&self.params.osc1_params))
-                                .push(self.osc_params_2.content(&self.params.osc2_params))
-                                .push(self.osc_params_3.content(&self.params.osc3_params))
-                                .push(self.osc_params_4.content(&self.params.osc4_params)),
+                                .padding(Padding::from(scale.px(5)))
+                                .spacing(scale.px(20))
+                                .push(self.osc_params_1.content(
+                                    &self.params.osc1_params,
+                                    &wavetables,
+                                    self.meters.envelopes[0].load(),
+                                    self.operator_clipboard.is_some(),
+                                    self.swapping,
+                                    scale,
+                                ))
+                                .push(self.osc_params_2.content(
+                                    &self.params.osc2_params,
+                                    &wavetables,
+                                    self.meters.envelopes[1].load(),
+                                    self.operator_clipboard.is_some(),
+                                    self.swapping,
+                                    scale,
+                                ))
+                                .push(self.osc_params_3.content(
+                                    &self.params.osc3_params,
+                                    &wavetables,
+                                    self.meters.envelopes[2].load(),
+                                    self.operator_clipboard.is_some(),
+                                    self.swapping,
+                                    scale,
+                                ))
+                                .push(self.osc_params_4.content(
+                                    &self.params.osc4_params,
+                                    &wavetables,
+                                    self.meters.envelopes[3].load(),
+                                    self.operator_clipboard.is_some(),
+                                    self.swapping,
+                                    scale,
+                                )),
                         )
                         .push(
                             Row::new()
-                                .padding(Padding::from(5))
-                                .spacing(20)
-                                .push(self.osc_params_5.content(&self.params.osc5_params))
-                                .push(self.osc_params_6.content(&self.params.osc6_params))
-                                .push(self.osc_params_7.content(&self.params.osc7_params))
-                                .push(self.osc_params_8.content(&self.params.osc8_params)),
+                                .padding(Padding::from(scale.px(5)))
+                                .spacing(scale.px(20))
+                                .push(self.osc_params_5.content(
+                                    &self.params.osc5_params,
+                                    &wavetables,
+                                    self.meters.envelopes[4].load(),
+                                    self.operator_clipboard.is_some(),
+                                    self.swapping,
+                                    scale,
+                                ))
+                                .push(self.osc_params_6.content(
+                                    &self.params.osc6_params,
+                                    &wavetables,
+                                    self.meters.envelopes[5].load(),
+                                    self.operator_clipboard.is_some(),
+                                    self.swapping,
+                                    scale,
+                                ))
+                                .push(self.osc_params_7.content(
+                                    &self.params.osc7_params,
+                                    &wavetables,
+                                    self.meters.envelopes[6].load(),
+                                    self.operator_clipboard.is_some(),
+                                    self.swapping,
+                                    scale,
+                                ))
+                                .push(self.osc_params_8.content(
+                                    &self.params.osc8_params,
+                                    &wavetables,
+                                    self.meters.envelopes[7].load(),
+                                    self.operator_clipboard.is_some(),
+                                    self.swapping,
+                                    scale,
+                                )),
                         ),
                 ),
             )
-            // .push(
-            //     nih_widgets::PeakMeter::new(
-            //         &mut self.peak_meter_state,
-            //         util::gain_to_db(self.peak_meter.load(std::sync::atomic::Ordering::Relaxed)),
-            //     )
-            //     .hold_time(Duration::from_millis(600)),
-            // )
-            // .push(Row::new().padding(5)
+            .push(
+                Row::new()
+                    .padding(Padding::from(scale.px(10)))
+                    .spacing(scale.px(26))
+                    .push(self.macros.ui(&self.params, &self.param_names, scale))
+                    .push(self.scope.ui(&self.meters, scale))
+                    .push(self.preview.ui(&self.params, &self.param_names, scale)),
+            )
+            .push(
+                Row::new()
+                    .padding(Padding::from(scale.px(10)))
+                    .push(self.randomiser.ui(scale)),
+            )
+            .push(
+                Row::new()
+                    .padding(Padding::from(scale.px(10)))
+                    .push(self.midi_learn.ui(&self.params, &self.param_names, scale)),
+            )
+            .push(
+                Row::new()
+                    .push(history_controls(
+                        &mut self.history_buttons,
+                        &self.history,
+                        scale,
+                    ))
+                    .push(scale_controls(
+                        &mut self.scale_buttons,
+                        &mut self.resize_corner,
+                        scale,
+                        Scale::from_state(&self.params.editor_state),
+                    )),
+            )
             .into()
     }
 
@@ -440,16 +711,387 @@ impl IcedEditor for SynthPluginEditor {
         }
     }
 }
+impl Drop for SynthPluginEditor {
+    fn drop(&mut self) {
+        // Closing the editor stops the controllers from being applied, so their gestures end
+        for (param, _) in std::mem::take(&mut self.cc_gestures) {
+            self.handle_param_message(nih_widgets::ParamMessage::EndSetParameter(param));
+        }
+        // Another instance's parameters could later be allocated where these were
+        param_slider::stop_learning(|param| self.owns(param));
+    }
+}
+impl SynthPluginEditor {
+    /// Whether `param` belongs to this editor's plugin instance rather than another one's.
+    fn owns(&self, param: ParamPtr) -> bool {
+        self.param_names.iter().any(|(_, other, _)| *other == param)
+    }
+    /// The parameter this editor's MIDI learn is waiting on, if any.
+    fn learning(&self) -> Option<ParamPtr> {
+        param_slider::learning(|param| self.owns(param))
+    }
+    /// Passes a widget's parameter change on to the host, recording it in the undo history.
+    fn param_message(&mut self, message: nih_widgets::ParamMessage) {
+        match message {
+            nih_widgets::ParamMessage::BeginSetParameter(param) => {
+                // The macros can't move each other
+                let is_macro = self
+                    .params
+                    .macros()
+                    .iter()
+                    .any(|knob| knob.as_ptr() == param);
+                if self.macros.learning && !is_macro {
+                    self.macros.target = Some(param);
+                    self.macros.learning = false;
+                }
+                self.history.begin();
+            }
+            nih_widgets::ParamMessage::SetParameterNormalized(param, value) => {
+                // SAFETY: The parameters are owned by `self.params`, which outlives this call
+                let before = unsafe { param.unmodulated_normalized_value() };
+                self.history.set(param, before, value);
+            }
+            nih_widgets::ParamMessage::EndSetParameter(_) => self.history.end(),
+        }
+        self.handle_param_message(message);
+    }
+    /// Sets a parameter from the editor itself rather than from a widget, as a single gesture.
+    fn set_parameter<P: Param>(&mut self, param: &P, value: P::Plain) {
+        self.set_normalized_values(&[(param.as_ptr(), param.preview_normalized(value))]);
+    }
+    /// Sets several parameters as one edit, which is also a single step in the undo history.
+    fn set_normalized_values(&mut self, values: &[(ParamPtr, f32)]) {
+        self.history.record(values.iter().map(|&(param, value)| {
+            // SAFETY: The parameters are owned by `self.params`, which outlives this call
+            (
+                param,
+                unsafe { param.unmodulated_normalized_value() },
+                value,
+            )
+        }));
+        self.send_normalized_values(values);
+    }
+    /// Sends several parameter changes to the host without recording them. All of the gestures
+    /// are open while the values change, so hosts that group overlapping gestures undo them in
+    /// one step.
+    fn send_normalized_values(&self, values: &[(ParamPtr, f32)]) {
+        for &(param, _) in values {
+            self.handle_param_message(nih_widgets::ParamMessage::BeginSetParameter(param));
+        }
+        for &(param, value) in values {
+            self.handle_param_message(nih_widgets::ParamMessage::SetParameterNormalized(
+                param, value,
+            ));
+        }
+        for &(param, _) in values {
+            self.handle_param_message(nih_widgets::ParamMessage::EndSetParameter(param));
+        }
+    }
+    /// Normalized values of an oscillator's parameters, in [`OscillatorParams::param_ptrs`] order.
+    fn operator_values(&self, index: usize) -> Vec<f32> {
+        self.params.oscillators()[index]
+            .param_ptrs()
+            .iter()
+            // SAFETY: The parameters are owned by `self.params`, which outlives this call
+            .map(|param| unsafe { param.unmodulated_normalized_value() })
+            .collect()
+    }
+    /// Maps the first MIDI controller that moved onto the parameter waiting for MIDI learn, or
+    /// moves the parameters mapped to the controllers. Controller changes aren't recorded in the
+    /// undo history, the host can record them as automation instead.
+    fn apply_cc_changes(&mut self) {
+        let changes = self.cc_values.take_changes();
+        let now = Instant::now();
+
+        if let (Some(learning), Some(change)) = (self.learning(), changes.first()) {
+            if let Some((id, _, _)) = self
+                .param_names
+                .iter()
+                .find(|(_, param, _)| *param == learning)
+            {
+                if let Ok(mut mappings) = self.params.cc_mappings.write() {
+                    // Learning the same controller again starts the mapping over
+                    mappings.retain(|mapping| mapping.cc != change.cc || mapping.param != *id);
+                    if mappings.len() < MAX_CC_MAPPINGS {
+                        mappings.push(CcMapping::new(change.cc, id.clone()));
+                    }
+                }
+            }
+            param_slider::stop_learning(|param| self.owns(param));
+        } else if !changes.is_empty() {
+            let mappings = self
+                .params
+                .cc_mappings
+                .read()
+                .map(|mappings| mappings.to_vec())
+                .unwrap_or_default();
+            for change in changes {
+                for mapping in mappings.iter().filter(|mapping| mapping.cc == change.cc) {
+                    let param = match self
+                        .param_names
+                        .iter()
+                        .find(|(id, _, _)| *id == mapping.param)
+                    {
+                        Some((_, param, _)) => *param,
+                        None => continue,
+                    };
+                    // SAFETY: The parameters are owned by `self.params`, which outlives this call
+                    let (current, step_count) =
+                        unsafe { (param.unmodulated_normalized_value(), param.step_count()) };
+                    let value = mapping.range.value(
+                        current,
+                        change.position,
+                        change.increments,
+                        step_count,
+                    );
+
+                    match self
+                        .cc_gestures
+                        .iter_mut()
+                        .find(|(other, _)| *other == param)
+                    {
+                        Some((_, last_moved)) => *last_moved = now,
+                        None => {
+                            self.handle_param_message(
+                                nih_widgets::ParamMessage::BeginSetParameter(param),
+                            );
+                            self.cc_gestures.push((param, now));
+                        }
+                    }
+                    self.handle_param_message(nih_widgets::ParamMessage::SetParameterNormalized(
+                        param, value,
+                    ));
+                }
+            }
+        }
+
+        let (moving, stopped): (Vec<_>, Vec<_>) = self
+            .cc_gestures
+            .drain(..)
+            .partition(|(_, last_moved)| now.duration_since(*last_moved) < CC_GESTURE_TIMEOUT);
+        self.cc_gestures = moving;
+        for (param, _) in stopped {
+            self.handle_param_message(nih_widgets::ParamMessage::EndSetParameter(param));
+        }
+    }
+    fn undo(&mut self) {
+        if let Some(step) = self.history.undo() {
+            self.apply_step(step);
+        }
+    }
+    fn redo(&mut self) {
+        if let Some(step) = self.history.redo() {
+            self.apply_step(step);
+        }
+    }
+    fn apply_step(&self, step: history::Step) {
+        self.send_normalized_values(&step.values);
+        if let Some((a, b)) = step.swap {
+            self.swap_unrecorded(a, b);
+        }
+    }
+    fn paste_operator(&mut self, index: usize, values: &[f32]) {
+        let params = self.params.oscillators()[index].param_ptrs();
+        let changes: Vec<_> = params.into_iter().zip(values.iter().copied()).collect();
+        self.set_normalized_values(&changes);
+    }
+    fn reset_operator(&mut self, index: usize) {
+        let params = self.params.oscillators()[index].param_ptrs();
+        let changes: Vec<_> = params
+            .into_iter()
+            // SAFETY: The parameters are owned by `self.params`, which outlives this call
+            .map(|param| (param, unsafe { param.default_normalized_value() }))
+            .collect();
+        self.set_normalized_values(&changes);
+    }
+    /// The values `change` gives each gene that isn't locked, from its current value.
+    fn gene_changes(
+        &self,
+        genes: &[Gene],
+        change: impl Fn(usize, &Gene, f32) -> f32,
+    ) -> Vec<(ParamPtr, f32)> {
+        genes
+            .iter()
+            .enumerate()
+            .filter(|(_, gene)| !self.randomiser.is_locked(gene.group))
+            .map(|(index, gene)| {
+                // SAFETY: The parameters are owned by `self.params`, which outlives this call
+                let value = unsafe { gene.param.unmodulated_normalized_value() };
+                (gene.param, change(index, gene, value))
+            })
+            .collect()
+    }
+    /// Gives one oscillator a new random sound, leaving locked groups alone.
+    fn randomise_operator(&mut self, index: usize) {
+        let genes = randomiser::oscillator_genes(self.params.oscillators()[index]);
+        let changes = self.gene_changes(&genes, |_, gene, value| gene.mutate(value, 1.0));
+        self.set_normalized_values(&changes);
+    }
+    /// Normalized values of the whole patch, in [`randomiser::genes`] order.
+    fn patch_values(&self) -> Vec<f32> {
+        randomiser::genes(&self.params)
+            .iter()
+            // SAFETY: The parameters are owned by `self.params`, which outlives this call
+            .map(|gene| unsafe { gene.param.unmodulated_normalized_value() })
+            .collect()
+    }
+    fn mutate_patch(&mut self, amount: f32) {
+        let genes = randomiser::genes(&self.params);
+        let changes = self.gene_changes(&genes, |_, gene, value| gene.mutate(value, amount));
+        self.set_normalized_values(&changes);
+    }
+    fn breed_patch(&mut self) {
+        let changes = match &self.randomiser.parents {
+            [Some(a), Some(b)] => {
+                let amount = RANDOMISER_AMOUNTS[self.randomiser.amount];
+                let genes = randomiser::genes(&self.params);
+                self.gene_changes(&genes, |index, gene, _| {
+                    gene.breed(a[index], b[index], amount)
+                })
+            }
+            _ => return,
+        };
+        self.set_normalized_values(&changes);
+    }
+    /// Exchanges two oscillators. The matrix rows and columns, wavetables, macro mappings and MIDI
+    /// controller mappings move with them, so the patch sounds the same afterwards.
+    fn swap_operators(&mut self, a: usize, b: usize) {
+        let changes: Vec<(ParamPtr, f32, f32)> = self
+            .swap_pairs(a, b)
+            .into_iter()
+            // SAFETY: The parameters are owned by `self.params`, which outlives this call
+            .map(|(to, from)| unsafe {
+                (
+                    to,
+                    to.unmodulated_normalized_value(),
+                    from.unmodulated_normalized_value(),
+                )
+            })
+            .collect();
+        self.history.record_swap(changes.iter().copied(), a, b);
+        let values: Vec<_> = changes
+            .iter()
+            .map(|&(param, _, after)| (param, after))
+            .collect();
+        self.send_normalized_values(&values);
+        self.swap_unrecorded(a, b);
+    }
+    /// Each parameter that changes when swapping oscillators `a` and `b`, paired with the one
+    /// whose value it takes.
+    fn swap_pairs(&self, a: usize, b: usize) -> Vec<(ParamPtr, ParamPtr)> {
+        let oscillators = self.params.oscillators();
+        let (params_a, params_b) = (oscillators[a].param_ptrs(), oscillators[b].param_ptrs());
+        let mut pairs: Vec<(ParamPtr, ParamPtr)> = params_a
+            .iter()
+            .copied()
+            .zip(params_b.iter().copied())
+            .chain(params_b.iter().copied().zip(params_a.iter().copied()))
+            .collect();
+
+        let swapped = |osc: usize| {
+            if osc == a {
+                b
+            } else if osc == b {
+                a
+            } else {
+                osc
+            }
+        };
+        let fm_mods = self.params.fm_mods();
+        for (target, fm_mod) in fm_mods.iter().enumerate() {
+            let from = fm_mods[swapped(target)];
+            if swapped(target) != target {
+                pairs.push((fm_mod.by_input.as_ptr(), from.by_input.as_ptr()));
+            }
+            for source in 0..8 {
+                if swapped(target) == target && swapped(source) == source {
+                    continue;
+                }
+                pairs.push((
+                    fm_mod.amount_params()[source].as_ptr(),
+                    from.amount_params()[swapped(source)].as_ptr(),
+                ));
+                pairs.push((
+                    fm_mod.mode_params()[source].as_ptr(),
+                    from.mode_params()[swapped(source)].as_ptr(),
+                ));
+            }
+        }
+        pairs
+    }
+    /// Swaps what isn't a parameter between oscillators `a` and `b`. The host's undo doesn't
+    /// cover these, the editor's history does it again when undoing or redoing the swap.
+    fn swap_unrecorded(&self, a: usize, b: usize) {
+        let pairs = self.swap_pairs(a, b);
+        if let Ok(mut wavetables) = self.params.wavetables.write() {
+            wavetables.swap(a, b);
+        }
+        // Mappings follow their parameter's value to its new place
+        let id = |param: ParamPtr| {
+            self.param_names
+                .iter()
+                .find(|(_, other, _)| *other == param)
+                .map(|(id, _, _)| id.clone())
+        };
+        let moves: Vec<(String, String)> = pairs
+            .iter()
+            .filter_map(|&(to, from)| Some((id(to)?, id(from)?)))
+            .collect();
+        if let Ok(mut mappings) = self.params.macro_mappings.write() {
+            history::move_ids(
+                mappings.iter_mut().map(|mapping| &mut mapping.param),
+                &moves,
+            );
+        }
+        if let Ok(mut mappings) = self.params.cc_mappings.write() {
+            history::move_ids(
+                mappings.iter_mut().map(|mapping| &mut mapping.param),
+                &moves,
+            );
+        }
+    }
+    fn apply_algorithm(&mut self, algorithm: &Algorithm) {
+        let amounts = algorithm.amounts();
+        let mut changes = Vec::new();
+        for (target, fm_mod) in self.params.fm_mods().into_iter().enumerate() {
+            for (source, (amount, mode)) in fm_mod
+                .amount_params()
+                .into_iter()
+                .zip(fm_mod.mode_params())
+                .enumerate()
+            {
+                changes.push((
+                    amount.as_ptr(),
+                    amount.preview_normalized(amounts[target][source]),
+                ));
+                changes.push((mode.as_ptr(), mode.preview_normalized(MatrixMode::Phase)));
+            }
+        }
+        for (index, osc) in self.params.oscillators().into_iter().enumerate() {
+            let amp = if algorithm.is_carrier(index) {
+                100.0
+            } else {
+                0.0
+            };
+            changes.push((osc.amp.as_ptr(), osc.amp.preview_normalized(amp)));
+        }
+        self.set_normalized_values(&changes);
+    }
+}
 
 struct OscillatorWidget {
     pub index: usize,
     pub name: String,
     pub amp: param_slider::State,
+    pub output_bus: param_slider::State,
     pub coarse: param_slider::State,
     pub fine: param_slider::State,
     pub freq_mult: param_slider::State,
     pub freq_div: param_slider::State,
     pub hz_detune: param_slider::State,
+    pub frequency_mode: param_slider::State,
+    pub fixed_range: param_slider::State,
+    pub fixed_fine: param_slider::State,
     pub phase_offset: param_slider::State,
     pub phase_rand: param_slider::State,
     pub attack_level: param_slider::State,
@@ -462,11 +1104,41 @@ struct OscillatorWidget {
     pub release: param_slider::State,
     pub feedback: param_slider::State,
     pub velocity_sensitivity: param_slider::State,
+    pub velocity_attack: param_slider::State,
+    pub velocity_feedback: param_slider::State,
     pub keyscaling: param_slider::State,
+    pub rate_scaling: param_slider::State,
+    pub breakpoint: param_slider::State,
+    pub left_depth: param_slider::State,
+    pub left_curve: param_slider::State,
+    pub right_depth: param_slider::State,
+    pub right_curve: param_slider::State,
+    pub waveform: param_slider::State,
+    pub wavetable_position: param_slider::State,
+    pub noise_type: param_slider::State,
+    pub noise_tone: param_slider::State,
     pub waveshaper: param_slider::State,
     pub waveshaper_amount: param_slider::State,
     pub phaseshaper: param_slider::State,
     pub phaseshaper_amount: param_slider::State,
+    pub pitch_env_amount: param_slider::State,
+    pub pitch_env_attack: param_slider::State,
+    pub pitch_env_decay: param_slider::State,
+    pub pitch_env_sustain: param_slider::State,
+    pub pitch_env_release: param_slider::State,
+    pub pitch_env_attack_curve: param_slider::State,
+    pub pitch_env_decay_curve: param_slider::State,
+    pub pitch_env_release_curve: param_slider::State,
+    pub envelope_mode: param_slider::State,
+    pub attack_curve: param_slider::State,
+    pub decay_curve: param_slider::State,
+    pub release_curve: param_slider::State,
+    pub multi_stage_sustain: param_slider::State,
+    pub multi_stage_loop: param_slider::State,
+    pub multi_stage: MultiStageWidget,
+    pub envelope: envelope::State,
+    /// Copy, paste, swap, reset and randomise.
+    pub action_buttons: [widget::button::State; 5],
 }
 
 impl OscillatorWidget {
@@ -475,11 +1147,15 @@ impl OscillatorWidget {
             index,
             name: format!("Osc {}", index + 1),
             amp: Default::default(),
+            output_bus: Default::default(),
             coarse: Default::default(),
             fine: Default::default(),
             freq_mult: Default::default(),
             freq_div: Default::default(),
             hz_detune: Default::default(),
+            frequency_mode: Default::default(),
+            fixed_range: Default::default(),
+            fixed_fine: Default::default(),
             phase_offset: Default::default(),
             phase_rand: Default::default(),
             attack_level: Default::default(),
@@ -492,57 +1168,154 @@ impl OscillatorWidget {
             release: Default::default(),
             feedback: Default::default(),
             velocity_sensitivity: Default::default(),
+            velocity_attack: Default::default(),
+            velocity_feedback: Default::default(),
             keyscaling: Default::default(),
+            rate_scaling: Default::default(),
+            breakpoint: Default::default(),
+            left_depth: Default::default(),
+            left_curve: Default::default(),
+            right_depth: Default::default(),
+            right_curve: Default::default(),
+            waveform: Default::default(),
+            wavetable_position: Default::default(),
+            noise_type: Default::default(),
+            noise_tone: Default::default(),
             waveshaper: Default::default(),
             waveshaper_amount: Default::default(),
             phaseshaper: Default::default(),
             phaseshaper_amount: Default::default(),
+            pitch_env_amount: Default::default(),
+            pitch_env_attack: Default::default(),
+            pitch_env_decay: Default::default(),
+            pitch_env_sustain: Default::default(),
+            pitch_env_release: Default::default(),
+            pitch_env_attack_curve: Default::default(),
+            pitch_env_decay_curve: Default::default(),
+            pitch_env_release_curve: Default::default(),
+            envelope_mode: Default::default(),
+            attack_curve: Default::default(),
+            decay_curve: Default::default(),
+            release_curve: Default::default(),
+            multi_stage_sustain: Default::default(),
+            multi_stage_loop: Default::default(),
+            multi_stage: Default::default(),
+            envelope: Default::default(),
+            action_buttons: Default::default(),
         }
     }
-    fn content<'a>(&'a mut self, osc_params: &'a OscillatorParams) -> Column<Message> {
-        let param_font_size = 14;
-        let slider_font_size = 14;
-        let slider_width = 60;
-        let slider_height = 14;
-        let osc_env_spacing = 8;
-        Column::new()
+    /// `activity` is the oscillator's current envelope level, shown as a light next to its name.
+    /// `can_paste` is whether an oscillator has been copied and `swapping` is the oscillator
+    /// picked to be swapped, if any.
+    fn content<'a>(
+        &'a mut self,
+        osc_params: &'a OscillatorParams,
+        wavetables: &Wavetables,
+        activity: f32,
+        can_paste: bool,
+        swapping: Option<usize>,
+        scale: Scale,
+    ) -> Column<Message> {
+        let index = self.index;
+        let action = |action| Message::Operator { index, action };
+        let [copy, paste, swap, reset, randomise] = &mut self.action_buttons;
+        let swap_label = match swapping {
+            Some(other) if other == index => "Cancel".to_string(),
+            Some(other) => format!("With {}", other + 1),
+            None => "Swap".to_string(),
+        };
+        let actions = Row::new()
+            .spacing(scale.px(4))
+            .push(operator_button(
+                scale,
+                copy,
+                "Copy".to_string(),
+                Some(action(OperatorAction::Copy)),
+            ))
+            .push(operator_button(
+                scale,
+                paste,
+                "Paste".to_string(),
+                can_paste.then(|| action(OperatorAction::Paste)),
+            ))
+            .push(operator_button(
+                scale,
+                swap,
+                swap_label,
+                Some(action(OperatorAction::Swap)),
+            ))
+            .push(operator_button(
+                scale,
+                reset,
+                "Reset".to_string(),
+                Some(action(OperatorAction::Reset)),
+            ))
+            .push(operator_button(
+                scale,
+                randomise,
+                "Random".to_string(),
+                Some(action(OperatorAction::Randomise)),
+            ));
+        let param_font_size = scale.px(14);
+        let slider_font_size = scale.px(14);
+        let slider_width = scale.px(60);
+        let slider_height = scale.px(14);
+        let osc_env_spacing = scale.px(8);
+        let content = Column::new()
             .push(
                 Row::new()
                     .push(
                         Text::new(&self.name)
-                            .size(18)
+                            .size(scale.px(18))
                             .width(slider_width.into())
                             .font(assets::NOTO_SANS_BOLD),
                     )
                     .push(Space::with_width(osc_env_spacing.into()))
-                    .push({
-                        Canvas::new(EnvelopeWidget::new(
-                            osc_params.delay.value(),
-                            osc_params.attack.value(),
-                            osc_params.attack_level.value(),
-                            osc_params.hold.value(),
-                            osc_params.decay.value(),
-                            osc_params.sustain.value(),
-                            osc_params.release.value(),
-                            osc_params.release_level.value(),
+                    .push(
+                        Canvas::new(EnvelopeEditor::new(
+                            &mut self.envelope,
+                            EnvelopeParams {
+                                delay: Some(&osc_params.delay),
+                                attack: &osc_params.attack,
+                                attack_level: Some(&osc_params.attack_level),
+                                hold: Some(&osc_params.hold),
+                                decay: &osc_params.decay,
+                                sustain: &osc_params.sustain,
+                                release: &osc_params.release,
+                                release_level: Some(&osc_params.release_level),
+                            },
                         ))
-                        .height(18.into())
-                        .width(slider_width.into())
-                    })
+                        .height(scale.len(18))
+                        .width(slider_width.into()),
+                    )
                     .push(Space::with_width(osc_env_spacing.into()))
                     .push({
                         let mut params = [OscParams::default(); 8];
-                        params[self.index] = osc_params.to_osc_params(100.0, 1.0, 0.0, 0);
+                        params[self.index] = osc_params.to_osc_params(
+                            100.0,
+                            1.0,
+                            0.0,
+                            None,
+                            &MacroOffsets::default(),
+                        );
                         Canvas::new(OscilloscopeWidget::new(
                             1.0,
                             params.into(),
                             100.0,
                             self.index,
+                            wavetables.clone(),
                         ))
-                        .height(18.into())
+                        .height(scale.len(18))
                         .width(slider_width.into())
-                    }),
+                    })
+                    .push(Space::with_width(osc_env_spacing.into()))
+                    .push(
+                        Canvas::new(ActivityLight { level: activity })
+                            .height(scale.len(18))
+                            .width(scale.len(10)),
+                    ),
             )
+            .push(actions)
             .push(
                 Row::new()
                     .push(
@@ -596,7 +1369,7 @@ impl OscillatorWidget {
                                     .map(Message::ParamUpdate),
                             ),
                     )
-                    .push(Space::with_width(8.into()))
+                    .push(Space::with_width(scale.len(8)))
                     .push(
                         Column::new()
                             .push(Text::new("Delay").size(param_font_size))
@@ -648,7 +1421,7 @@ impl OscillatorWidget {
                                     .map(Message::ParamUpdate),
                             ),
                     )
-                    .push(Space::with_width(8.into()))
+                    .push(Space::with_width(scale.len(8)))
                     .push(
                         Column::new()
                             .push(Text::new("Amplitude").size(param_font_size))
@@ -706,9 +1479,17 @@ impl OscillatorWidget {
                                 .map(Message::ParamUpdate),
                             ),
                     )
-                    .push(Space::with_width(8.into()))
+                    .push(Space::with_width(scale.len(8)))
                     .push(
                         Column::new()
+                            .push(Text::new("Waveform").size(param_font_size))
+                            .push(
+                                ParamSlider::new(&mut self.waveform, &osc_params.waveform)
+                                    .width(slider_width.into())
+                                    .height(slider_height.into())
+                                    .text_size(slider_font_size)
+                                    .map(Message::ParamUpdate),
+                            )
                             .push(Text::new("Waveshaper").size(param_font_size))
                             .push(
                                 ParamSlider::new(&mut self.waveshaper, &osc_params.waveshaper)
@@ -746,6 +1527,14 @@ impl OscillatorWidget {
                                 .height(slider_height.into())
                                 .text_size(slider_font_size)
                                 .map(Message::ParamUpdate),
+                            )
+                            .push(Text::new("Rate Scale").size(param_font_size))
+                            .push(
+                                ParamSlider::new(&mut self.rate_scaling, &osc_params.rate_scaling)
+                                    .width(slider_width.into())
+                                    .height(slider_height.into())
+                                    .text_size(slider_font_size)
+                                    .map(Message::ParamUpdate),
                             ), // .push(Text::new("Keyscaling").size(param_font_size))
                                // .push(
                                //     ParamSlider::new(&mut self.keyscaling, &osc_params.keyscaling)
@@ -767,116 +1556,446 @@ impl OscillatorWidget {
                                // ),
                     ),
             )
-    }
-}
-
-fn title_bar<'a>() -> Column<'a, Message> {
-    Column::new()
-        .align_items(Alignment::Start)
-        .push(
-            Text::new("Foam FM Synth")
-                .font(assets::NOTO_SANS_LIGHT)
-                .size(20)
-                .horizontal_alignment(alignment::Horizontal::Center)
-                .vertical_alignment(alignment::Vertical::Center),
-        )
-        .push(
-            Text::new("WORK IN PROGRESS GUI.")
-                .font(assets::NOTO_SANS_BOLD)
-                .size(12)
-                .color(Color::from_rgb8(255, 80, 80))
-                .horizontal_alignment(alignment::Horizontal::Center)
-                .vertical_alignment(alignment::Vertical::Center),
-        )
-}
-
-#[derive(Debug, Default)]
-struct MatrixRow(
-    param_slider::State,
-    param_slider::State,
-    param_slider::State,
-    param_slider::State,
-    param_slider::State,
-    param_slider::State,
-    param_slider::State,
-    param_slider::State,
-);
-impl MatrixRow {
-    pub fn to_ui<'a>(
-        &'a mut self,
-        label: String,
-        spacing: u16,
-        slider_width: u16,
-        slider_height: u16,
-        slider_font_size: u16,
-        params: &'a OscMod,
-    ) -> Row<Message> {
-        Row::new()
-            .spacing(spacing)
-            .push(
-                Text::new(label)
-                    .size(14)
-                    .width(slider_width.into())
-                    .height(slider_height.into())
-                    .horizontal_alignment(alignment::Horizontal::Center)
-                    .vertical_alignment(alignment::Vertical::Center),
-            )
-            .push(
-                ParamSlider::new(&mut self.0, &params.by_osc1)
-                    .width(slider_width.into())
-                    .height(slider_height.into())
-                    .text_size(slider_font_size)
-                    .map(Message::ParamUpdate),
-            )
-            .push(
-                ParamSlider::new(&mut self.1, &params.by_osc2)
-                    .width(slider_width.into())
-                    .height(slider_height.into())
-                    .text_size(slider_font_size)
-                    .map(Message::ParamUpdate),
-            )
-            .push(
-                ParamSlider::new(&mut self.2, &params.by_osc3)
-                    .width(slider_width.into())
-                    .height(slider_height.into())
-                    .text_size(slider_font_size)
-                    .map(Message::ParamUpdate),
-            )
-            .push(
-                ParamSlider::new(&mut self.3, &params.by_osc4)
-                    .width(slider_width.into())
-                    .height(slider_height.into())
-                    .text_size(slider_font_size)
-                    .map(Message::ParamUpdate),
-            )
-            .push(
-                ParamSlider::new(&mut self.4, &params.by_osc5)
-                    .width(slider_width.into())
-                    .height(slider_height.into())
-                    .text_size(slider_font_size)
-                    .map(Message::ParamUpdate),
-            )
             .push(
-                ParamSlider::new(&mut self.5, &params.by_osc6)
+                Row::new()
+                    .push(
+                        Column::new()
+                            .push(Text::new("Pitch Env.").size(param_font_size))
+                            .push(
+                                ParamSlider::new(
+                                    &mut self.pitch_env_amount,
+                                    &osc_params.pitch_env_amount,
+                                )
+                                .width(slider_width.into())
+                                .height(slider_height.into())
+                                .text_size(slider_font_size)
+                                .map(Message::ParamUpdate),
+                            )
+                            .push(Text::new("P. Attack").size(param_font_size))
+                            .push(
+                                ParamSlider::new(
+                                    &mut self.pitch_env_attack,
+                                    &osc_params.pitch_env_attack,
+                                )
+                                .width(slider_width.into())
+                                .height(slider_height.into())
+                                .text_size(slider_font_size)
+                                .map(Message::ParamUpdate),
+                            )
+                            .push(Text::new("P. Decay").size(param_font_size))
+                            .push(
+                                ParamSlider::new(
+                                    &mut self.pitch_env_decay,
+                                    &osc_params.pitch_env_decay,
+                                )
+                                .width(slider_width.into())
+                                .height(slider_height.into())
+                                .text_size(slider_font_size)
+                                .map(Message::ParamUpdate),
+                            )
+                            .push(Text::new("P. Sustain").size(param_font_size))
+                            .push(
+                                ParamSlider::new(
+                                    &mut self.pitch_env_sustain,
+                                    &osc_params.pitch_env_sustain,
+                                )
+                                .width(slider_width.into())
+                                .height(slider_height.into())
+                                .text_size(slider_font_size)
+                                .map(Message::ParamUpdate),
+                            )
+                            .push(Text::new("P. Release").size(param_font_size))
+                            .push(
+                                ParamSlider::new(
+                                    &mut self.pitch_env_release,
+                                    &osc_params.pitch_env_release,
+                                )
+                                .width(slider_width.into())
+                                .height(slider_height.into())
+                                .text_size(slider_font_size)
+                                .map(Message::ParamUpdate),
+                            )
+                            .push(Text::new("P. Atk. Curve").size(param_font_size))
+                            .push(
+                                ParamSlider::new(
+                                    &mut self.pitch_env_attack_curve,
+                                    &osc_params.pitch_env_attack_curve,
+                                )
+                                .width(slider_width.into())
+                                .height(slider_height.into())
+                                .text_size(slider_font_size)
+                                .map(Message::ParamUpdate),
+                            )
+                            .push(Text::new("P. Dec. Curve").size(param_font_size))
+                            .push(
+                                ParamSlider::new(
+                                    &mut self.pitch_env_decay_curve,
+                                    &osc_params.pitch_env_decay_curve,
+                                )
+                                .width(slider_width.into())
+                                .height(slider_height.into())
+                                .text_size(slider_font_size)
+                                .map(Message::ParamUpdate),
+                            )
+                            .push(Text::new("P. Rls. Curve").size(param_font_size))
+                            .push(
+                                ParamSlider::new(
+                                    &mut self.pitch_env_release_curve,
+                                    &osc_params.pitch_env_release_curve,
+                                )
+                                .width(slider_width.into())
+                                .height(slider_height.into())
+                                .text_size(slider_font_size)
+                                .map(Message::ParamUpdate),
+                            )
+                            .push(Text::new("Freq. Mode").size(param_font_size))
+                            .push(
+                                ParamSlider::new(
+                                    &mut self.frequency_mode,
+                                    &osc_params.frequency_mode,
+                                )
+                                .width(slider_width.into())
+                                .height(slider_height.into())
+                                .text_size(slider_font_size)
+                                .map(Message::ParamUpdate),
+                            ),
+                    )
+                    .push(Space::with_width(scale.len(8)))
+                    .push(
+                        Column::new()
+                            .push(Text::new("Env. Mode").size(param_font_size))
+                            .push(
+                                ParamSlider::new(
+                                    &mut self.envelope_mode,
+                                    &osc_params.envelope_mode,
+                                )
+                                .width(slider_width.into())
+                                .height(slider_height.into())
+                                .text_size(slider_font_size)
+                                .map(Message::ParamUpdate),
+                            )
+                            .push(Text::new("Atk. Curve").size(param_font_size))
+                            .push(
+                                ParamSlider::new(&mut self.attack_curve, &osc_params.attack_curve)
+                                    .width(slider_width.into())
+                                    .height(slider_height.into())
+                                    .text_size(slider_font_size)
+                                    .map(Message::ParamUpdate),
+                            )
+                            .push(Text::new("Dec. Curve").size(param_font_size))
+                            .push(
+                                ParamSlider::new(&mut self.decay_curve, &osc_params.decay_curve)
+                                    .width(slider_width.into())
+                                    .height(slider_height.into())
+                                    .text_size(slider_font_size)
+                                    .map(Message::ParamUpdate),
+                            )
+                            .push(Text::new("Rls. Curve").size(param_font_size))
+                            .push(
+                                ParamSlider::new(
+                                    &mut self.release_curve,
+                                    &osc_params.release_curve,
+                                )
+                                .width(slider_width.into())
+                                .height(slider_height.into())
+                                .text_size(slider_font_size)
+                                .map(Message::ParamUpdate),
+                            )
+                            .push(Text::new("MSEG Sus.").size(param_font_size))
+                            .push(
+                                ParamSlider::new(
+                                    &mut self.multi_stage_sustain,
+                                    &osc_params.multi_stage.sustain_point,
+                                )
+                                .width(slider_width.into())
+                                .height(slider_height.into())
+                                .text_size(slider_font_size)
+                                .map(Message::ParamUpdate),
+                            )
+                            .push(Text::new("MSEG Loop").size(param_font_size))
+                            .push(
+                                ParamSlider::new(
+                                    &mut self.multi_stage_loop,
+                                    &osc_params.multi_stage.loop_point,
+                                )
+                                .width(slider_width.into())
+                                .height(slider_height.into())
+                                .text_size(slider_font_size)
+                                .map(Message::ParamUpdate),
+                            ),
+                    )
+                    .push(Space::with_width(scale.len(8)))
+                    .push(
+                        Column::new()
+                            .push(Text::new("Breakpoint").size(param_font_size))
+                            .push(
+                                ParamSlider::new(&mut self.breakpoint, &osc_params.breakpoint)
+                                    .width(slider_width.into())
+                                    .height(slider_height.into())
+                                    .text_size(slider_font_size)
+                                    .map(Message::ParamUpdate),
+                            )
+                            .push(Text::new("L. Depth").size(param_font_size))
+                            .push(
+                                ParamSlider::new(&mut self.left_depth, &osc_params.left_depth)
+                                    .width(slider_width.into())
+                                    .height(slider_height.into())
+                                    .text_size(slider_font_size)
+                                    .map(Message::ParamUpdate),
+                            )
+                            .push(Text::new("L. Curve").size(param_font_size))
+                            .push(
+                                ParamSlider::new(&mut self.left_curve, &osc_params.left_curve)
+                                    .width(slider_width.into())
+                                    .height(slider_height.into())
+                                    .text_size(slider_font_size)
+                                    .map(Message::ParamUpdate),
+                            )
+                            .push(Text::new("R. Depth").size(param_font_size))
+                            .push(
+                                ParamSlider::new(&mut self.right_depth, &osc_params.right_depth)
+                                    .width(slider_width.into())
+                                    .height(slider_height.into())
+                                    .text_size(slider_font_size)
+                                    .map(Message::ParamUpdate),
+                            )
+                            .push(Text::new("R. Curve").size(param_font_size))
+                            .push(
+                                ParamSlider::new(&mut self.right_curve, &osc_params.right_curve)
+                                    .width(slider_width.into())
+                                    .height(slider_height.into())
+                                    .text_size(slider_font_size)
+                                    .map(Message::ParamUpdate),
+                            )
+                            .push(Text::new("Fixed Range").size(param_font_size))
+                            .push(
+                                ParamSlider::new(&mut self.fixed_range, &osc_params.fixed_range)
+                                    .width(slider_width.into())
+                                    .height(slider_height.into())
+                                    .text_size(slider_font_size)
+                                    .map(Message::ParamUpdate),
+                            )
+                            .push(Text::new("Output").size(param_font_size))
+                            .push(
+                                ParamSlider::new(&mut self.output_bus, &osc_params.output_bus)
+                                    .width(slider_width.into())
+                                    .height(slider_height.into())
+                                    .text_size(slider_font_size)
+                                    .map(Message::ParamUpdate),
+                            ),
+                    )
+                    .push(Space::with_width(scale.len(8)))
+                    .push(
+                        Column::new()
+                            .push(Text::new("Velo. Attack").size(param_font_size))
+                            .push(
+                                ParamSlider::new(
+                                    &mut self.velocity_attack,
+                                    &osc_params.velocity_attack,
+                                )
+                                .width(slider_width.into())
+                                .height(slider_height.into())
+                                .text_size(slider_font_size)
+                                .map(Message::ParamUpdate),
+                            )
+                            .push(Text::new("Velo. Fdbk.").size(param_font_size))
+                            .push(
+                                ParamSlider::new(
+                                    &mut self.velocity_feedback,
+                                    &osc_params.velocity_feedback,
+                                )
+                                .width(slider_width.into())
+                                .height(slider_height.into())
+                                .text_size(slider_font_size)
+                                .map(Message::ParamUpdate),
+                            )
+                            .push(Text::new("WT Position").size(param_font_size))
+                            .push(
+                                ParamSlider::new(
+                                    &mut self.wavetable_position,
+                                    &osc_params.wavetable_position,
+                                )
+                                .width(slider_width.into())
+                                .height(slider_height.into())
+                                .text_size(slider_font_size)
+                                .map(Message::ParamUpdate),
+                            )
+                            .push(Text::new("Noise Type").size(param_font_size))
+                            .push(
+                                ParamSlider::new(&mut self.noise_type, &osc_params.noise_type)
+                                    .width(slider_width.into())
+                                    .height(slider_height.into())
+                                    .text_size(slider_font_size)
+                                    .map(Message::ParamUpdate),
+                            )
+                            .push(Text::new("Noise Tone").size(param_font_size))
+                            .push(
+                                ParamSlider::new(&mut self.noise_tone, &osc_params.noise_tone)
+                                    .width(slider_width.into())
+                                    .height(slider_height.into())
+                                    .text_size(slider_font_size)
+                                    .map(Message::ParamUpdate),
+                            )
+                            .push(Text::new("Fixed Fine").size(param_font_size))
+                            .push(
+                                ParamSlider::new(&mut self.fixed_fine, &osc_params.fixed_fine)
+                                    .width(slider_width.into())
+                                    .height(slider_height.into())
+                                    .text_size(slider_font_size)
+                                    .map(Message::ParamUpdate),
+                            ),
+                    ),
+            );
+        // The point grid is only shown when it's in use, since it takes up a lot of room
+        if osc_params.envelope_mode.value() == EnvelopeMode::MultiStage {
+            content.push(self.multi_stage.to_ui(&osc_params.multi_stage, scale))
+        } else {
+            content
+        }
+    }
+}
+
+/// A button of an oscillator's action row, disabled if there's no message.
+fn operator_button(
+    scale: Scale,
+    state: &mut widget::button::State,
+    label: String,
+    message: Option<Message>,
+) -> Button<'_, Message> {
+    let button = Button::new(state, Text::new(label).size(scale.px(12))).padding(scale.px(1));
+    match message {
+        Some(message) => button.on_press(message),
+        None => button,
+    }
+}
+
+#[derive(Default)]
+struct MultiStageWidget {
+    times: [param_slider::State; 8],
+    levels: [param_slider::State; 8],
+}
+impl MultiStageWidget {
+    pub fn to_ui<'a>(
+        &'a mut self,
+        params: &'a MultiStageParams,
+        scale: Scale,
+    ) -> Column<'a, Message> {
+        let label_width = scale.px(30);
+        let spacing = scale.px(2);
+        let slider_width = scale.px(26);
+        let slider_height = scale.px(14);
+        let slider_font_size = scale.px(10);
+        let label = |text: &str| {
+            Text::new(text)
+                .size(scale.px(12))
+                .width(label_width.into())
+                .height(slider_height.into())
+                .vertical_alignment(alignment::Vertical::Center)
+        };
+        let mut times = Row::new().spacing(spacing).push(label("Time"));
+        for (state, param) in self.times.iter_mut().zip(params.time_params()) {
+            times = times.push(
+                ParamSlider::new(state, param)
                     .width(slider_width.into())
                     .height(slider_height.into())
                     .text_size(slider_font_size)
                     .map(Message::ParamUpdate),
-            )
-            .push(
-                ParamSlider::new(&mut self.6, &params.by_osc7)
+            );
+        }
+        let mut levels = Row::new().spacing(spacing).push(label("Level"));
+        for (state, param) in self.levels.iter_mut().zip(params.level_params()) {
+            levels = levels.push(
+                ParamSlider::new(state, param)
                     .width(slider_width.into())
                     .height(slider_height.into())
                     .text_size(slider_font_size)
                     .map(Message::ParamUpdate),
-            )
-            .push(
-                ParamSlider::new(&mut self.7, &params.by_osc8)
+            );
+        }
+        Column::new()
+            .spacing(spacing)
+            .push(Text::new("Multi-Stage Env.").size(scale.px(14)))
+            .push(times)
+            .push(levels)
+    }
+}
+
+fn title_bar<'a>(scale: Scale) -> Column<'a, Message> {
+    Column::new()
+        .align_items(Alignment::Start)
+        .push(
+            Text::new("Foam FM Synth")
+                .font(assets::NOTO_SANS_LIGHT)
+                .size(scale.px(20))
+                .horizontal_alignment(alignment::Horizontal::Center)
+                .vertical_alignment(alignment::Vertical::Center),
+        )
+        .push(
+            Text::new("WORK IN PROGRESS GUI.")
+                .font(assets::NOTO_SANS_BOLD)
+                .size(scale.px(12))
+                .color(Color::from_rgb8(255, 80, 80))
+                .horizontal_alignment(alignment::Horizontal::Center)
+                .vertical_alignment(alignment::Vertical::Center),
+        )
+}
+
+#[derive(Debug, Default)]
+struct MatrixRow {
+    amounts: [param_slider::State; 8],
+    modes: [param_slider::State; 8],
+    input: param_slider::State,
+}
+impl MatrixRow {
+    pub fn to_ui<'a>(
+        &'a mut self,
+        label: String,
+        spacing: u16,
+        slider_width: u16,
+        slider_height: u16,
+        slider_font_size: u16,
+        params: &'a OscMod,
+        show_modes: bool,
+        scale: Scale,
+    ) -> Row<Message> {
+        let mut row = Row::new().spacing(spacing).push(
+            Text::new(label)
+                .size(scale.px(14))
+                .width(slider_width.into())
+                .height(slider_height.into())
+                .horizontal_alignment(alignment::Horizontal::Center)
+                .vertical_alignment(alignment::Vertical::Center),
+        );
+        if show_modes {
+            for (state, param) in self.modes.iter_mut().zip(params.mode_params()) {
+                row = row.push(
+                    ParamSlider::new(state, param)
+                        .width(slider_width.into())
+                        .height(slider_height.into())
+                        .text_size(slider_font_size)
+                        .map(Message::ParamUpdate),
+                );
+            }
+            // The sidechain input only does phase modulation
+            row.push(Space::new(slider_width.into(), slider_height.into()))
+        } else {
+            for (state, param) in self.amounts.iter_mut().zip(params.amount_params()) {
+                row = row.push(
+                    ParamSlider::new(state, param)
+                        .width(slider_width.into())
+                        .height(slider_height.into())
+                        .text_size(slider_font_size)
+                        .map(Message::ParamUpdate),
+                );
+            }
+            row.push(
+                ParamSlider::new(&mut self.input, &params.by_input)
                     .width(slider_width.into())
                     .height(slider_height.into())
                     .text_size(slider_font_size)
                     .map(Message::ParamUpdate),
             )
+        }
     }
 }
 
@@ -899,41 +2018,127 @@ struct MatrixWidget {
     osc6_amp: param_slider::State,
     osc7_amp: param_slider::State,
     osc8_amp: param_slider::State,
+
+    /// Shows each cell's modulation mode instead of its amount.
+    show_modes: bool,
+    modes_button: widget::button::State,
+    /// Shows the algorithm graph instead of the grid.
+    show_graph: bool,
+    graph_button: widget::button::State,
+    graph: algorithm_graph::State,
+
+    /// Index into [`ALGORITHMS`] of the algorithm to apply.
+    algorithm: usize,
+    previous_algorithm_button: widget::button::State,
+    next_algorithm_button: widget::button::State,
+    apply_algorithm_button: widget::button::State,
 }
 impl MatrixWidget {
-    fn fm_matrix<'a>(&'a mut self, params: &'a SynthPluginParams) -> Column<'a, Message> {
-        let slider_width = 30;
-        let slider_height = 14;
-        let slider_font_size = 12;
-        let spacing = 2;
-        Column::new()
+    fn fm_matrix<'a>(
+        &'a mut self,
+        params: &'a SynthPluginParams,
+        scale: Scale,
+    ) -> Column<'a, Message> {
+        let slider_width = scale.px(30);
+        let slider_height = scale.px(14);
+        let slider_font_size = scale.px(12);
+        let spacing = scale.px(2);
+        let column = Column::new()
             .spacing(spacing)
             .push(
                 Row::new()
                     .push(
                         Text::new("FM Matrix")
                             .font(assets::NOTO_SANS_BOLD)
-                            .size(18)
+                            .size(scale.px(18))
                             .horizontal_alignment(alignment::Horizontal::Left)
                             .vertical_alignment(alignment::Vertical::Center),
                     )
                     .push(
                         Text::new("From")
                             .font(assets::NOTO_SANS_REGULAR)
-                            .size(16)
-                            .width((slider_width * 6 + spacing * 6 * 2).into())
+                            .size(scale.px(16))
+                            .width((slider_width * 4 + spacing * 4 * 2).into())
                             .horizontal_alignment(alignment::Horizontal::Center)
                             .vertical_alignment(alignment::Vertical::Center),
+                    )
+                    .push(
+                        Button::new(
+                            &mut self.modes_button,
+                            Text::new(if self.show_modes { "Amounts" } else { "Modes" })
+                                .size(scale.px(14)),
+                        )
+                        .padding(scale.px(2))
+                        .on_press(Message::ToggleMatrixModes),
+                    )
+                    .push(Space::with_width(spacing.into()))
+                    .push(
+                        Button::new(
+                            &mut self.graph_button,
+                            Text::new(if self.show_graph { "Grid" } else { "Graph" })
+                                .size(scale.px(14)),
+                        )
+                        .padding(scale.px(2))
+                        .on_press(Message::ToggleMatrixGraph),
                     ),
             )
+            .push(
+                Row::new()
+                    .spacing(spacing)
+                    .align_items(Alignment::Center)
+                    .push(
+                        Button::new(
+                            &mut self.previous_algorithm_button,
+                            Text::new("<").size(scale.px(14)),
+                        )
+                        .padding(scale.px(2))
+                        .on_press(Message::PreviousAlgorithm),
+                    )
+                    .push(
+                        Text::new(ALGORITHMS[self.algorithm].name)
+                            .size(scale.px(14))
+                            .width((slider_width * 4).into())
+                            .horizontal_alignment(alignment::Horizontal::Center),
+                    )
+                    .push(
+                        Button::new(
+                            &mut self.next_algorithm_button,
+                            Text::new(">").size(scale.px(14)),
+                        )
+                        .padding(scale.px(2))
+                        .on_press(Message::NextAlgorithm),
+                    )
+                    .push(
+                        Button::new(
+                            &mut self.apply_algorithm_button,
+                            Text::new("Apply").size(scale.px(14)),
+                        )
+                        .padding(scale.px(2))
+                        .on_press(Message::ApplyAlgorithm),
+                    ),
+            );
+        if self.show_graph {
+            let fm_mods = params.fm_mods();
+            return column.push(
+                Canvas::new(AlgorithmGraph::new(
+                    &mut self.graph,
+                    fm_mods.map(OscMod::to_array),
+                    fm_mods.map(OscMod::modes),
+                    params.oscillators().map(|osc| osc.amp.value() / 100.0),
+                ))
+                .width((slider_width * 9 + spacing * 8).into())
+                .height(((slider_height + spacing) * 9).into()),
+            );
+        }
+        column
             .push({
                 let mut row = Row::new()
                     .spacing(spacing)
                     .push(Space::new(slider_width.into(), slider_height.into()));
-                for i in 1..=8 {
+                for label in (1..=8).map(|i| i.to_string()).chain(["In".to_string()]) {
                     row = row.push(
-                        Text::new(i.to_string())
-                            .size(14)
+                        Text::new(label)
+                            .size(scale.px(14))
                             .width(slider_width.into())
                             .height(slider_height.into())
                             .horizontal_alignment(alignment::Horizontal::Center)
@@ -949,6 +2154,8 @@ impl MatrixWidget {
                 slider_height,
                 slider_font_size,
                 &params.osc1_fm_mod,
+                self.show_modes,
+                scale,
             ))
             .push(self._2.to_ui(
                 "To 2".to_string(),
@@ -957,6 +2164,8 @@ impl MatrixWidget {
                 slider_height,
                 slider_font_size,
                 &params.osc2_fm_mod,
+                self.show_modes,
+                scale,
             ))
             .push(self._3.to_ui(
                 "To 3".to_string(),
@@ -965,6 +2174,8 @@ impl MatrixWidget {
                 slider_height,
                 slider_font_size,
                 &params.osc3_fm_mod,
+                self.show_modes,
+                scale,
             ))
             .push(self._4.to_ui(
                 "To 4".to_string(),
@@ -973,6 +2184,8 @@ impl MatrixWidget {
                 slider_height,
                 slider_font_size,
                 &params.osc4_fm_mod,
+                self.show_modes,
+                scale,
             ))
             .push(self._5.to_ui(
                 "To 5".to_string(),
@@ -981,6 +2194,8 @@ impl MatrixWidget {
                 slider_height,
                 slider_font_size,
                 &params.osc5_fm_mod,
+                self.show_modes,
+                scale,
             ))
             .push(self._6.to_ui(
                 "To 6".to_string(),
@@ -989,6 +2204,8 @@ impl MatrixWidget {
                 slider_height,
                 slider_font_size,
                 &params.osc6_fm_mod,
+                self.show_modes,
+                scale,
             ))
             .push(self._7.to_ui(
                 "To 7".to_string(),
@@ -997,6 +2214,8 @@ impl MatrixWidget {
                 slider_height,
                 slider_font_size,
                 &params.osc7_fm_mod,
+                self.show_modes,
+                scale,
             ))
             .push(self._8.to_ui(
                 "To 8".to_string(),
@@ -1005,13 +2224,15 @@ impl MatrixWidget {
                 slider_height,
                 slider_font_size,
                 &params.osc8_fm_mod,
+                self.show_modes,
+                scale,
             ))
             .push(
                 Row::new()
                     .spacing(spacing)
                     .push(
                         Text::new("Out".to_string())
-                            .size(14)
+                            .size(scale.px(14))
                             .width(slider_width.into())
                             .height(slider_height.into())
                             .horizontal_alignment(alignment::Horizontal::Center)
@@ -1079,6 +2300,7 @@ impl MatrixWidget {
 
 #[derive(Default)]
 struct FilterWidget {
+    envelope: envelope::State,
     filter_enabled_slider_state: param_slider::State,
     filter_type_slider_state: param_slider::State,
     filter_cutoff_slider_state: param_slider::State,
@@ -1089,18 +2311,22 @@ struct FilterWidget {
     filter_envelope_decay_slider_state: param_slider::State,
     filter_envelope_sustain_slider_state: param_slider::State,
     filter_envelope_release_slider_state: param_slider::State,
+    filter_envelope_attack_curve_slider_state: param_slider::State,
+    filter_envelope_decay_curve_slider_state: param_slider::State,
+    filter_envelope_release_curve_slider_state: param_slider::State,
+    filter_envelope_rate_scaling_slider_state: param_slider::State,
 }
 impl FilterWidget {
-    fn ui<'a>(&'a mut self, params: &'a SynthPluginParams) -> Column<'a, Message> {
-        let slider_height: Length = 14.into();
-        let slider_width: Length = 60.into();
-        let slider_font_size = 14;
-        let font_size = 14;
+    fn ui<'a>(&'a mut self, params: &'a SynthPluginParams, scale: Scale) -> Column<'a, Message> {
+        let slider_height: Length = scale.len(14);
+        let slider_width: Length = scale.len(60);
+        let slider_font_size = scale.px(14);
+        let font_size = scale.px(14);
         Column::new()
-            .max_width(200)
+            .max_width(scale.px(200).into())
             .push(
                 Text::new("Filter")
-                    .size(18)
+                    .size(scale.px(18))
                     .horizontal_alignment(alignment::Horizontal::Center)
                     .font(assets::NOTO_SANS_BOLD),
             )
@@ -1108,7 +2334,7 @@ impl FilterWidget {
                 Row::new()
                     .push(
                         Column::new()
-                            .max_width(90)
+                            .max_width(scale.px(90).into())
                             .push(Text::new("Enabled").size(font_size))
                             .push(
                                 ParamSlider::new(
@@ -1123,41 +2349,331 @@ impl FilterWidget {
                             .push(Text::new("Type").size(font_size))
                             .push(
                                 ParamSlider::new(
-                                    &mut self.filter_type_slider_state,
-                                    &params.filter_type,
+                                    &mut self.filter_type_slider_state,
+                                    &params.filter_type,
+                                )
+                                .height(slider_height)
+                                .width(slider_width)
+                                .text_size(slider_font_size)
+                                .map(Message::ParamUpdate),
+                            )
+                            .push(Text::new("Cutoff").size(font_size))
+                            .push(
+                                ParamSlider::new(
+                                    &mut self.filter_cutoff_slider_state,
+                                    &params.filter_cutoff,
+                                )
+                                .height(slider_height)
+                                .width(slider_width)
+                                .text_size(slider_font_size)
+                                .map(Message::ParamUpdate),
+                            )
+                            .push(Text::new("Resonance").size(font_size))
+                            .push(
+                                ParamSlider::new(
+                                    &mut self.filter_resonance_slider_state,
+                                    &params.filter_resonance,
+                                )
+                                .height(slider_height)
+                                .width(slider_width)
+                                .text_size(slider_font_size)
+                                .map(Message::ParamUpdate),
+                            )
+                            .push(Text::new("Keytrack").size(font_size))
+                            .push(
+                                ParamSlider::new(
+                                    &mut self.filter_keytrack_slider_state,
+                                    &params.filter_keytrack,
+                                )
+                                .height(slider_height)
+                                .width(slider_width)
+                                .text_size(slider_font_size)
+                                .map(Message::ParamUpdate),
+                            ),
+                    )
+                    .push(Space::with_width(scale.len(8)))
+                    .push(
+                        Column::new()
+                            .push(
+                                Canvas::new(EnvelopeEditor::new(
+                                    &mut self.envelope,
+                                    EnvelopeParams {
+                                        delay: None,
+                                        attack: &params.filter_envelope_attack,
+                                        attack_level: None,
+                                        hold: None,
+                                        decay: &params.filter_envelope_decay,
+                                        sustain: &params.filter_envelope_sustain,
+                                        release: &params.filter_envelope_release,
+                                        release_level: None,
+                                    },
+                                ))
+                                .height(scale.len(28))
+                                .width(slider_width),
+                            )
+                            .push(Text::new("Env. Amt.").size(font_size))
+                            .push(
+                                ParamSlider::new(
+                                    &mut self.filter_envelope_amount_slider_state,
+                                    &params.filter_envelope_amount,
+                                )
+                                .height(slider_height)
+                                .width(slider_width)
+                                .text_size(slider_font_size)
+                                .map(Message::ParamUpdate),
+                            )
+                            .push(Text::new("Fil. Attack").size(font_size))
+                            .push(
+                                ParamSlider::new(
+                                    &mut self.filter_envelope_attack_slider_state,
+                                    &params.filter_envelope_attack,
+                                )
+                                .height(slider_height)
+                                .width(slider_width)
+                                .text_size(slider_font_size)
+                                .map(Message::ParamUpdate),
+                            )
+                            .push(Text::new("Fil. Decay").size(font_size))
+                            .push(
+                                ParamSlider::new(
+                                    &mut self.filter_envelope_decay_slider_state,
+                                    &params.filter_envelope_decay,
+                                )
+                                .height(slider_height)
+                                .width(slider_width)
+                                .text_size(slider_font_size)
+                                .map(Message::ParamUpdate),
+                            )
+                            .push(Text::new("Fil. Sustain").size(font_size))
+                            .push(
+                                ParamSlider::new(
+                                    &mut self.filter_envelope_sustain_slider_state,
+                                    &params.filter_envelope_sustain,
+                                )
+                                .height(slider_height)
+                                .width(slider_width)
+                                .text_size(slider_font_size)
+                                .map(Message::ParamUpdate),
+                            )
+                            .push(Text::new("Fil. Release").size(font_size))
+                            .push(
+                                ParamSlider::new(
+                                    &mut self.filter_envelope_release_slider_state,
+                                    &params.filter_envelope_release,
+                                )
+                                .height(slider_height)
+                                .width(slider_width)
+                                .text_size(slider_font_size)
+                                .map(Message::ParamUpdate),
+                            ),
+                    )
+                    .push(Space::with_width(scale.len(8)))
+                    .push(
+                        Column::new()
+                            .push(Text::new("Atk. Curve").size(font_size))
+                            .push(
+                                ParamSlider::new(
+                                    &mut self.filter_envelope_attack_curve_slider_state,
+                                    &params.filter_envelope_attack_curve,
+                                )
+                                .height(slider_height)
+                                .width(slider_width)
+                                .text_size(slider_font_size)
+                                .map(Message::ParamUpdate),
+                            )
+                            .push(Text::new("Dec. Curve").size(font_size))
+                            .push(
+                                ParamSlider::new(
+                                    &mut self.filter_envelope_decay_curve_slider_state,
+                                    &params.filter_envelope_decay_curve,
+                                )
+                                .height(slider_height)
+                                .width(slider_width)
+                                .text_size(slider_font_size)
+                                .map(Message::ParamUpdate),
+                            )
+                            .push(Text::new("Rls. Curve").size(font_size))
+                            .push(
+                                ParamSlider::new(
+                                    &mut self.filter_envelope_release_curve_slider_state,
+                                    &params.filter_envelope_release_curve,
+                                )
+                                .height(slider_height)
+                                .width(slider_width)
+                                .text_size(slider_font_size)
+                                .map(Message::ParamUpdate),
+                            )
+                            .push(Text::new("Rate Scale").size(font_size))
+                            .push(
+                                ParamSlider::new(
+                                    &mut self.filter_envelope_rate_scaling_slider_state,
+                                    &params.filter_envelope_rate_scaling,
+                                )
+                                .height(slider_height)
+                                .width(slider_width)
+                                .text_size(slider_font_size)
+                                .map(Message::ParamUpdate),
+                            ),
+                    ),
+            )
+    }
+}
+
+#[derive(Default)]
+struct GlobalEnvelopeWidget {
+    envelope: envelope::State,
+    attack: param_slider::State,
+    decay: param_slider::State,
+    sustain: param_slider::State,
+    release: param_slider::State,
+    pitch_amount: param_slider::State,
+    pitch_attack: param_slider::State,
+    pitch_decay: param_slider::State,
+    pitch_sustain: param_slider::State,
+    pitch_release: param_slider::State,
+    attack_curve: param_slider::State,
+    decay_curve: param_slider::State,
+    release_curve: param_slider::State,
+    pitch_attack_curve: param_slider::State,
+    pitch_decay_curve: param_slider::State,
+    pitch_release_curve: param_slider::State,
+    rate_scaling: param_slider::State,
+}
+impl GlobalEnvelopeWidget {
+    fn ui<'a>(&'a mut self, params: &'a SynthPluginParams, scale: Scale) -> Column<'a, Message> {
+        let slider_height: Length = scale.len(14);
+        let slider_width: Length = scale.len(60);
+        let slider_font_size = scale.px(14);
+        let font_size = scale.px(14);
+        Column::new()
+            .max_width(scale.px(200).into())
+            // .push(Space::with_height(scale.len(20)))
+            .push(
+                Text::new("Amp Env.")
+                    .size(scale.px(18))
+                    .horizontal_alignment(alignment::Horizontal::Center)
+                    .font(assets::NOTO_SANS_BOLD),
+            )
+            .push(
+                Row::new()
+                    // .spacing(scale.px(10))
+                    .push(
+                        Column::new()
+                            .max_width(scale.px(90).into())
+                            .push(
+                                Canvas::new(EnvelopeEditor::new(
+                                    &mut self.envelope,
+                                    EnvelopeParams {
+                                        delay: None,
+                                        attack: &params.global_attack,
+                                        attack_level: None,
+                                        hold: None,
+                                        decay: &params.global_decay,
+                                        sustain: &params.global_sustain,
+                                        release: &params.global_release,
+                                        release_level: None,
+                                    },
+                                ))
+                                .height(scale.len(28))
+                                .width(slider_width),
+                            )
+                            .push(Text::new("Attack").size(font_size))
+                            .push(
+                                ParamSlider::new(&mut self.attack, &params.global_attack)
+                                    .height(slider_height)
+                                    .width(slider_width)
+                                    .text_size(slider_font_size)
+                                    .map(Message::ParamUpdate),
+                            )
+                            .push(Text::new("Decay").size(font_size))
+                            .push(
+                                ParamSlider::new(&mut self.decay, &params.global_decay)
+                                    .height(slider_height)
+                                    .width(slider_width)
+                                    .text_size(slider_font_size)
+                                    .map(Message::ParamUpdate),
+                            )
+                            .push(Text::new("Sustain").size(font_size))
+                            .push(
+                                ParamSlider::new(&mut self.sustain, &params.global_sustain)
+                                    .height(slider_height)
+                                    .width(slider_width)
+                                    .text_size(slider_font_size)
+                                    .map(Message::ParamUpdate),
+                            )
+                            .push(Text::new("Release").size(font_size))
+                            .push(
+                                ParamSlider::new(&mut self.release, &params.global_release)
+                                    .height(slider_height)
+                                    .width(slider_width)
+                                    .text_size(slider_font_size)
+                                    .map(Message::ParamUpdate),
+                            )
+                            .push(Text::new("Rate Scale").size(font_size))
+                            .push(
+                                ParamSlider::new(
+                                    &mut self.rate_scaling,
+                                    &params.global_rate_scaling,
+                                )
+                                .height(slider_height)
+                                .width(slider_width)
+                                .text_size(slider_font_size)
+                                .map(Message::ParamUpdate),
+                            ),
+                    )
+                    .push(Space::with_width(scale.len(8)))
+                    .push(
+                        Column::new()
+                            .max_width(scale.px(90).into())
+                            .push(Text::new("Pitch Env.").size(font_size))
+                            .push(
+                                ParamSlider::new(
+                                    &mut self.pitch_amount,
+                                    &params.pitch_envelope_amount,
+                                )
+                                .height(slider_height)
+                                .width(slider_width)
+                                .text_size(slider_font_size)
+                                .map(Message::ParamUpdate),
+                            )
+                            .push(Text::new("P. Attack").size(font_size))
+                            .push(
+                                ParamSlider::new(
+                                    &mut self.pitch_attack,
+                                    &params.pitch_envelope_attack,
                                 )
                                 .height(slider_height)
                                 .width(slider_width)
                                 .text_size(slider_font_size)
                                 .map(Message::ParamUpdate),
                             )
-                            .push(Text::new("Cutoff").size(font_size))
+                            .push(Text::new("P. Decay").size(font_size))
                             .push(
                                 ParamSlider::new(
-                                    &mut self.filter_cutoff_slider_state,
-                                    &params.filter_cutoff,
+                                    &mut self.pitch_decay,
+                                    &params.pitch_envelope_decay,
                                 )
                                 .height(slider_height)
                                 .width(slider_width)
                                 .text_size(slider_font_size)
                                 .map(Message::ParamUpdate),
                             )
-                            .push(Text::new("Resonance").size(font_size))
+                            .push(Text::new("P. Sustain").size(font_size))
                             .push(
                                 ParamSlider::new(
-                                    &mut self.filter_resonance_slider_state,
-                                    &params.filter_resonance,
+                                    &mut self.pitch_sustain,
+                                    &params.pitch_envelope_sustain,
                                 )
                                 .height(slider_height)
                                 .width(slider_width)
                                 .text_size(slider_font_size)
                                 .map(Message::ParamUpdate),
                             )
-                            .push(Text::new("Keytrack").size(font_size))
+                            .push(Text::new("P. Release").size(font_size))
                             .push(
                                 ParamSlider::new(
-                                    &mut self.filter_keytrack_slider_state,
-                                    &params.filter_keytrack,
+                                    &mut self.pitch_release,
+                                    &params.pitch_envelope_release,
                                 )
                                 .height(slider_height)
                                 .width(slider_width)
@@ -1165,58 +2681,67 @@ impl FilterWidget {
                                 .map(Message::ParamUpdate),
                             ),
                     )
-                    .push(Space::with_width(8.into()))
+                    .push(Space::with_width(scale.len(8)))
                     .push(
                         Column::new()
-                            .push(Text::new("Env. Amt.").size(font_size))
+                            .max_width(scale.px(90).into())
+                            .push(Text::new("Atk. Curve").size(font_size))
                             .push(
                                 ParamSlider::new(
-                                    &mut self.filter_envelope_amount_slider_state,
-                                    &params.filter_envelope_amount,
+                                    &mut self.attack_curve,
+                                    &params.global_attack_curve,
                                 )
                                 .height(slider_height)
                                 .width(slider_width)
                                 .text_size(slider_font_size)
                                 .map(Message::ParamUpdate),
                             )
-                            .push(Text::new("Fil. Attack").size(font_size))
+                            .push(Text::new("Dec. Curve").size(font_size))
+                            .push(
+                                ParamSlider::new(&mut self.decay_curve, &params.global_decay_curve)
+                                    .height(slider_height)
+                                    .width(slider_width)
+                                    .text_size(slider_font_size)
+                                    .map(Message::ParamUpdate),
+                            )
+                            .push(Text::new("Rls. Curve").size(font_size))
                             .push(
                                 ParamSlider::new(
-                                    &mut self.filter_envelope_attack_slider_state,
-                                    &params.filter_envelope_attack,
+                                    &mut self.release_curve,
+                                    &params.global_release_curve,
                                 )
                                 .height(slider_height)
                                 .width(slider_width)
                                 .text_size(slider_font_size)
                                 .map(Message::ParamUpdate),
                             )
-                            .push(Text::new("Fil. Decay").size(font_size))
+                            .push(Text::new("P. Atk. Crv.").size(font_size))
                             .push(
                                 ParamSlider::new(
-                                    &mut self.filter_envelope_decay_slider_state,
-                                    &params.filter_envelope_decay,
+                                    &mut self.pitch_attack_curve,
+                                    &params.pitch_envelope_attack_curve,
                                 )
                                 .height(slider_height)
                                 .width(slider_width)
                                 .text_size(slider_font_size)
                                 .map(Message::ParamUpdate),
                             )
-                            .push(Text::new("Fil. Sustain").size(font_size))
+                            .push(Text::new("P. Dec. Crv.").size(font_size))
                             .push(
                                 ParamSlider::new(
-                                    &mut self.filter_envelope_sustain_slider_state,
-                                    &params.filter_envelope_sustain,
+                                    &mut self.pitch_decay_curve,
+                                    &params.pitch_envelope_decay_curve,
                                 )
                                 .height(slider_height)
                                 .width(slider_width)
                                 .text_size(slider_font_size)
                                 .map(Message::ParamUpdate),
                             )
-                            .push(Text::new("Fil. Release").size(font_size))
+                            .push(Text::new("P. Rls. Crv.").size(font_size))
                             .push(
                                 ParamSlider::new(
-                                    &mut self.filter_envelope_release_slider_state,
-                                    &params.filter_envelope_release,
+                                    &mut self.pitch_release_curve,
+                                    &params.pitch_envelope_release_curve,
                                 )
                                 .height(slider_height)
                                 .width(slider_width)
@@ -1224,71 +2749,1255 @@ impl FilterWidget {
                                 .map(Message::ParamUpdate),
                             ),
                     ),
-            )
+            )
+    }
+}
+
+/// Loads wavetable files into an oscillator's wavetable slot.
+#[derive(Default)]
+struct WavetableWidget {
+    path: String,
+    target: usize,
+    error: Option<String>,
+    path_input: widget::text_input::State,
+    previous_button: widget::button::State,
+    next_button: widget::button::State,
+    load_button: widget::button::State,
+    clear_button: widget::button::State,
+}
+impl WavetableWidget {
+    fn load(&mut self, params: &SynthPluginParams) {
+        match WavetableSource::load(self.path.trim()) {
+            Ok(source) => {
+                if let Ok(mut wavetables) = params.wavetables.write() {
+                    wavetables.set(self.target, Some(source));
+                }
+                self.error = None;
+            }
+            Err(error) => self.error = Some(error),
+        }
+    }
+    fn clear(&mut self, params: &SynthPluginParams) {
+        if let Ok(mut wavetables) = params.wavetables.write() {
+            wavetables.set(self.target, None);
+        }
+        self.error = None;
+    }
+    fn ui<'a>(&'a mut self, params: &'a SynthPluginParams, scale: Scale) -> Column<'a, Message> {
+        let font_size = scale.px(14);
+        let (status, embedded) = params
+            .wavetables
+            .read()
+            .ok()
+            .and_then(|wavetables| {
+                wavetables.sources[self.target].as_ref().map(|source| {
+                    let status = if source.is_valid() {
+                        format!(
+                            "{} ({} frames)",
+                            source.name,
+                            source.samples.len() / source.frame_size
+                        )
+                    } else {
+                        format!("{} not found", source.path.display())
+                    };
+                    (status, Some(source.embed))
+                })
+            })
+            .unwrap_or_else(|| ("No wavetable loaded".to_string(), None));
+        let status = self.error.clone().unwrap_or(status);
+        let mut buttons = Row::new()
+            .spacing(scale.px(4))
+            .align_items(Alignment::Center)
+            .push(
+                Button::new(&mut self.load_button, Text::new("Load").size(font_size))
+                    .padding(scale.px(2))
+                    .on_press(Message::LoadWavetable),
+            )
+            .push(
+                Button::new(&mut self.clear_button, Text::new("Clear").size(font_size))
+                    .padding(scale.px(2))
+                    .on_press(Message::ClearWavetable),
+            );
+        if let Some(embedded) = embedded {
+            buttons = buttons.push(
+                Checkbox::new(embedded, "Embed", Message::EmbedWavetable)
+                    .size(scale.px(14))
+                    .spacing(scale.px(4))
+                    .text_size(font_size),
+            );
+        }
+        Column::new()
+            .spacing(scale.px(2))
+            .push(
+                Row::new()
+                    .spacing(scale.px(4))
+                    .align_items(Alignment::Center)
+                    .push(
+                        Text::new("Wavetable")
+                            .size(font_size)
+                            .font(assets::NOTO_SANS_BOLD),
+                    )
+                    .push(
+                        Button::new(&mut self.previous_button, Text::new("<").size(font_size))
+                            .padding(scale.px(2))
+                            .on_press(Message::WavetableTarget(self.target.saturating_sub(1))),
+                    )
+                    .push(Text::new(format!("Osc {}", self.target + 1)).size(font_size))
+                    .push(
+                        Button::new(&mut self.next_button, Text::new(">").size(font_size))
+                            .padding(scale.px(2))
+                            .on_press(Message::WavetableTarget((self.target + 1).min(7))),
+                    ),
+            )
+            .push(
+                TextInput::new(
+                    &mut self.path_input,
+                    "Path to .wav file",
+                    &self.path,
+                    Message::WavetablePath,
+                )
+                .size(font_size)
+                .padding(scale.px(2))
+                .width(scale.len(200))
+                .on_submit(Message::LoadWavetable),
+            )
+            .push(buttons.push(Text::new(status).size(scale.px(12))))
+    }
+}
+
+/// The macro knobs, and an editor for what they're mapped to.
+struct MacroWidget {
+    sliders: [param_slider::State; MACRO_COUNT],
+    /// Macro whose mappings are shown.
+    selected: usize,
+    /// Parameter the next mapping moves, picked by moving it while `learning`.
+    target: Option<ParamPtr>,
+    learning: bool,
+    /// Offsets in percent of the target's range, and the curve exponent.
+    min: String,
+    max: String,
+    curve: String,
+    error: Option<String>,
+
+    macro_buttons: [widget::button::State; 2],
+    learn_button: widget::button::State,
+    min_input: widget::text_input::State,
+    max_input: widget::text_input::State,
+    curve_input: widget::text_input::State,
+    add_button: widget::button::State,
+    remove_buttons: Vec<widget::button::State>,
+}
+impl MacroWidget {
+    fn new() -> Self {
+        Self {
+            sliders: Default::default(),
+            selected: 0,
+            target: None,
+            learning: false,
+            min: "0".to_string(),
+            max: "100".to_string(),
+            curve: "1".to_string(),
+            error: None,
+            macro_buttons: Default::default(),
+            learn_button: Default::default(),
+            min_input: Default::default(),
+            max_input: Default::default(),
+            curve_input: Default::default(),
+            add_button: Default::default(),
+            remove_buttons: Vec::new(),
+        }
+    }
+    fn add(&mut self, params: &SynthPluginParams, param_names: &[(String, ParamPtr, String)]) {
+        let parse = |text: &str, name: &str| {
+            text.trim()
+                .trim_end_matches('%')
+                .parse::<f32>()
+                .map_err(|_| format!("{name} isn't a number"))
+        };
+        let mapping = (|| {
+            let (id, _, _) = param_names
+                .iter()
+                .find(|(_, param, _)| Some(*param) == self.target)
+                .ok_or_else(|| "Learn a parameter to map first".to_string())?;
+            Ok(MacroMapping {
+                macro_index: self.selected,
+                param: id.clone(),
+                shape: MacroShape {
+                    min: parse(&self.min, "Min")?.clamp(-100.0, 100.0) / 100.0,
+                    max: parse(&self.max, "Max")?.clamp(-100.0, 100.0) / 100.0,
+                    curve: parse(&self.curve, "Curve")?.clamp(0.1, 10.0),
+                },
+            })
+        })();
+        let added = mapping.and_then(|mapping| {
+            let mut mappings = match params.macro_mappings.write() {
+                Ok(mappings) => mappings,
+                Err(_) => return Ok(()),
+            };
+            // A parameter can only be mapped once per macro, so adding it again updates it
+            mappings.retain(|existing| {
+                existing.macro_index != mapping.macro_index || existing.param != mapping.param
+            });
+            if mappings.len() >= MAX_MAPPINGS {
+                return Err(format!("There can't be more than {MAX_MAPPINGS} mappings"));
+            }
+            mappings.push(mapping);
+            Ok(())
+        });
+        self.error = added.err();
+    }
+    fn ui<'a>(
+        &'a mut self,
+        params: &'a SynthPluginParams,
+        param_names: &[(String, ParamPtr, String)],
+        scale: Scale,
+    ) -> Column<'a, Message> {
+        let slider_height: Length = scale.len(14);
+        let slider_width: Length = scale.len(60);
+        let font_size = scale.px(14);
+        let stepper = |states: &'a mut [widget::button::State; 2],
+                       label: String,
+                       width: u16,
+                       previous: Message,
+                       next: Message| {
+            let [previous_state, next_state] = states;
+            Row::new()
+                .spacing(scale.px(2))
+                .align_items(Alignment::Center)
+                .push(
+                    Button::new(previous_state, Text::new("<").size(font_size))
+                        .padding(scale.px(2))
+                        .on_press(previous),
+                )
+                .push(
+                    Text::new(label)
+                        .size(font_size)
+                        .width(scale.len(width))
+                        .horizontal_alignment(alignment::Horizontal::Center),
+                )
+                .push(
+                    Button::new(next_state, Text::new(">").size(font_size))
+                        .padding(scale.px(2))
+                        .on_press(next),
+                )
+        };
+
+        let mut knobs = Row::new().spacing(scale.px(8));
+        for (index, (state, param)) in self.sliders.iter_mut().zip(params.macros()).enumerate() {
+            knobs = knobs.push(
+                Column::new()
+                    .width(slider_width)
+                    .push(Text::new(format!("Macro {}", index + 1)).size(font_size))
+                    .push(
+                        ParamSlider::new(state, param)
+                            .height(slider_height)
+                            .width(slider_width)
+                            .text_size(font_size)
+                            .map(Message::ParamUpdate),
+                    ),
+            );
+        }
+
+        let name = |id: &str| {
+            param_names
+                .iter()
+                .find(|(other, _, _)| other == id)
+                .map_or_else(|| id.to_string(), |(_, _, name)| name.clone())
+        };
+        let target = if self.learning {
+            "Move a parameter...".to_string()
+        } else {
+            param_names
+                .iter()
+                .find(|(_, param, _)| Some(*param) == self.target)
+                .map_or_else(|| "Nothing".to_string(), |(_, _, name)| name.clone())
+        };
+        let editor = Row::new()
+            .spacing(scale.px(8))
+            .align_items(Alignment::Center)
+            .push(Text::new("Map").size(font_size))
+            .push(stepper(
+                &mut self.macro_buttons,
+                format!("Macro {}", self.selected + 1),
+                60,
+                Message::SelectMacro(self.selected.saturating_sub(1)),
+                Message::SelectMacro((self.selected + 1).min(MACRO_COUNT - 1)),
+            ))
+            .push(Text::new("to").size(font_size))
+            .push(Text::new(target).size(font_size).width(scale.len(140)))
+            .push(
+                Button::new(
+                    &mut self.learn_button,
+                    Text::new(if self.learning { "Cancel" } else { "Learn" }).size(font_size),
+                )
+                .padding(scale.px(2))
+                .on_press(Message::LearnMacroTarget),
+            )
+            .push(Text::new("Min %").size(font_size))
+            .push(
+                TextInput::new(&mut self.min_input, "0", &self.min, Message::MacroMin)
+                    .size(font_size)
+                    .padding(scale.px(2))
+                    .width(scale.len(40))
+                    .on_submit(Message::AddMacroMapping),
+            )
+            .push(Text::new("Max %").size(font_size))
+            .push(
+                TextInput::new(&mut self.max_input, "100", &self.max, Message::MacroMax)
+                    .size(font_size)
+                    .padding(scale.px(2))
+                    .width(scale.len(40))
+                    .on_submit(Message::AddMacroMapping),
+            )
+            .push(Text::new("Curve").size(font_size))
+            .push(
+                TextInput::new(&mut self.curve_input, "1", &self.curve, Message::MacroCurve)
+                    .size(font_size)
+                    .padding(scale.px(2))
+                    .width(scale.len(40))
+                    .on_submit(Message::AddMacroMapping),
+            )
+            .push(
+                Button::new(&mut self.add_button, Text::new("Add").size(font_size))
+                    .padding(scale.px(2))
+                    .on_press(Message::AddMacroMapping),
+            )
+            .push(Text::new(self.error.clone().unwrap_or_default()).size(scale.px(12)));
+
+        let mappings = params
+            .macro_mappings
+            .read()
+            .map(|mappings| mappings.to_vec())
+            .unwrap_or_default();
+        self.remove_buttons
+            .resize_with(mappings.len(), Default::default);
+        let mut list = Column::new().spacing(scale.px(2));
+        for ((index, mapping), button) in mappings
+            .iter()
+            .enumerate()
+            .zip(self.remove_buttons.iter_mut())
+            .filter(|((_, mapping), _)| mapping.macro_index == self.selected)
+        {
+            list = list.push(
+                Row::new()
+                    .spacing(scale.px(8))
+                    .align_items(Alignment::Center)
+                    .push(
+                        Text::new(name(&mapping.param))
+                            .size(font_size)
+                            .width(scale.len(140)),
+                    )
+                    .push(
+                        Text::new(format!(
+                            "{:.0}% to {:.0}%, curve {:.2}",
+                            mapping.shape.min * 100.0,
+                            mapping.shape.max * 100.0,
+                            mapping.shape.curve
+                        ))
+                        .size(font_size)
+                        .width(scale.len(180)),
+                    )
+                    .push(
+                        Button::new(button, Text::new("Remove").size(scale.px(12)))
+                            .padding(scale.px(2))
+                            .on_press(Message::RemoveMacroMapping(index)),
+                    ),
+            );
+        }
+
+        Column::new()
+            .spacing(scale.px(4))
+            .push(
+                Text::new("Macros")
+                    .size(scale.px(18))
+                    .font(assets::NOTO_SANS_BOLD),
+            )
+            .push(knobs)
+            .push(editor)
+            .push(list)
+    }
+}
+
+/// Output level, clipping and voice count, read from the audio thread's [`Meters`].
+#[derive(Default)]
+struct MeterWidget {
+    peak_meter: nih_widgets::peak_meter::State,
+}
+impl MeterWidget {
+    fn ui<'a>(&'a mut self, meters: &Meters, scale: Scale) -> Column<'a, Message> {
+        let font_size = scale.px(14);
+        let peak = meters.peak.load();
+        let rms = meters.rms.load();
+        let clip_color = if peak >= 1.0 {
+            Color::from_rgb8(220, 40, 40)
+        } else {
+            Color::from_rgb8(200, 200, 200)
+        };
+        let rms_text = if rms > util::MINUS_INFINITY_GAIN {
+            format!("RMS {:.1} dB", util::gain_to_db(rms))
+        } else {
+            "RMS -inf dB".to_string()
+        };
+        Column::new()
+            .spacing(scale.px(2))
+            .push(
+                Text::new("Output")
+                    .size(font_size)
+                    .font(assets::NOTO_SANS_BOLD),
+            )
+            .push(
+                nih_widgets::PeakMeter::new(&mut self.peak_meter, util::gain_to_db(peak))
+                    .hold_time(Duration::from_millis(600))
+                    .width(scale.len(260)),
+            )
+            .push(
+                Row::new()
+                    .spacing(scale.px(12))
+                    .push(Text::new(rms_text).size(font_size).width(scale.len(100)))
+                    .push(Text::new("Clip").size(font_size).color(clip_color))
+                    .push(
+                        Text::new(format!(
+                            "Voices {}",
+                            meters.active_voices.load(Ordering::Relaxed)
+                        ))
+                        .size(font_size),
+                    ),
+            )
+    }
+}
+
+/// Live waveform or spectrum of the output, or of one oscillator of the newest voice.
+struct ScopeWidget {
+    spectrum: bool,
+    /// Lines the waveform up with a rising zero crossing.
+    trigger: bool,
+    /// Keeps showing the same samples.
+    frozen: bool,
+    /// The samples last read from [`Meters::scope`].
+    samples: Vec<f32>,
+    /// [`crate::metering::ScopeBuffer::written`] when `samples` were read, so they're only read
+    /// again once the audio thread has pushed more.
+    written: usize,
+    /// Spectrum of `samples`, worked out the first time it's shown.
+    magnitudes: Option<Vec<f32>>,
+    /// 0 for the output or `n` for oscillator `n`, mirrored into [`Meters::scope_source`].
+    source: usize,
+    fft: Arc<dyn RealToComplex<f32>>,
+
+    spectrum_button: widget::button::State,
+    trigger_button: widget::button::State,
+    freeze_button: widget::button::State,
+    source_buttons: [widget::button::State; 2],
+}
+impl ScopeWidget {
+    fn new(source: usize) -> Self {
+        Self {
+            spectrum: false,
+            trigger: true,
+            frozen: false,
+            samples: vec![0.0; SCOPE_SIZE],
+            written: 0,
+            magnitudes: None,
+            source,
+            fft: RealFftPlanner::<f32>::new().plan_fft_forward(SCOPE_SIZE),
+            spectrum_button: Default::default(),
+            trigger_button: Default::default(),
+            freeze_button: Default::default(),
+            source_buttons: Default::default(),
+        }
+    }
+    /// Reads the scope's samples if there are new ones, and works out the spectrum if it's shown.
+    fn refresh(&mut self, meters: &Meters) {
+        let written = meters.scope.written();
+        if !self.frozen && written != self.written {
+            self.samples = meters.scope.read();
+            self.written = written;
+            self.magnitudes = None;
+        }
+        if self.spectrum && self.magnitudes.is_none() {
+            self.magnitudes = Some(scope::spectrum(self.fft.as_ref(), &self.samples));
+        }
+    }
+    fn ui<'a>(&'a mut self, meters: &Meters, scale: Scale) -> Column<'a, Message> {
+        let font_size = scale.px(14);
+        let scope = match &self.magnitudes {
+            Some(magnitudes) if self.spectrum => {
+                Scope::spectrum(magnitudes, meters.sample_rate.load())
+            }
+            _ => Scope::waveform(&self.samples, self.trigger),
+        };
+        let source = match self.source {
+            0 => "Output".to_string(),
+            oscillator => format!("Osc {}", oscillator),
+        };
+        let toggle = |state: &'a mut widget::button::State, label: &str, message: Message| {
+            Button::new(state, Text::new(label).size(font_size))
+                .padding(scale.px(2))
+                .on_press(message)
+        };
+        let [previous_source, next_source] = &mut self.source_buttons;
+        Column::new()
+            .spacing(scale.px(4))
+            .push(
+                Row::new()
+                    .spacing(scale.px(8))
+                    .align_items(Alignment::Center)
+                    .push(
+                        Text::new("Scope")
+                            .size(scale.px(18))
+                            .font(assets::NOTO_SANS_BOLD),
+                    )
+                    .push(
+                        Button::new(previous_source, Text::new("<").size(font_size))
+                            .padding(scale.px(2))
+                            .on_press(Message::ScopeSource(self.source.saturating_sub(1))),
+                    )
+                    .push(
+                        Text::new(source)
+                            .size(font_size)
+                            .width(scale.len(50))
+                            .horizontal_alignment(alignment::Horizontal::Center),
+                    )
+                    .push(
+                        Button::new(next_source, Text::new(">").size(font_size))
+                            .padding(scale.px(2))
+                            .on_press(Message::ScopeSource((self.source + 1).min(8))),
+                    )
+                    .push(toggle(
+                        &mut self.spectrum_button,
+                        if self.spectrum { "Wave" } else { "Spectrum" },
+                        Message::ToggleScopeSpectrum,
+                    ))
+                    .push(toggle(
+                        &mut self.trigger_button,
+                        if self.trigger {
+                            "Trigger On"
+                        } else {
+                            "Trigger Off"
+                        },
+                        Message::ToggleScopeTrigger,
+                    ))
+                    .push(toggle(
+                        &mut self.freeze_button,
+                        if self.frozen { "Unfreeze" } else { "Freeze" },
+                        Message::ToggleScopeFreeze,
+                    )),
+            )
+            .push(
+                Canvas::new(scope)
+                    .width(scale.len(360))
+                    .height(scale.len(120)),
+            )
+    }
+}
+
+/// Times after the start of the note the voice preview can start at, in seconds.
+const PREVIEW_OFFSETS: [f32; 8] = [0.0, 0.01, 0.05, 0.1, 0.25, 0.5, 1.0, 2.0];
+/// How long the patch has to stay the same before the voice preview is rendered again, so
+/// dragging a slider doesn't render a voice every frame.
+const PREVIEW_DELAY: Duration = Duration::from_millis(150);
+
+/// Everything the voice preview depends on, so it only gets rendered again when one changes.
+#[derive(PartialEq)]
+struct PreviewKey {
+    values: Vec<f32>,
+    mappings_version: u64,
+    wavetable_version: u64,
+    note: u8,
+    offset: usize,
+}
+
+/// The waveform of a whole voice, with the matrix, filter and envelopes, at a chosen note.
+struct PreviewWidget {
+    note: u8,
+    /// Index into [`PREVIEW_OFFSETS`].
+    offset: usize,
+    rendered: Option<(PreviewKey, Vec<f32>)>,
+    /// The values the patch last changed to, and when, while they differ from the ones rendered.
+    changed: Option<(Vec<f32>, Instant)>,
+
+    note_buttons: [widget::button::State; 2],
+    offset_buttons: [widget::button::State; 2],
+}
+impl PreviewWidget {
+    fn new() -> Self {
+        Self {
+            note: 60,
+            offset: 3,
+            rendered: None,
+            changed: None,
+            note_buttons: Default::default(),
+            offset_buttons: Default::default(),
+        }
+    }
+    /// Renders the voice again when the patch changed and then stayed the same for
+    /// [`PREVIEW_DELAY`].
+    fn render(&mut self, params: &SynthPluginParams, param_names: &[(String, ParamPtr, String)]) {
+        let values: Vec<f32> = param_names
+            .iter()
+            // SAFETY: The parameters are owned by `params`, which outlives this call
+            .map(|(_, param, _)| unsafe { param.modulated_normalized_value() })
+            .collect();
+        let wavetable_version = params
+            .wavetables
+            .read()
+            .map(|wavetables| wavetables.version)
+            .unwrap_or_default();
+        let mappings = match params.macro_mappings.read() {
+            Ok(mappings) => mappings,
+            Err(_) => return,
+        };
+        let unchanged = matches!(&self.rendered, Some((key, _)) if key.values == values
+            && key.mappings_version == mappings.version()
+            && key.wavetable_version == wavetable_version
+            && key.note == self.note
+            && key.offset == self.offset);
+        if unchanged {
+            self.changed = None;
+            return;
+        }
+        // Every change starts the wait over, the first preview doesn't wait
+        match &self.changed {
+            Some((last, since)) if *last == values => {
+                if self.rendered.is_some() && since.elapsed() < PREVIEW_DELAY {
+                    return;
+                }
+            }
+            _ => {
+                if self.rendered.is_some() {
+                    self.changed = Some((values, Instant::now()));
+                    return;
+                }
+            }
+        }
+        self.changed = None;
+
+        let mut offsets = MacroOffsets::default();
+        offsets.set_mappings(&mappings, |id| {
+            param_names
+                .iter()
+                .find(|(other, _, _)| other == id)
+                .map(|(_, param, _)| *param)
+        });
+        offsets.update(params.macros().map(|param| param.value()));
+        let (osc_params, voice_params, mod_matrix) =
+            params.engine_params(voice_preview::SAMPLE_RATE, None, &offsets);
+        let wavetables = params
+            .wavetables
+            .read()
+            .map(|wavetables| wavetables.tables.clone())
+            .unwrap_or_default();
+        let samples = voice_preview::render(
+            &osc_params,
+            voice_params,
+            &mod_matrix,
+            &wavetables,
+            self.note,
+            PREVIEW_OFFSETS[self.offset],
+        );
+        let key = PreviewKey {
+            values,
+            mappings_version: mappings.version(),
+            wavetable_version,
+            note: self.note,
+            offset: self.offset,
+        };
+        self.rendered = Some((key, samples));
+    }
+    fn ui<'a>(
+        &'a mut self,
+        params: &'a SynthPluginParams,
+        param_names: &[(String, ParamPtr, String)],
+        scale: Scale,
+    ) -> Column<'a, Message> {
+        let font_size = scale.px(14);
+        self.render(params, param_names);
+
+        let samples: &[f32] = match &self.rendered {
+            Some((_, samples)) => samples,
+            None => &[],
+        };
+
+        let stepper = |states: &'a mut [widget::button::State; 2],
+                       label: String,
+                       previous: Message,
+                       next: Message| {
+            let [previous_state, next_state] = states;
+            Row::new()
+                .spacing(scale.px(2))
+                .align_items(Alignment::Center)
+                .push(
+                    Button::new(previous_state, Text::new("<").size(font_size))
+                        .padding(scale.px(2))
+                        .on_press(previous),
+                )
+                .push(
+                    Text::new(label)
+                        .size(font_size)
+                        .width(scale.len(60))
+                        .horizontal_alignment(alignment::Horizontal::Center),
+                )
+                .push(
+                    Button::new(next_state, Text::new(">").size(font_size))
+                        .padding(scale.px(2))
+                        .on_press(next),
+                )
+        };
+        Column::new()
+            .spacing(scale.px(4))
+            .push(
+                Row::new()
+                    .spacing(scale.px(8))
+                    .align_items(Alignment::Center)
+                    .push(
+                        Text::new("Voice Preview")
+                            .size(scale.px(18))
+                            .font(assets::NOTO_SANS_BOLD),
+                    )
+                    .push(stepper(
+                        &mut self.note_buttons,
+                        format!(
+                            "{}{}",
+                            util::NOTES[self.note as usize % 12],
+                            self.note as i32 / 12 - 1
+                        ),
+                        Message::PreviewNote(self.note.saturating_sub(1)),
+                        Message::PreviewNote((self.note + 1).min(127)),
+                    ))
+                    .push(stepper(
+                        &mut self.offset_buttons,
+                        format!("{} ms", (PREVIEW_OFFSETS[self.offset] * 1000.0).round()),
+                        Message::PreviewOffset(self.offset.saturating_sub(1)),
+                        Message::PreviewOffset((self.offset + 1).min(PREVIEW_OFFSETS.len() - 1)),
+                    )),
+            )
+            .push(
+                Canvas::new(VoicePreview::new(samples))
+                    .width(scale.len(300))
+                    .height(scale.len(120)),
+            )
+    }
+}
+
+/// Amounts the randomiser's mutations and breeding step through.
+const RANDOMISER_AMOUNTS: [f32; 7] = [0.05, 0.1, 0.25, 0.5, 0.75, 0.9, 1.0];
+
+/// Randomises, mutates and breeds whole patches.
+struct RandomiserWidget {
+    /// Index into [`RANDOMISER_AMOUNTS`].
+    amount: usize,
+    /// Whether each group in [`randomiser::Group::ALL`] is locked.
+    locks: [bool; 6],
+    /// The patches to breed, stored as normalized values in [`randomiser::genes`] order.
+    parents: [Option<Vec<f32>>; 2],
+
+    amount_buttons: [widget::button::State; 2],
+    randomise_button: widget::button::State,
+    mutate_button: widget::button::State,
+    parent_buttons: [widget::button::State; 2],
+    breed_button: widget::button::State,
+}
+impl RandomiserWidget {
+    fn new() -> Self {
+        Self {
+            amount: 2,
+            locks: Default::default(),
+            parents: Default::default(),
+            amount_buttons: Default::default(),
+            randomise_button: Default::default(),
+            mutate_button: Default::default(),
+            parent_buttons: Default::default(),
+            breed_button: Default::default(),
+        }
+    }
+    fn is_locked(&self, group: randomiser::Group) -> bool {
+        randomiser::Group::ALL
+            .iter()
+            .zip(self.locks)
+            .any(|(other, locked)| *other == group && locked)
+    }
+    fn ui<'a>(&'a mut self, scale: Scale) -> Column<'a, Message> {
+        let font_size = scale.px(14);
+        let button = |state: &'a mut widget::button::State, label: &str| {
+            Button::new(state, Text::new(label).size(font_size)).padding(scale.px(2))
+        };
+        let [previous, next] = &mut self.amount_buttons;
+        let [store_a, store_b] = &mut self.parent_buttons;
+        let stored = |parent: &Option<Vec<f32>>| if parent.is_some() { "Stored" } else { "Empty" };
+
+        let mut locks = Row::new()
+            .spacing(scale.px(10))
+            .align_items(Alignment::Center)
+            .push(Text::new("Lock").size(font_size));
+        for (index, (group, locked)) in randomiser::Group::ALL.iter().zip(self.locks).enumerate() {
+            locks = locks.push(
+                Checkbox::new(locked, group.name(), move |locked| {
+                    Message::LockGroup(index, locked)
+                })
+                .size(scale.px(14))
+                .spacing(scale.px(4))
+                .text_size(font_size),
+            );
+        }
+
+        let mut breed = button(&mut self.breed_button, "Breed");
+        if self.parents.iter().all(Option::is_some) {
+            breed = breed.on_press(Message::BreedPatch);
+        }
+        Column::new()
+            .spacing(scale.px(6))
+            .push(
+                Row::new()
+                    .spacing(scale.px(8))
+                    .align_items(Alignment::Center)
+                    .push(
+                        Text::new("Randomiser")
+                            .size(scale.px(18))
+                            .font(assets::NOTO_SANS_BOLD),
+                    )
+                    .push(
+                        button(&mut self.randomise_button, "Randomise")
+                            .on_press(Message::RandomisePatch),
+                    )
+                    .push(button(&mut self.mutate_button, "Mutate").on_press(Message::MutatePatch))
+                    .push(Text::new("Amount").size(font_size))
+                    .push(
+                        button(previous, "<")
+                            .on_press(Message::RandomiserAmount(self.amount.saturating_sub(1))),
+                    )
+                    .push(
+                        Text::new(format!("{:.0}%", RANDOMISER_AMOUNTS[self.amount] * 100.0))
+                            .size(font_size)
+                            .width(scale.len(40))
+                            .horizontal_alignment(alignment::Horizontal::Center),
+                    )
+                    .push(button(next, ">").on_press(Message::RandomiserAmount(
+                        (self.amount + 1).min(RANDOMISER_AMOUNTS.len() - 1),
+                    )))
+                    .push(Space::with_width(scale.len(20)))
+                    .push(button(store_a, "Store A").on_press(Message::StoreParent(0)))
+                    .push(Text::new(stored(&self.parents[0])).size(font_size))
+                    .push(button(store_b, "Store B").on_press(Message::StoreParent(1)))
+                    .push(Text::new(stored(&self.parents[1])).size(font_size))
+                    .push(breed),
+            )
+            .push(locks)
+    }
+}
+
+/// The MIDI controllers learned by the parameters, with their ranges and modes.
+#[derive(Default)]
+struct MidiLearnWidget {
+    rows: Vec<CcMappingRow>,
+    cancel_button: widget::button::State,
+}
+/// A mapping in the list, with its range as typed while it's being edited.
+struct CcMappingRow {
+    /// The mapping as it was when the text was filled in, so the text is refreshed when the
+    /// mapping changes some other way, like loading a patch.
+    mapping: CcMapping,
+    /// Range in percent of the parameter's range.
+    min: String,
+    max: String,
+
+    min_input: widget::text_input::State,
+    max_input: widget::text_input::State,
+    mode_button: widget::button::State,
+    remove_button: widget::button::State,
+}
+impl CcMappingRow {
+    fn new(mapping: &CcMapping) -> Self {
+        Self {
+            mapping: mapping.clone(),
+            min: format!("{:.0}", mapping.range.min * 100.0),
+            max: format!("{:.0}", mapping.range.max * 100.0),
+            min_input: Default::default(),
+            max_input: Default::default(),
+            mode_button: Default::default(),
+            remove_button: Default::default(),
+        }
+    }
+}
+impl MidiLearnWidget {
+    /// Applies the range typed into a row to its mapping, or puts back the mapping's range if
+    /// it isn't a number.
+    fn set_range(&mut self, params: &SynthPluginParams, index: usize) {
+        let parse = |text: &str| {
+            text.trim()
+                .trim_end_matches('%')
+                .parse::<f32>()
+                .ok()
+                .map(|percent| percent.clamp(0.0, 100.0) / 100.0)
+        };
+        if let (Some(row), Ok(mut mappings)) =
+            (self.rows.get_mut(index), params.cc_mappings.write())
+        {
+            if let Some(mapping) = mappings.get_mut(index) {
+                if let (Some(min), Some(max)) = (parse(&row.min), parse(&row.max)) {
+                    mapping.range.min = min;
+                    mapping.range.max = max;
+                }
+                *row = CcMappingRow::new(mapping);
+            }
+        }
+    }
+    fn ui<'a>(
+        &'a mut self,
+        params: &SynthPluginParams,
+        param_names: &[(String, ParamPtr, String)],
+        scale: Scale,
+    ) -> Column<'a, Message> {
+        let font_size = scale.px(14);
+        let mappings = params
+            .cc_mappings
+            .read()
+            .map(|mappings| mappings.to_vec())
+            .unwrap_or_default();
+        // Rows whose mapping changed since their text was filled in are rebuilt
+        let unchanged = self
+            .rows
+            .iter()
+            .zip(&mappings)
+            .take_while(|(row, mapping)| row.mapping == **mapping)
+            .count();
+        self.rows.truncate(unchanged);
+        self.rows
+            .extend(mappings[unchanged..].iter().map(CcMappingRow::new));
+
+        let learning = param_slider::learning(|learning| {
+            param_names.iter().any(|(_, param, _)| *param == learning)
+        })
+        .and_then(|learning| {
+            param_names
+                .iter()
+                .find(|(_, param, _)| *param == learning)
+                .map(|(_, _, name)| name.clone())
+        });
+        let mut header = Row::new()
+            .spacing(scale.px(8))
+            .align_items(Alignment::Center)
+            .push(
+                Text::new("MIDI Learn")
+                    .size(scale.px(18))
+                    .font(assets::NOTO_SANS_BOLD),
+            );
+        header = match learning {
+            Some(name) => header
+                .push(Text::new(format!("Move a controller to map it to {name}")).size(font_size))
+                .push(
+                    Button::new(&mut self.cancel_button, Text::new("Cancel").size(font_size))
+                        .padding(scale.px(2))
+                        .on_press(Message::CancelMidiLearn),
+                ),
+            None if mappings.len() >= MAX_CC_MAPPINGS => header.push(
+                Text::new(format!(
+                    "There can't be more than {MAX_CC_MAPPINGS} mappings, remove one to map another"
+                ))
+                .size(font_size),
+            ),
+            None => header.push(
+                Text::new("Right-click a slider and move a controller to map it").size(font_size),
+            ),
+        };
+
+        let mut list = Column::new().spacing(scale.px(2));
+        for (index, (row, mapping)) in self.rows.iter_mut().zip(&mappings).enumerate() {
+            let name = param_names
+                .iter()
+                .find(|(id, _, _)| *id == mapping.param)
+                .map(|(_, _, name)| name.clone())
+                .unwrap_or_else(|| format!("Unknown ({})", mapping.param));
+            list = list.push(
+                Row::new()
+                    .spacing(scale.px(8))
+                    .align_items(Alignment::Center)
+                    .push(
+                        Text::new(format!("CC {}", mapping.cc))
+                            .size(font_size)
+                            .width(scale.len(50)),
+                    )
+                    .push(Text::new(name).size(font_size).width(scale.len(160)))
+                    .push(Text::new("Min %").size(font_size))
+                    .push(
+                        TextInput::new(&mut row.min_input, "0", &row.min, move |text| {
+                            Message::CcMin(index, text)
+                        })
+                        .size(font_size)
+                        .padding(scale.px(2))
+                        .width(scale.len(40))
+                        .on_submit(Message::SetCcRange(index)),
+                    )
+                    .push(Text::new("Max %").size(font_size))
+                    .push(
+                        TextInput::new(&mut row.max_input, "100", &row.max, move |text| {
+                            Message::CcMax(index, text)
+                        })
+                        .size(font_size)
+                        .padding(scale.px(2))
+                        .width(scale.len(40))
+                        .on_submit(Message::SetCcRange(index)),
+                    )
+                    .push(
+                        Button::new(
+                            &mut row.mode_button,
+                            Text::new(mapping.range.mode.name())
+                                .size(font_size)
+                                .width(scale.len(60))
+                                .horizontal_alignment(alignment::Horizontal::Center),
+                        )
+                        .padding(scale.px(2))
+                        .on_press(Message::ToggleCcMode(index)),
+                    )
+                    .push(
+                        Button::new(
+                            &mut row.remove_button,
+                            Text::new("Remove").size(scale.px(12)),
+                        )
+                        .padding(scale.px(2))
+                        .on_press(Message::RemoveCcMapping(index)),
+                    ),
+            );
+        }
+
+        Column::new().spacing(scale.px(6)).push(header).push(list)
+    }
+}
+
+/// Undo and redo buttons, disabled when there's nothing to undo or redo.
+fn history_controls<'a>(
+    [undo, redo]: &'a mut [widget::button::State; 2],
+    history: &History,
+    scale: Scale,
+) -> Row<'a, Message> {
+    let font_size = scale.px(14);
+    let mut undo = Button::new(undo, Text::new("Undo").size(font_size)).padding(scale.px(2));
+    if history.can_undo() {
+        undo = undo.on_press(Message::Undo);
+    }
+    let mut redo = Button::new(redo, Text::new("Redo").size(font_size)).padding(scale.px(2));
+    if history.can_redo() {
+        redo = redo.on_press(Message::Redo);
+    }
+    Row::new()
+        .padding(scale.px(4))
+        .spacing(scale.px(4))
+        .push(undo)
+        .push(redo)
+}
+
+/// The UI scale buttons and the resize corner, along the bottom edge of the editor. They set the
+/// `saved` scale, which the editor is laid out at once it's opened again.
+fn scale_controls<'a>(
+    [smaller, larger]: &'a mut [widget::button::State; 2],
+    resize_corner: &'a mut ResizeCorner,
+    scale: Scale,
+    saved: Scale,
+) -> Row<'a, Message> {
+    let font_size = scale.px(14);
+    let smaller_step = SCALE_STEPS
+        .iter()
+        .rev()
+        .find(|step| **step < saved.0 - 0.01)
+        .unwrap_or(&MIN_SCALE);
+    let larger_step = SCALE_STEPS
+        .iter()
+        .find(|step| **step > saved.0 + 0.01)
+        .unwrap_or(&MAX_SCALE);
+    let mut row = Row::new()
+        .width(Length::Fill)
+        .padding(scale.px(4))
+        .spacing(scale.px(4))
+        .align_items(Alignment::End)
+        .push(Space::with_width(Length::Fill));
+    if (saved.0 - scale.0).abs() > 0.01 {
+        row = row.push(Text::new("Reopen the editor to apply").size(font_size));
+    }
+    row.push(Text::new("UI Scale").size(font_size))
+        .push(
+            Button::new(smaller, Text::new("<").size(font_size))
+                .padding(scale.px(2))
+                .on_press(Message::SetScale(*smaller_step)),
+        )
+        .push(
+            Text::new(format!("{:.0}%", saved.0 * 100.0))
+                .size(font_size)
+                .width(scale.len(40))
+                .horizontal_alignment(alignment::Horizontal::Center),
+        )
+        .push(
+            Button::new(larger, Text::new(">").size(font_size))
+                .padding(scale.px(2))
+                .on_press(Message::SetScale(*larger_step)),
+        )
+        .push(
+            Canvas::new(ResizeCornerProgram {
+                state: resize_corner,
+                scale: saved.0,
+            })
+            .width(scale.len(16))
+            .height(scale.len(16)),
+        )
+}
+
+/// Interaction state of the resize corner.
+#[derive(Debug, Default)]
+struct ResizeCorner {
+    /// Cursor position and scale when the drag started.
+    dragging: Option<(Point, f32)>,
+    /// Scale the drag sets when it's released.
+    preview: Option<f32>,
+}
+
+/// A grip which sets the UI scale by dragging, keeping the default aspect ratio.
+struct ResizeCornerProgram<'a> {
+    state: &'a mut ResizeCorner,
+    scale: f32,
+}
+impl<'a> ResizeCornerProgram<'a> {
+    fn scale_at(&self, cursor: Point) -> Option<f32> {
+        let (origin, scale) = self.state.dragging?;
+        // Follow whichever direction was dragged further, relative to the default size
+        let width = (cursor.x - origin.x) / WIDTH as f32;
+        let height = (cursor.y - origin.y) / HEIGHT as f32;
+        let change = if width.abs() > height.abs() {
+            width
+        } else {
+            height
+        };
+        Some((scale + change).clamp(MIN_SCALE, MAX_SCALE))
+    }
+}
+impl<'a> canvas::Program<Message> for ResizeCornerProgram<'a> {
+    fn update(
+        &mut self,
+        event: canvas::Event,
+        bounds: Rectangle,
+        cursor: canvas::Cursor,
+    ) -> (canvas::event::Status, Option<Message>) {
+        match event {
+            canvas::Event::Mouse(mouse::Event::ButtonPressed(mouse::Button::Left)) => {
+                if let Some(position) = cursor.position().filter(|_| cursor.is_over(&bounds)) {
+                    self.state.dragging = Some((position, self.scale));
+                    return (canvas::event::Status::Captured, None);
+                }
+            }
+            canvas::Event::Mouse(mouse::Event::CursorMoved { position }) => {
+                if self.state.dragging.is_some() {
+                    self.state.preview = self.scale_at(position);
+                    return (canvas::event::Status::Captured, None);
+                }
+            }
+            canvas::Event::Mouse(mouse::Event::ButtonReleased(mouse::Button::Left)) => {
+                if self.state.dragging.is_some() {
+                    let scale = self.state.preview.take();
+                    self.state.dragging = None;
+                    return (
+                        canvas::event::Status::Captured,
+                        scale.map(Message::SetScale),
+                    );
+                }
+            }
+            _ => {}
+        }
+        (canvas::event::Status::Ignored, None)
+    }
+
+    fn draw(&self, bounds: Rectangle, _cursor: canvas::Cursor) -> Vec<canvas::Geometry> {
+        let mut frame = canvas::Frame::new(bounds.size());
+        let (width, height) = (bounds.width, bounds.height);
+        let grip = canvas::Path::new(|p| {
+            for i in 1..=3 {
+                let offset = i as f32 / 4.0;
+                p.move_to(Point::new(width * offset, height));
+                p.line_to(Point::new(width, height * offset));
+            }
+        });
+        frame.stroke(
+            &grip,
+            canvas::Stroke::default().with_color(Color::from_rgb8(120, 120, 120)),
+        );
+        vec![frame.into_geometry()]
+    }
+
+    fn mouse_interaction(&self, bounds: Rectangle, cursor: canvas::Cursor) -> mouse::Interaction {
+        if self.state.dragging.is_some() {
+            mouse::Interaction::Grabbing
+        } else if cursor.is_over(&bounds) {
+            mouse::Interaction::Grab
+        } else {
+            mouse::Interaction::default()
+        }
+    }
+}
+
+/// A light which brightens with an oscillator's envelope level.
+struct ActivityLight {
+    level: f32,
+}
+impl canvas::Program<Message> for ActivityLight {
+    fn draw(&self, bounds: Rectangle, _cursor: canvas::Cursor) -> Vec<canvas::Geometry> {
+        let mut frame = canvas::Frame::new(bounds.size());
+        let radius = bounds.width.min(bounds.height) * 0.5 - 1.0;
+        let light = canvas::Path::circle(frame.center(), radius);
+        let level = self.level.clamp(0.0, 1.0);
+        frame.fill(
+            &light,
+            Color::from_rgb(0.9 - level * 0.8, 0.9, 0.9 - level * 0.8),
+        );
+        frame.stroke(&light, canvas::Stroke::default());
+        vec![frame.into_geometry()]
     }
 }
 
+/// Gain and envelope follower settings for the sidechain input.
 #[derive(Default)]
-struct GlobalEnvelopeWidget {
-    attack: param_slider::State,
-    decay: param_slider::State,
-    sustain: param_slider::State,
-    release: param_slider::State,
+struct SidechainWidget {
+    gain_slider: param_slider::State,
+    mode_slider: param_slider::State,
+    attack_slider: param_slider::State,
+    release_slider: param_slider::State,
 }
-impl GlobalEnvelopeWidget {
-    fn ui<'a>(&'a mut self, params: &'a SynthPluginParams) -> Column<'a, Message> {
-        let slider_height: Length = 14.into();
-        let slider_width: Length = 60.into();
-        let slider_font_size = 14;
-        let font_size = 14;
+impl SidechainWidget {
+    fn ui<'a>(&'a mut self, params: &'a SynthPluginParams, scale: Scale) -> Column<'a, Message> {
+        let slider_height: Length = scale.len(14);
+        let slider_width: Length = scale.len(60);
+        let slider_font_size = scale.px(14);
+        let font_size = scale.px(14);
         Column::new()
-            .max_width(200)
-            // .push(Space::with_height(20.into()))
             .push(
-                Text::new("Amp Env.")
-                    .size(18)
-                    .horizontal_alignment(alignment::Horizontal::Center)
+                Text::new("Sidechain")
+                    .size(font_size)
                     .font(assets::NOTO_SANS_BOLD),
             )
             .push(
                 Row::new()
-                    // .spacing(10)
+                    .spacing(scale.px(8))
                     .push(
                         Column::new()
-                            .max_width(90)
-                            .push({
-                                Canvas::new(EnvelopeWidget::new(
-                                    0.0,
-                                    params.global_attack.value(),
-                                    0.0,
-                                    0.0,
-                                    params.global_decay.value(),
-                                    params.global_sustain.value(),
-                                    params.global_release.value(),
-                                    0.0,
-                                ))
-                                .height(28.into())
-                                .width(slider_width.into())
-                            })
-                            .push(Text::new("Attack").size(font_size))
+                            .width(slider_width)
+                            .push(Text::new("Gain").size(font_size))
                             .push(
-                                ParamSlider::new(&mut self.attack, &params.global_attack)
+                                ParamSlider::new(&mut self.gain_slider, &params.sidechain_gain)
                                     .height(slider_height)
                                     .width(slider_width)
                                     .text_size(slider_font_size)
                                     .map(Message::ParamUpdate),
                             )
-                            .push(Text::new("Decay").size(font_size))
+                            .push(Text::new("Mode").size(font_size))
                             .push(
-                                ParamSlider::new(&mut self.decay, &params.global_decay)
+                                ParamSlider::new(&mut self.mode_slider, &params.sidechain_mode)
                                     .height(slider_height)
                                     .width(slider_width)
                                     .text_size(slider_font_size)
                                     .map(Message::ParamUpdate),
-                            )
-                            .push(Text::new("Sustain").size(font_size))
+                            ),
+                    )
+                    .push(
+                        Column::new()
+                            .width(slider_width)
+                            .push(Text::new("Attack").size(font_size))
                             .push(
-                                ParamSlider::new(&mut self.sustain, &params.global_sustain)
+                                ParamSlider::new(&mut self.attack_slider, &params.sidechain_attack)
                                     .height(slider_height)
                                     .width(slider_width)
                                     .text_size(slider_font_size)
@@ -1296,11 +4005,14 @@ impl GlobalEnvelopeWidget {
                             )
                             .push(Text::new("Release").size(font_size))
                             .push(
-                                ParamSlider::new(&mut self.release, &params.global_release)
-                                    .height(slider_height)
-                                    .width(slider_width)
-                                    .text_size(slider_font_size)
-                                    .map(Message::ParamUpdate),
+                                ParamSlider::new(
+                                    &mut self.release_slider,
+                                    &params.sidechain_release,
+                                )
+                                .height(slider_height)
+                                .width(slider_width)
+                                .text_size(slider_font_size)
+                                .map(Message::ParamUpdate),
                             ),
                     ),
             )
@@ -1318,105 +4030,146 @@ struct GlobalParamWidget {
     unison_detune_slider: param_slider::State,
     legato_slider: param_slider::State,
     portamento_slider: param_slider::State,
+    velocity_curve_slider: param_slider::State,
+    velocity_curve_amount_slider: param_slider::State,
+    zero_delay_slider: param_slider::State,
 }
 impl GlobalParamWidget {
-    fn ui<'a>(&'a mut self, params: &'a SynthPluginParams) -> Column<'a, Message> {
-        let slider_height: Length = 14.into();
-        let slider_width: Length = 60.into();
-        let slider_font_size = 14;
-        let font_size = 14;
-        title_bar().push(Space::with_height(14.into())).push(
-            Row::new()
-                .spacing(8)
-                .push(
-                    Column::new()
-                        .width(slider_width)
-                        .push(Text::new("Output Gain").size(font_size))
-                        .push(
-                            ParamSlider::new(&mut self.gain_slider, &params.gain)
+    fn ui<'a>(&'a mut self, params: &'a SynthPluginParams, scale: Scale) -> Column<'a, Message> {
+        let slider_height: Length = scale.len(14);
+        let slider_width: Length = scale.len(60);
+        let slider_font_size = scale.px(14);
+        let font_size = scale.px(14);
+        title_bar(scale)
+            .push(Space::with_height(scale.len(14)))
+            .push(
+                Row::new()
+                    .spacing(scale.px(8))
+                    .push(
+                        Column::new()
+                            .width(slider_width)
+                            .push(Text::new("Output Gain").size(font_size))
+                            .push(
+                                ParamSlider::new(&mut self.gain_slider, &params.gain)
+                                    .height(slider_height)
+                                    .width(slider_width)
+                                    .text_size(slider_font_size)
+                                    .map(Message::ParamUpdate),
+                            )
+                            .push(Text::new("Glob. Crs.").size(font_size))
+                            .push(
+                                ParamSlider::new(
+                                    &mut self.global_coarse_slider,
+                                    &params.global_coarse,
+                                )
                                 .height(slider_height)
                                 .width(slider_width)
                                 .text_size(slider_font_size)
                                 .map(Message::ParamUpdate),
-                        )
-                        .push(Text::new("Glob. Crs.").size(font_size))
-                        .push(
-                            ParamSlider::new(&mut self.global_coarse_slider, &params.global_coarse)
+                            )
+                            .push(Text::new("Octave Size").size(font_size))
+                            .push(
+                                ParamSlider::new(
+                                    &mut self.octave_stretch_slider,
+                                    &params.octave_stretch,
+                                )
                                 .height(slider_height)
                                 .width(slider_width)
                                 .text_size(slider_font_size)
                                 .map(Message::ParamUpdate),
-                        )
-                        .push(Text::new("Octave Size").size(font_size))
-                        .push(
-                            ParamSlider::new(
-                                &mut self.octave_stretch_slider,
-                                &params.octave_stretch,
                             )
-                            .height(slider_height)
+                            .push(Text::new("Bend Range").size(font_size))
+                            .push(
+                                ParamSlider::new(&mut self.bend_range_slider, &params.bend_range)
+                                    .height(slider_height)
+                                    .width(slider_width)
+                                    .text_size(slider_font_size)
+                                    .map(Message::ParamUpdate),
+                            ),
+                    )
+                    .push(
+                        Column::new()
+                            .align_items(Alignment::Start)
                             .width(slider_width)
-                            .text_size(slider_font_size)
-                            .map(Message::ParamUpdate),
-                        )
-                        .push(Text::new("Bend Range").size(font_size))
-                        .push(
-                            ParamSlider::new(&mut self.bend_range_slider, &params.bend_range)
-                                .height(slider_height)
-                                .width(slider_width)
-                                .text_size(slider_font_size)
-                                .map(Message::ParamUpdate),
-                        ),
-                )
-                .push(
-                    Column::new()
-                        .align_items(Alignment::Start)
-                        .width(slider_width)
-                        .push(Text::new("Max Voices").size(font_size))
-                        .push(
-                            ParamSlider::new(&mut self.voice_count_slider, &params.voice_count)
-                                .height(slider_height)
-                                .width(slider_width)
-                                .text_size(slider_font_size)
-                                .map(Message::ParamUpdate),
-                        )
-                        .push(Text::new("Unison").size(font_size))
-                        .push(
-                            ParamSlider::new(&mut self.unison_slider, &params.unison_count)
-                                .height(slider_height)
-                                .width(slider_width)
-                                .text_size(slider_font_size)
-                                .map(Message::ParamUpdate),
-                        )
-                        .push(Text::new("Uni. Detune").size(font_size))
-                        .push(
-                            ParamSlider::new(&mut self.unison_detune_slider, &params.unison_detune)
+                            .push(Text::new("Max Voices").size(font_size))
+                            .push(
+                                ParamSlider::new(&mut self.voice_count_slider, &params.voice_count)
+                                    .height(slider_height)
+                                    .width(slider_width)
+                                    .text_size(slider_font_size)
+                                    .map(Message::ParamUpdate),
+                            )
+                            .push(Text::new("Unison").size(font_size))
+                            .push(
+                                ParamSlider::new(&mut self.unison_slider, &params.unison_count)
+                                    .height(slider_height)
+                                    .width(slider_width)
+                                    .text_size(slider_font_size)
+                                    .map(Message::ParamUpdate),
+                            )
+                            .push(Text::new("Uni. Detune").size(font_size))
+                            .push(
+                                ParamSlider::new(
+                                    &mut self.unison_detune_slider,
+                                    &params.unison_detune,
+                                )
                                 .height(slider_height)
                                 .width(slider_width)
                                 .text_size(slider_font_size)
                                 .map(Message::ParamUpdate),
-                        )
-                        .push(Text::new("Legato").size(font_size))
-                        .push(
-                            ParamSlider::new(&mut self.legato_slider, &params.legato)
+                            )
+                            .push(Text::new("Legato").size(font_size))
+                            .push(
+                                ParamSlider::new(&mut self.legato_slider, &params.legato)
+                                    .height(slider_height)
+                                    .width(slider_width)
+                                    .text_size(slider_font_size)
+                                    .map(Message::ParamUpdate),
+                            ),
+                    )
+                    .push(
+                        Column::new()
+                            .align_items(Alignment::Start)
+                            .width(slider_width)
+                            .push(Text::new("Portamento").size(font_size))
+                            .push(
+                                ParamSlider::new(&mut self.portamento_slider, &params.portamento)
+                                    .height(slider_height)
+                                    .width(slider_width)
+                                    .text_size(slider_font_size)
+                                    .map(Message::ParamUpdate),
+                            )
+                            .push(Text::new("Velo. Curve").size(font_size))
+                            .push(
+                                ParamSlider::new(
+                                    &mut self.velocity_curve_slider,
+                                    &params.velocity_curve,
+                                )
                                 .height(slider_height)
                                 .width(slider_width)
                                 .text_size(slider_font_size)
                                 .map(Message::ParamUpdate),
-                        ),
-                )
-                .push(
-                    Column::new()
-                        .align_items(Alignment::Start)
-                        .width(slider_width)
-                        .push(Text::new("Portamento").size(font_size))
-                        .push(
-                            ParamSlider::new(&mut self.portamento_slider, &params.portamento)
+                            )
+                            .push(Text::new("Custom Velo.").size(font_size))
+                            .push(
+                                ParamSlider::new(
+                                    &mut self.velocity_curve_amount_slider,
+                                    &params.velocity_curve_amount,
+                                )
                                 .height(slider_height)
                                 .width(slider_width)
                                 .text_size(slider_font_size)
                                 .map(Message::ParamUpdate),
-                        ),
-                ),
-        )
+                            )
+                            .push(Text::new("Zero Delay").size(font_size))
+                            .push(
+                                ParamSlider::new(&mut self.zero_delay_slider, &params.zero_delay)
+                                    .height(slider_height)
+                                    .width(slider_width)
+                                    .text_size(slider_font_size)
+                                    .map(Message::ParamUpdate),
+                            ),
+                    ),
+            )
     }
 }
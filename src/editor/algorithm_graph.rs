@@ -0,0 +1,242 @@
+//! A node graph view of the modulation matrix.
+
+use std::array;
+
+use nih_plug_iced::canvas::{self, event, Cursor, Event, Frame, Geometry, Path, Stroke};
+use nih_plug_iced::{alignment, mouse, Color, Point, Rectangle, Size, Vector};
+
+use crate::algorithm::levels;
+use crate::voice::MatrixMode;
+
+use super::Message;
+
+const NODE_RADIUS: f32 = 8.0;
+/// Space kept below the bottom row of nodes for the output bus.
+const OUTPUT_HEIGHT: f32 = 12.0;
+
+/// Interaction state for [`AlgorithmGraph`], which has to outlive the widget itself.
+#[derive(Debug, Default)]
+pub struct State {
+    /// Operator a connection is being dragged from.
+    dragging: Option<usize>,
+    cursor: Option<Point>,
+}
+
+/// Draws the oscillators as nodes with an edge for every active matrix cell, modulators above
+/// the oscillators they modulate and carriers connected to the output bus at the bottom.
+///
+/// Dragging from one node onto another toggles that modulation, clicking a node toggles its
+/// output and right clicking it toggles its feedback.
+pub struct AlgorithmGraph<'a> {
+    state: &'a mut State,
+    /// Indexed by `[target][source]`.
+    amounts: [[f32; 8]; 8],
+    modes: [[MatrixMode; 8]; 8],
+    /// Output levels between 0 and 1.
+    outputs: [f32; 8],
+}
+impl<'a> AlgorithmGraph<'a> {
+    pub fn new(
+        state: &'a mut State,
+        amounts: [[f32; 8]; 8],
+        modes: [[MatrixMode; 8]; 8],
+        outputs: [f32; 8],
+    ) -> Self {
+        Self {
+            state,
+            amounts,
+            modes,
+            outputs,
+        }
+    }
+
+    fn positions(&self, size: Size) -> [Point; 8] {
+        // Levels counted from the bottom, so an operator sits above everything it modulates
+        let depths = levels(array::from_fn(|operator| {
+            array::from_fn(|target| operator != target && self.amounts[target][operator] > 0.0)
+        }));
+        let rows = depths.iter().max().unwrap() + 1;
+        let row_height = (size.height - OUTPUT_HEIGHT) / rows as f32;
+
+        let mut positions = [Point::ORIGIN; 8];
+        for depth in 0..rows {
+            let row: Vec<usize> = (0..8).filter(|&i| depths[i] == depth).collect();
+            let column_width = size.width / row.len() as f32;
+            for (column, &operator) in row.iter().enumerate() {
+                positions[operator] = Point::new(
+                    column_width * (column as f32 + 0.5),
+                    size.height - OUTPUT_HEIGHT - row_height * (depth as f32 + 0.5),
+                );
+            }
+        }
+        positions
+    }
+
+    fn node_at(&self, size: Size, point: Point) -> Option<usize> {
+        self.positions(size)
+            .iter()
+            .position(|position| position.distance(point) <= NODE_RADIUS + 2.0)
+    }
+
+    fn is_used(&self, operator: usize) -> bool {
+        self.outputs[operator] > 0.0
+            || (0..8).any(|other| {
+                self.amounts[operator][other] > 0.0 || self.amounts[other][operator] > 0.0
+            })
+    }
+}
+
+fn mode_color(mode: MatrixMode) -> Color {
+    match mode {
+        MatrixMode::Phase => Color::BLACK,
+        MatrixMode::LinearFm => Color::from_rgb8(40, 90, 200),
+        MatrixMode::Sync => Color::from_rgb8(220, 120, 20),
+        MatrixMode::Ring => Color::from_rgb8(30, 150, 60),
+    }
+}
+
+impl<'a> canvas::Program<Message> for AlgorithmGraph<'a> {
+    fn update(
+        &mut self,
+        event: Event,
+        bounds: Rectangle,
+        cursor: Cursor,
+    ) -> (event::Status, Option<Message>) {
+        let position = cursor.position_in(&bounds);
+        match event {
+            Event::Mouse(mouse::Event::ButtonPressed(mouse::Button::Left)) => {
+                if let Some(node) = position.and_then(|p| self.node_at(bounds.size(), p)) {
+                    self.state.dragging = Some(node);
+                    self.state.cursor = position;
+                    return (event::Status::Captured, None);
+                }
+            }
+            Event::Mouse(mouse::Event::ButtonPressed(mouse::Button::Right)) => {
+                if let Some(node) = position.and_then(|p| self.node_at(bounds.size(), p)) {
+                    let message = Message::ToggleModulation {
+                        source: node,
+                        target: node,
+                    };
+                    return (event::Status::Captured, Some(message));
+                }
+            }
+            Event::Mouse(mouse::Event::CursorMoved { .. }) if self.state.dragging.is_some() => {
+                self.state.cursor = position;
+                return (event::Status::Captured, None);
+            }
+            Event::Mouse(mouse::Event::ButtonReleased(mouse::Button::Left)) => {
+                if let Some(source) = self.state.dragging.take() {
+                    self.state.cursor = None;
+                    let message = match position.and_then(|p| self.node_at(bounds.size(), p)) {
+                        Some(target) if target == source => Some(Message::ToggleOutput(source)),
+                        Some(target) => Some(Message::ToggleModulation { source, target }),
+                        None => None,
+                    };
+                    return (event::Status::Captured, message);
+                }
+            }
+            _ => {}
+        }
+        (event::Status::Ignored, None)
+    }
+
+    fn draw(&self, bounds: Rectangle, _cursor: Cursor) -> Vec<Geometry> {
+        let mut frame = Frame::new(bounds.size());
+        let positions = self.positions(bounds.size());
+        let bus = bounds.height - OUTPUT_HEIGHT * 0.5;
+
+        // Output bus
+        let carriers: Vec<Point> = (0..8)
+            .filter(|&i| self.outputs[i] > 0.0)
+            .map(|i| positions[i])
+            .collect();
+        if !carriers.is_empty() {
+            let left = carriers.iter().map(|p| p.x).fold(f32::MAX, f32::min);
+            let right = carriers.iter().map(|p| p.x).fold(f32::MIN, f32::max);
+            let path = Path::new(|p| {
+                for carrier in carriers.iter() {
+                    p.move_to(Point::new(carrier.x, carrier.y + NODE_RADIUS));
+                    p.line_to(Point::new(carrier.x, bus));
+                }
+                p.move_to(Point::new(left - 4.0, bus));
+                p.line_to(Point::new(right + 4.0, bus));
+            });
+            frame.stroke(&path, Stroke::default().with_width(1.5));
+        }
+
+        // Modulations, with an arrow head at the target
+        for target in 0..8 {
+            for source in 0..8 {
+                let amount = self.amounts[target][source];
+                if amount <= 0.0 {
+                    continue;
+                }
+                let stroke = Stroke::default()
+                    .with_color(mode_color(self.modes[target][source]))
+                    .with_width(1.0 + amount * 2.0);
+                if source == target {
+                    let center = positions[target] + Vector::new(NODE_RADIUS, -NODE_RADIUS);
+                    frame.stroke(&Path::circle(center, NODE_RADIUS * 0.6), stroke);
+                    continue;
+                }
+                let (from, to) = (positions[source], positions[target]);
+                let length = from.distance(to);
+                let direction = Vector::new((to.x - from.x) / length, (to.y - from.y) / length);
+                let start = from + direction * NODE_RADIUS;
+                let end = to + direction * -NODE_RADIUS;
+                let side = Vector::new(-direction.y, direction.x) * 3.0;
+                let back = end + direction * -5.0;
+                let path = Path::new(|p| {
+                    p.move_to(start);
+                    p.line_to(end);
+                    p.move_to(back + side);
+                    p.line_to(end);
+                    p.line_to(back + side * -1.0);
+                });
+                frame.stroke(&path, stroke);
+            }
+        }
+
+        // Connection being dragged
+        if let (Some(source), Some(cursor)) = (self.state.dragging, self.state.cursor) {
+            let path = Path::line(positions[source], cursor);
+            frame.stroke(
+                &path,
+                Stroke::default().with_color(Color::from_rgb8(150, 150, 150)),
+            );
+        }
+
+        for (operator, position) in positions.iter().enumerate() {
+            let color = if self.is_used(operator) {
+                Color::BLACK
+            } else {
+                Color::from_rgb8(180, 180, 180)
+            };
+            let node = Path::circle(*position, NODE_RADIUS);
+            frame.fill(&node, Color::from_rgb8(250, 250, 250));
+            frame.stroke(&node, Stroke::default().with_color(color));
+            frame.fill_text(canvas::Text {
+                content: (operator + 1).to_string(),
+                position: *position,
+                color,
+                size: 12.0,
+                horizontal_alignment: alignment::Horizontal::Center,
+                vertical_alignment: alignment::Vertical::Center,
+                ..Default::default()
+            });
+        }
+
+        vec![frame.into_geometry()]
+    }
+
+    fn mouse_interaction(&self, bounds: Rectangle, cursor: Cursor) -> mouse::Interaction {
+        let hovered = cursor
+            .position_in(&bounds)
+            .and_then(|p| self.node_at(bounds.size(), p));
+        if self.state.dragging.is_some() || hovered.is_some() {
+            mouse::Interaction::Pointer
+        } else {
+            mouse::Interaction::default()
+        }
+    }
+}
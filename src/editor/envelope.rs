@@ -0,0 +1,279 @@
+//! The DAHDSR envelope display, which can also be edited by dragging its breakpoints.
+
+use nih_plug::prelude::{FloatParam, Param};
+use nih_plug_iced::canvas::{self, event, Cursor, Event, Fill, Frame, Geometry, Path, Stroke};
+use nih_plug_iced::widgets::ParamMessage;
+use nih_plug_iced::{mouse, Color, Point, Rectangle, Size};
+
+use super::Message;
+
+const X_OFFSET: f32 = 2.0;
+const X_MARGIN: f32 = 0.9;
+const Y_OFFSET: f32 = 2.0;
+const Y_MARGIN: f32 = 0.8;
+/// Length the sustain stage is drawn with, in seconds.
+const SUSTAIN_LENGTH: f32 = 1.0;
+/// How far from a breakpoint a click still grabs it.
+const GRAB_RADIUS: f32 = 5.0;
+
+/// The parameters shown by an [`EnvelopeEditor`]. Stages an envelope doesn't have are left out
+/// and drawn with a length or level of 0.
+pub struct EnvelopeParams<'a> {
+    pub delay: Option<&'a FloatParam>,
+    pub attack: &'a FloatParam,
+    pub attack_level: Option<&'a FloatParam>,
+    pub hold: Option<&'a FloatParam>,
+    pub decay: &'a FloatParam,
+    pub sustain: &'a FloatParam,
+    pub release: &'a FloatParam,
+    pub release_level: Option<&'a FloatParam>,
+}
+impl<'a> EnvelopeParams<'a> {
+    /// The parameters moved by dragging each breakpoint, horizontally (a stage's length in
+    /// seconds) and vertically (a level).
+    fn handles(&self) -> [(Option<&'a FloatParam>, Option<&'a FloatParam>); 7] {
+        [
+            (None, self.attack_level),
+            (self.delay, None),
+            (Some(self.attack), None),
+            (self.hold, None),
+            (Some(self.decay), Some(self.sustain)),
+            (None, Some(self.sustain)),
+            (Some(self.release), self.release_level),
+        ]
+    }
+}
+
+/// A breakpoint being dragged.
+#[derive(Debug, Clone, Copy)]
+struct Drag {
+    handle: usize,
+    origin: Point,
+    /// Value of the horizontal parameter in seconds when the drag started.
+    time: f32,
+    /// Normalized value of the vertical parameter when the drag started.
+    level: f32,
+    /// Scale of the envelope when the drag started, so it doesn't change while dragging.
+    seconds_per_pixel: f32,
+}
+
+/// Interaction state for [`EnvelopeEditor`], which has to outlive the widget itself.
+#[derive(Debug, Default)]
+pub struct State {
+    dragging: Option<Drag>,
+}
+
+/// Draws an envelope. Dragging a breakpoint sideways changes the length of the stage it ends and
+/// dragging it up or down changes its level, with the edit reported to the host as one gesture.
+pub struct EnvelopeEditor<'a> {
+    state: &'a mut State,
+    params: EnvelopeParams<'a>,
+}
+impl<'a> EnvelopeEditor<'a> {
+    pub fn new(state: &'a mut State, params: EnvelopeParams<'a>) -> Self {
+        Self { state, params }
+    }
+
+    /// Lengths of the delay, attack, hold, decay, sustain and release stages in seconds.
+    fn lengths(&self) -> [f32; 6] {
+        let value = |param: Option<&FloatParam>| param.map_or(0.0, |param| param.value());
+        [
+            value(self.params.delay),
+            self.params.attack.value(),
+            value(self.params.hold),
+            self.params.decay.value(),
+            SUSTAIN_LENGTH,
+            self.params.release.value(),
+        ]
+    }
+
+    fn seconds_per_pixel(&self, size: Size) -> f32 {
+        self.lengths().iter().sum::<f32>() / (size.width * X_MARGIN)
+    }
+
+    fn points(&self, size: Size) -> [Point; 7] {
+        let level = |param: Option<&FloatParam>| param.map_or(0.0, |param| param.value());
+        let y = |level: f32| Y_OFFSET + size.height * (Y_MARGIN - level * Y_MARGIN);
+        let levels = [
+            level(self.params.attack_level),
+            level(self.params.attack_level),
+            1.0,
+            1.0,
+            self.params.sustain.value(),
+            self.params.sustain.value(),
+            level(self.params.release_level),
+        ];
+        let seconds_per_pixel = self.seconds_per_pixel(size);
+        let mut time = 0.0;
+        let mut points = [Point::ORIGIN; 7];
+        for (i, point) in points.iter_mut().enumerate() {
+            if i > 0 {
+                time += self.lengths()[i - 1];
+            }
+            *point = Point::new(X_OFFSET + time / seconds_per_pixel, y(levels[i]));
+        }
+        points
+    }
+
+    /// The breakpoint under the cursor that moves at least one parameter.
+    fn handle_at(&self, size: Size, position: Point) -> Option<usize> {
+        let handles = self.params.handles();
+        self.points(size)
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| handles[*i].0.is_some() || handles[*i].1.is_some())
+            .map(|(i, point)| (i, point.distance(position)))
+            .filter(|(_, distance)| *distance <= GRAB_RADIUS)
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(i, _)| i)
+    }
+
+    /// Applies `message` to every parameter moved by the handle.
+    fn gesture(&self, handle: usize, message: impl Fn(&FloatParam) -> ParamMessage) -> Message {
+        let (time, level) = self.params.handles()[handle];
+        Message::ParamUpdates(time.into_iter().chain(level).map(message).collect())
+    }
+}
+
+impl<'a> canvas::Program<Message> for EnvelopeEditor<'a> {
+    fn update(
+        &mut self,
+        event: Event,
+        bounds: Rectangle,
+        cursor: Cursor,
+    ) -> (event::Status, Option<Message>) {
+        let position = cursor.position_in(&bounds);
+        match event {
+            Event::Mouse(mouse::Event::ButtonPressed(mouse::Button::Left)) => {
+                let handle = position.and_then(|p| self.handle_at(bounds.size(), p));
+                if let (Some(handle), Some(origin)) = (handle, position) {
+                    let (time, level) = self.params.handles()[handle];
+                    self.state.dragging = Some(Drag {
+                        handle,
+                        origin,
+                        time: time.map_or(0.0, |param| param.value()),
+                        level: level.map_or(0.0, |param| param.modulated_normalized_value()),
+                        seconds_per_pixel: self.seconds_per_pixel(bounds.size()),
+                    });
+                    let message = self.gesture(handle, |param| {
+                        ParamMessage::BeginSetParameter(param.as_ptr())
+                    });
+                    return (event::Status::Captured, Some(message));
+                }
+            }
+            Event::Mouse(mouse::Event::CursorMoved { .. }) => {
+                if let Some(drag) = self.state.dragging {
+                    // The cursor can leave the canvas while dragging
+                    let position = cursor
+                        .position()
+                        .map_or(drag.origin, |p| Point::new(p.x - bounds.x, p.y - bounds.y));
+                    let (time_param, _) = self.params.handles()[drag.handle];
+                    let time = (drag.time + (position.x - drag.origin.x) * drag.seconds_per_pixel)
+                        .max(0.0);
+                    let level = (drag.level
+                        - (position.y - drag.origin.y) / (bounds.height * Y_MARGIN))
+                        .clamp(0.0, 1.0);
+                    let message = self.gesture(drag.handle, |param| {
+                        let normalized = match time_param {
+                            Some(time_param) if std::ptr::eq(time_param, param) => {
+                                param.preview_normalized(time)
+                            }
+                            _ => level,
+                        };
+                        ParamMessage::SetParameterNormalized(param.as_ptr(), normalized)
+                    });
+                    return (event::Status::Captured, Some(message));
+                }
+            }
+            Event::Mouse(mouse::Event::ButtonReleased(mouse::Button::Left)) => {
+                if let Some(drag) = self.state.dragging.take() {
+                    let message = self.gesture(drag.handle, |param| {
+                        ParamMessage::EndSetParameter(param.as_ptr())
+                    });
+                    return (event::Status::Captured, Some(message));
+                }
+            }
+            _ => {}
+        }
+        (event::Status::Ignored, None)
+    }
+
+    fn draw(&self, bounds: Rectangle, _cursor: Cursor) -> Vec<Geometry> {
+        let mut frame = Frame::new(bounds.size());
+        let points = self.points(bounds.size());
+        let bottom = Y_OFFSET + bounds.height * Y_MARGIN;
+        let top = Y_OFFSET;
+
+        // A mark every half second
+        let seconds_per_pixel = self.seconds_per_pixel(bounds.size());
+        let marks = (self.lengths().iter().sum::<f32>() / 0.5) as usize;
+        let time_path = Path::new(|p| {
+            for i in 0..=marks {
+                let x = X_OFFSET + i as f32 * 0.5 / seconds_per_pixel;
+                p.move_to(Point::new(x, top));
+                p.line_to(Point::new(x, bottom));
+            }
+        });
+        let bg_path = Path::new(|p| {
+            p.move_to(points[4]);
+            p.line_to(Point::new(points[4].x, bottom));
+            p.move_to(points[5]);
+            p.line_to(Point::new(points[5].x, bottom));
+        });
+        let line_path = Path::new(|p| {
+            p.move_to(points[0]);
+            for point in points.iter() {
+                p.line_to(*point);
+            }
+        });
+        let fill_path = Path::new(|p| {
+            p.move_to(points[0]);
+            for point in points.iter() {
+                p.line_to(*point);
+            }
+            p.line_to(Point::new(points[6].x, bottom));
+            p.line_to(Point::new(X_OFFSET, bottom));
+            p.close();
+        });
+
+        frame.stroke(
+            &time_path,
+            Stroke::default().with_color(Color::from_rgba8(0, 0, 0, 0.3)),
+        );
+        frame.fill(
+            &fill_path,
+            Fill {
+                color: Color::from_rgb8(230, 230, 230),
+                rule: canvas::FillRule::NonZero,
+            },
+        );
+        frame.stroke(
+            &bg_path,
+            Stroke::default().with_color(Color::from_rgb8(150, 150, 150)),
+        );
+        frame.stroke(&line_path, Stroke::default());
+
+        let dragging = self.state.dragging.map(|drag| drag.handle);
+        for (i, point) in points.iter().enumerate() {
+            let radius = if dragging == Some(i) { 3.0 } else { 2.0 };
+            let corner = Point::new(point.x - radius, point.y - radius);
+            frame.fill_rectangle(corner, Size::new(radius * 2.0, radius * 2.0), Color::BLACK);
+        }
+
+        vec![frame.into_geometry()]
+    }
+
+    fn mouse_interaction(&self, bounds: Rectangle, cursor: Cursor) -> mouse::Interaction {
+        if self.state.dragging.is_some() {
+            return mouse::Interaction::Grabbing;
+        }
+        let hovered = cursor
+            .position_in(&bounds)
+            .and_then(|p| self.handle_at(bounds.size(), p));
+        if hovered.is_some() {
+            mouse::Interaction::Grab
+        } else {
+            mouse::Interaction::default()
+        }
+    }
+}
@@ -0,0 +1,219 @@
+//! Undo and redo for the parameter changes made in the editor, for hosts that don't undo plugin
+//! parameters themselves.
+
+use nih_plug::prelude::ParamPtr;
+
+/// Edits kept for undoing, the oldest are dropped first.
+const MAX_EDITS: usize = 200;
+
+/// Moves mappings' parameter IDs along with a swap. `moves` pairs each parameter's ID with the
+/// ID of the one whose value it takes, so swapping the same oscillators again moves them back.
+pub fn move_ids<'a>(ids: impl IntoIterator<Item = &'a mut String>, moves: &[(String, String)]) {
+    for id in ids {
+        if let Some((to, _)) = moves.iter().find(|(_, from)| from == id) {
+            *id = to.clone();
+        }
+    }
+}
+
+/// A parameter's normalized value before and after an edit.
+#[derive(Clone, Copy)]
+struct Change {
+    param: ParamPtr,
+    before: f32,
+    after: f32,
+}
+
+/// Everything set from the first of a group of overlapping gestures starting until the last one
+/// ends, undone as one step.
+#[derive(Default)]
+struct Edit {
+    changes: Vec<Change>,
+    /// Oscillators that traded places. Their wavetables and mappings aren't parameters, so undoing
+    /// or redoing the edit swaps those again.
+    swap: Option<(usize, usize)>,
+}
+
+/// What undoing or redoing an edit takes.
+pub struct Step {
+    /// Normalized parameter values to set.
+    pub values: Vec<(ParamPtr, f32)>,
+    /// Oscillators whose wavetables and mappings need swapping.
+    pub swap: Option<(usize, usize)>,
+}
+
+#[derive(Default)]
+pub struct History {
+    undo: Vec<Edit>,
+    redo: Vec<Edit>,
+    /// The edit being recorded and how many of its gestures are still open.
+    current: Option<(Edit, usize)>,
+}
+impl History {
+    pub fn begin(&mut self) {
+        self.current.get_or_insert_with(Default::default).1 += 1;
+    }
+    /// Records a parameter being set from `before` to `after`. Outside of a gesture the change
+    /// is an edit of its own.
+    pub fn set(&mut self, param: ParamPtr, before: f32, after: f32) {
+        match &mut self.current {
+            Some((edit, _)) => match edit.changes.iter_mut().find(|change| change.param == param) {
+                Some(change) => change.after = after,
+                None => edit.changes.push(Change {
+                    param,
+                    before,
+                    after,
+                }),
+            },
+            None => self.push(Edit {
+                changes: vec![Change {
+                    param,
+                    before,
+                    after,
+                }],
+                swap: None,
+            }),
+        }
+    }
+    pub fn end(&mut self) {
+        if let Some((_, open)) = &mut self.current {
+            *open = open.saturating_sub(1);
+            if *open == 0 {
+                if let Some((edit, _)) = self.current.take() {
+                    self.push(edit);
+                }
+            }
+        }
+    }
+    /// Records several parameters being set at once, given as `(param, before, after)`.
+    pub fn record(&mut self, changes: impl IntoIterator<Item = (ParamPtr, f32, f32)>) {
+        self.begin();
+        for (param, before, after) in changes {
+            self.set(param, before, after);
+        }
+        self.end();
+    }
+    /// Like [`History::record()`], for swapping oscillators `a` and `b`.
+    pub fn record_swap(
+        &mut self,
+        changes: impl IntoIterator<Item = (ParamPtr, f32, f32)>,
+        a: usize,
+        b: usize,
+    ) {
+        self.begin();
+        for (param, before, after) in changes {
+            self.set(param, before, after);
+        }
+        if let Some((edit, _)) = &mut self.current {
+            edit.swap = Some((a, b));
+        }
+        self.end();
+    }
+
+    pub fn can_undo(&self) -> bool {
+        self.current.is_none() && !self.undo.is_empty()
+    }
+    pub fn can_redo(&self) -> bool {
+        self.current.is_none() && !self.redo.is_empty()
+    }
+    /// What takes back the last edit. Nothing is undone while a gesture is open.
+    pub fn undo(&mut self) -> Option<Step> {
+        if !self.can_undo() {
+            return None;
+        }
+        let edit = self.undo.pop()?;
+        let step = Step {
+            values: edit
+                .changes
+                .iter()
+                .rev()
+                .map(|change| (change.param, change.before))
+                .collect(),
+            swap: edit.swap,
+        };
+        self.redo.push(edit);
+        Some(step)
+    }
+    /// What makes the last undone edit again.
+    pub fn redo(&mut self) -> Option<Step> {
+        if !self.can_redo() {
+            return None;
+        }
+        let edit = self.redo.pop()?;
+        let step = Step {
+            values: edit
+                .changes
+                .iter()
+                .map(|change| (change.param, change.after))
+                .collect(),
+            swap: edit.swap,
+        };
+        self.undo.push(edit);
+        Some(step)
+    }
+
+    fn push(&mut self, mut edit: Edit) {
+        edit.changes.retain(|change| change.before != change.after);
+        if edit.changes.is_empty() && edit.swap.is_none() {
+            return;
+        }
+        self.undo.push(edit);
+        if self.undo.len() > MAX_EDITS {
+            self.undo.remove(0);
+        }
+        self.redo.clear();
+    }
+}
+
+mod test {
+    use super::*;
+    use crate::midi_learn::CcMapping;
+    use nih_plug::prelude::{FloatParam, FloatRange, Param};
+
+    #[test]
+    fn test_swap() {
+        let range = || FloatRange::Linear { min: 0.0, max: 1.0 };
+        let amp_1 = FloatParam::new("Osc1 Amp", 1.0, range());
+        let amp_2 = FloatParam::new("Osc2 Amp", 0.0, range());
+        let moves = [
+            ("osc1_amp".to_string(), "osc2_amp".to_string()),
+            ("osc2_amp".to_string(), "osc1_amp".to_string()),
+        ];
+        let mut mappings = vec![
+            CcMapping::new(1, "osc1_amp".to_string()),
+            CcMapping::new(2, "gain".to_string()),
+        ];
+        let ids = |mappings: &[CcMapping]| {
+            mappings
+                .iter()
+                .map(|mapping| mapping.param.clone())
+                .collect::<Vec<_>>()
+        };
+
+        let mut history = History::default();
+        history.record_swap(
+            [(amp_1.as_ptr(), 1.0, 0.0), (amp_2.as_ptr(), 0.0, 1.0)],
+            0,
+            1,
+        );
+        move_ids(
+            mappings.iter_mut().map(|mapping| &mut mapping.param),
+            &moves,
+        );
+        assert_eq!(ids(&mappings), ["osc2_amp", "gain"]);
+
+        // Undoing sets the values back and swaps again, which moves the mappings back
+        let step = history.undo().unwrap();
+        assert_eq!(step.swap, Some((0, 1)));
+        assert_eq!(step.values, [(amp_2.as_ptr(), 0.0), (amp_1.as_ptr(), 1.0)]);
+        move_ids(
+            mappings.iter_mut().map(|mapping| &mut mapping.param),
+            &moves,
+        );
+        assert_eq!(ids(&mappings), ["osc1_amp", "gain"]);
+
+        let step = history.redo().unwrap();
+        assert_eq!(step.swap, Some((0, 1)));
+        assert_eq!(step.values, [(amp_1.as_ptr(), 0.0), (amp_2.as_ptr(), 1.0)]);
+    }
+}
@@ -20,8 +20,10 @@
 //! A slider that integrates with NIH-plug's [`Param`] types.
 
 use atomic_refcell::AtomicRefCell;
-use nih_plug::prelude::Param;
+use nih_plug::prelude::{Param, ParamPtr};
 use std::borrow::Borrow;
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicU64, Ordering};
 
 use nih_plug_iced::backend::widget;
 use nih_plug_iced::backend::Renderer;
@@ -39,13 +41,61 @@ use nih_plug_iced::widgets::{util, ParamMessage};
 const GRANULAR_DRAG_MULTIPLIER: f32 = 0.2;
 const GRANULAR_DRAG_MULTIPLIER_SHIFT: f32 = 0.05;
 
+/// Scrolling this many pixels on a touchpad moves the parameter by one step, like one notch of a
+/// mouse wheel.
+const PIXELS_PER_STEP: f32 = 20.0;
+
 /// The thickness of this widget's borders.
 const BORDER_WIDTH: f32 = 1.0;
 
+/// Used to tell the sliders apart for keyboard focus.
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+thread_local! {
+    /// Keyboard focus, shared by every slider since a slider can only change its own state.
+    static FOCUS: RefCell<Focus> = RefCell::default();
+    /// The parameters waiting for a MIDI controller after their slider was right-clicked, in the
+    /// order they were clicked. Every plugin instance's editor runs on this thread, so each editor
+    /// only looks at the parameters it `owns` and maps the next controller that moves onto the
+    /// last of them.
+    static LEARNING: RefCell<Vec<ParamPtr>> = RefCell::default();
+}
+
+/// The parameter that MIDI learn is waiting on among the ones an editor `owns`, if any.
+pub fn learning(owns: impl Fn(ParamPtr) -> bool) -> Option<ParamPtr> {
+    LEARNING.with(|learning| learning.borrow().iter().rev().copied().find(|&param| owns(param)))
+}
+
+/// Stops MIDI learn for the parameters an editor `owns`, leaving other editors' alone.
+pub fn stop_learning(owns: impl Fn(ParamPtr) -> bool) {
+    LEARNING.with(|learning| learning.borrow_mut().retain(|&param| !owns(param)));
+}
+
+/// Restarts MIDI learn on `param` alone, among the parameters an editor `owns`.
+pub fn start_learning(param: ParamPtr, owns: impl Fn(ParamPtr) -> bool) {
+    stop_learning(owns);
+    LEARNING.with(|learning| learning.borrow_mut().push(param));
+}
+
+fn is_learning(param: ParamPtr) -> bool {
+    LEARNING.with(|learning| learning.borrow().contains(&param))
+}
+
+#[derive(Debug, Default)]
+struct Focus {
+    /// The [`State::id`] of the slider with keyboard focus.
+    focused: Option<u64>,
+    /// The last slider to see a Tab press, which is the one before the current slider when
+    /// Shift+Tab reaches it. Every slider sees every event in layout order.
+    previous: Option<u64>,
+    /// Set when the focused slider passes focus on with Tab, the next slider to see it takes the
+    /// focus. If the last slider passes it on, the first one to be drawn takes it.
+    pass_on: bool,
+}
+
 /// A slider that integrates with NIH-plug's [`Param`] types.
 ///
 /// TODO: There are currently no styling options at all
-/// TODO: Handle scrolling for steps (and shift+scroll for smaller steps?)
 pub struct ParamSlider<'a, P: Param> {
     state: &'a mut State,
 
@@ -58,8 +108,9 @@ pub struct ParamSlider<'a, P: Param> {
 }
 
 /// State for a [`ParamSlider`].
-#[derive(Debug, Default)]
+#[derive(Debug)]
 pub struct State {
+    id: u64,
     keyboard_modifiers: keyboard::Modifiers,
     /// Will be set to `true` if we're dragging the parameter. Resetting the parameter or entering a
     /// text value should not initiate a drag.
@@ -75,6 +126,29 @@ pub struct State {
     /// The text that's currently in the text input. If this is set to `None`, then the text input
     /// is not visible.
     text_input_value: Option<String>,
+    /// Scrolling that hasn't added up to a whole step yet, in steps.
+    scroll_steps: f32,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        Self {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            keyboard_modifiers: Default::default(),
+            drag_active: false,
+            granular_drag_start_x_value: None,
+            last_click: None,
+            text_input_state: Default::default(),
+            text_input_value: None,
+            scroll_steps: 0.0,
+        }
+    }
+}
+
+impl State {
+    fn is_focused(&self) -> bool {
+        FOCUS.with(|focus| focus.borrow().focused == Some(self.id))
+    }
 }
 
 /// An internal message for intercep- I mean handling output from the embedded [`TextInpu`] widget.
@@ -221,6 +295,42 @@ impl<'a, P: Param> ParamSlider<'a, P> {
             ));
         }
     }
+
+    /// Moves the parameter up (positive) or down by a number of steps as a single gesture.
+    /// Stepped parameters move by their own steps, continuous ones by NIH-plug's default step
+    /// size, or a smaller one if `finer` is set.
+    fn step(&self, shell: &mut Shell<'_, ParamMessage>, steps: i32, finer: bool) {
+        let mut value = self.param.modulated_normalized_value();
+        for _ in 0..steps.unsigned_abs() {
+            value = if steps > 0 {
+                self.param.next_normalized_step(value, finer)
+            } else {
+                self.param.previous_normalized_step(value, finer)
+            };
+        }
+        shell.publish(ParamMessage::BeginSetParameter(self.param.as_ptr()));
+        self.set_normalized_value(shell, value);
+        shell.publish(ParamMessage::EndSetParameter(self.param.as_ptr()));
+    }
+
+    /// Moves keyboard focus on Tab and Shift+Tab.
+    fn traverse_focus(&self, backwards: bool) {
+        let id = self.state.id;
+        FOCUS.with(|focus| {
+            let mut focus = focus.borrow_mut();
+            if backwards {
+                if focus.focused == Some(id) && focus.previous.is_some() {
+                    focus.focused = focus.previous;
+                }
+            } else if focus.pass_on || focus.focused.is_none() {
+                focus.focused = Some(id);
+                focus.pass_on = false;
+            } else if focus.focused == Some(id) {
+                focus.pass_on = true;
+            }
+            focus.previous = Some(id);
+        });
+    }
 }
 
 impl<'a, P: Param> Widget<ParamMessage, Renderer> for ParamSlider<'a, P> {
@@ -321,7 +431,19 @@ impl<'a, P: Param> Widget<ParamMessage, Renderer> for ParamSlider<'a, P> {
         match event {
             Event::Mouse(mouse::Event::ButtonPressed(mouse::Button::Left))
             | Event::Touch(touch::Event::FingerPressed { .. }) => {
-                if bounds.contains(cursor_position) {
+                // Clicking a slider gives it keyboard focus, clicking anywhere else takes it away
+                let id = self.state.id;
+                let contains_cursor = bounds.contains(cursor_position);
+                FOCUS.with(|focus| {
+                    let mut focus = focus.borrow_mut();
+                    if contains_cursor {
+                        focus.focused = Some(id);
+                    } else if focus.focused == Some(id) {
+                        focus.focused = None;
+                    }
+                });
+
+                if contains_cursor {
                     let click = mouse::Click::new(cursor_position, self.state.last_click);
                     self.state.last_click = Some(click);
                     if self.state.keyboard_modifiers.alt() {
@@ -366,11 +488,22 @@ impl<'a, P: Param> Widget<ParamMessage, Renderer> for ParamSlider<'a, P> {
                 if bounds.contains(cursor_position) {
                     self.state.drag_active = false;
 
-                    // Changing the parameter happens in the TextInput event handler above
-                    let mut text_input_state = self.state.text_input_state.borrow_mut();
-                    self.state.text_input_value = Some(self.param.to_string());
-                    text_input_state.move_cursor_to_end();
-                    text_input_state.select_all();
+                    // Right-clicking starts MIDI learn, or cancels it when it's already waiting
+                    // on this parameter
+                    let param = self.param.as_ptr();
+                    LEARNING.with(|learning| {
+                        let mut learning = learning.borrow_mut();
+                        match learning.iter().position(|&other| other == param) {
+                            Some(index) => {
+                                learning.remove(index);
+                            }
+                            None => learning.push(param),
+                        }
+                    });
+
+                    // The editor listens for captured right-clicks to pick this up, and stops
+                    // learning the other parameters it owns
+                    return event::Status::Captured;
                 }
             }
             Event::Mouse(mouse::Event::ButtonReleased(mouse::Button::Left))
@@ -413,6 +546,45 @@ impl<'a, P: Param> Widget<ParamMessage, Renderer> for ParamSlider<'a, P> {
                     return event::Status::Captured;
                 }
             }
+            Event::Mouse(mouse::Event::WheelScrolled { delta }) => {
+                if bounds.contains(cursor_position) {
+                    self.state.scroll_steps += match delta {
+                        mouse::ScrollDelta::Lines { y, .. } => y,
+                        mouse::ScrollDelta::Pixels { y, .. } => y / PIXELS_PER_STEP,
+                    };
+                    let steps = self.state.scroll_steps.trunc();
+                    self.state.scroll_steps -= steps;
+                    if steps != 0.0 {
+                        self.step(shell, steps as i32, self.state.keyboard_modifiers.shift());
+                    }
+
+                    // Otherwise the editor would scroll as well
+                    return event::Status::Captured;
+                }
+            }
+            Event::Keyboard(keyboard::Event::KeyPressed {
+                key_code,
+                modifiers,
+            }) => {
+                if key_code == keyboard::KeyCode::Tab {
+                    self.traverse_focus(modifiers.shift());
+                } else if self.state.is_focused() {
+                    let steps = match key_code {
+                        keyboard::KeyCode::Up | keyboard::KeyCode::Right => 1,
+                        keyboard::KeyCode::Down | keyboard::KeyCode::Left => -1,
+                        keyboard::KeyCode::PageUp => 10,
+                        keyboard::KeyCode::PageDown => -10,
+                        keyboard::KeyCode::Escape => {
+                            FOCUS.with(|focus| focus.borrow_mut().focused = None);
+                            return event::Status::Captured;
+                        }
+                        _ => return event::Status::Ignored,
+                    };
+                    self.step(shell, steps, modifiers.shift());
+
+                    return event::Status::Captured;
+                }
+            }
             Event::Keyboard(keyboard::Event::ModifiersChanged(modifiers)) => {
                 self.state.keyboard_modifiers = modifiers;
 
@@ -474,19 +646,39 @@ impl<'a, P: Param> Widget<ParamMessage, Renderer> for ParamSlider<'a, P> {
         };
         let is_mouse_over = bounds.contains(cursor_position);
 
+        // Focus that was passed on by the last slider wraps around to the first one
+        let id = self.state.id;
+        let is_focused = FOCUS.with(|focus| {
+            let mut focus = focus.borrow_mut();
+            if focus.pass_on {
+                focus.focused = Some(id);
+                focus.pass_on = false;
+            }
+            focus.focused == Some(id)
+        });
+
+        let is_learning = is_learning(self.param.as_ptr());
+
         // The bar itself, show a different background color when the value is being edited or when
         // the mouse is hovering over it to indicate that it's interactive
-        let background_color =
-            if is_mouse_over || self.state.drag_active || self.state.text_input_value.is_some() {
-                Color::new(0.5, 0.5, 0.5, 0.1)
-            } else {
-                Color::TRANSPARENT
-            };
+        let background_color = if is_learning {
+            Color::from_rgba8(230, 140, 30, 0.2)
+        } else if is_mouse_over || self.state.drag_active || self.state.text_input_value.is_some() {
+            Color::new(0.5, 0.5, 0.5, 0.1)
+        } else {
+            Color::TRANSPARENT
+        };
 
         renderer.fill_quad(
             renderer::Quad {
                 bounds,
-                border_color: Color::BLACK,
+                border_color: if is_learning {
+                    Color::from_rgb8(230, 140, 30)
+                } else if is_focused {
+                    Color::from_rgb8(40, 110, 220)
+                } else {
+                    Color::BLACK
+                },
                 border_width: BORDER_WIDTH,
                 border_radius: 0.0,
             },
@@ -561,7 +753,11 @@ impl<'a, P: Param> Widget<ParamMessage, Renderer> for ParamSlider<'a, P> {
                 display_value.truncate(new_length);
                 display_value.push_str(self.param.unit());
             }
-            let display_string = format!("{}", display_value);
+            let display_string = if is_learning {
+                "Learning".to_string()
+            } else {
+                format!("{}", display_value)
+            };
             let text_size = self.text_size.unwrap_or_else(|| renderer.default_size()) as f32;
             let text_bounds = Rectangle {
                 x: bounds.center_x(),
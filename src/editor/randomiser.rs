@@ -0,0 +1,424 @@
+//! Generates patches by randomising, mutating or breeding the current one. Values are drawn
+//! from ranges that tend to sound musical rather than from each parameter's full range.
+
+use nih_plug::prelude::{Enum, FloatParam, Param, ParamPtr};
+
+use crate::parameters::{OscillatorParams, SynthPluginParams};
+use crate::voice::{MatrixMode, Phaseshaper, Waveform, Waveshaper};
+
+/// Parts of a patch that can be locked, so the randomiser leaves them alone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Group {
+    /// Tuning and frequency ratios.
+    Pitch,
+    /// Every envelope, including the pitch and filter envelopes' times.
+    Envelopes,
+    /// Oscillator output levels, velocity and key scaling.
+    Levels,
+    /// Waveforms, shapers and feedback.
+    Timbre,
+    Matrix,
+    Filter,
+}
+impl Group {
+    pub const ALL: [Group; 6] = [
+        Group::Pitch,
+        Group::Envelopes,
+        Group::Levels,
+        Group::Timbre,
+        Group::Matrix,
+        Group::Filter,
+    ];
+    pub fn name(&self) -> &'static str {
+        match self {
+            Group::Pitch => "Pitch",
+            Group::Envelopes => "Envelopes",
+            Group::Levels => "Levels",
+            Group::Timbre => "Timbre",
+            Group::Matrix => "Matrix",
+            Group::Filter => "Filter",
+        }
+    }
+}
+
+/// The normalized values the randomiser picks from for one parameter.
+#[derive(Debug, Clone)]
+enum Range {
+    Continuous {
+        min: f32,
+        max: f32,
+    },
+    /// One of a few values. A value listed more than once is picked more often.
+    Choices(Vec<f32>),
+    /// Zero most of the time, otherwise anywhere up to `max`. Keeps the matrix from filling up.
+    Sparse {
+        chance: f32,
+        max: f32,
+    },
+}
+impl Range {
+    fn full<P: Param>(param: &P) -> Self {
+        match param.step_count() {
+            Some(steps) => Range::Choices(
+                (0..=steps)
+                    .map(|step| step as f32 / steps.max(1) as f32)
+                    .collect(),
+            ),
+            None => Range::Continuous { min: 0.0, max: 1.0 },
+        }
+    }
+    fn between(param: &FloatParam, min: f32, max: f32) -> Self {
+        Range::Continuous {
+            min: param.preview_normalized(min),
+            max: param.preview_normalized(max),
+        }
+    }
+    fn choices<P: Param>(param: &P, values: impl IntoIterator<Item = P::Plain>) -> Self {
+        Range::Choices(
+            values
+                .into_iter()
+                .map(|value| param.preview_normalized(value))
+                .collect(),
+        )
+    }
+    fn sparse(param: &FloatParam, chance: f32, max: f32) -> Self {
+        Range::Sparse {
+            chance,
+            max: param.preview_normalized(max),
+        }
+    }
+
+    fn sample(&self) -> f32 {
+        match self {
+            Range::Continuous { min, max } => min + (max - min) * fastrand::f32(),
+            Range::Choices(values) => values[fastrand::usize(..values.len())],
+            Range::Sparse { chance, max } => {
+                if fastrand::f32() < *chance {
+                    max * fastrand::f32()
+                } else {
+                    0.0
+                }
+            }
+        }
+    }
+}
+
+/// A parameter the randomiser can change.
+pub struct Gene {
+    pub param: ParamPtr,
+    pub group: Group,
+    range: Range,
+}
+impl Gene {
+    /// Moves `value` towards a random one by `amount`, where 1 replaces it entirely. Stepped
+    /// parameters can't move part of the way, so they change with a chance of `amount` instead.
+    pub fn mutate(&self, value: f32, amount: f32) -> f32 {
+        match self.range {
+            Range::Choices(_) if fastrand::f32() >= amount => value,
+            Range::Choices(_) => self.range.sample(),
+            _ => value + (self.range.sample() - value) * amount,
+        }
+    }
+    /// Crosses the values of two patches, `amount` of the way from `a` to `b`. Stepped
+    /// parameters take `b`'s value with a chance of `amount`.
+    pub fn breed(&self, a: f32, b: f32, amount: f32) -> f32 {
+        match self.range {
+            Range::Choices(_) if fastrand::f32() < amount => b,
+            Range::Choices(_) => a,
+            _ => a + (b - a) * amount,
+        }
+    }
+}
+
+/// Collects genes that share a group.
+struct Genes<'a> {
+    genes: &'a mut Vec<Gene>,
+    group: Group,
+}
+impl<'a> Genes<'a> {
+    fn add(&mut self, param: &impl Param, range: Range) {
+        self.genes.push(Gene {
+            param: param.as_ptr(),
+            group: self.group,
+            range,
+        });
+    }
+}
+
+/// Every parameter of the patch the randomiser changes. Settings that aren't part of the sound,
+/// like the voice count, outputs and macros, are left out, as is the fixed frequency mode and
+/// the sidechain input which only make sense when picked deliberately.
+pub fn genes(params: &SynthPluginParams) -> Vec<Gene> {
+    let mut genes = Vec::new();
+    for osc in params.oscillators() {
+        genes.extend(oscillator_genes(osc));
+    }
+
+    let mut matrix = Genes {
+        genes: &mut genes,
+        group: Group::Matrix,
+    };
+    for (target, fm_mod) in params.fm_mods().into_iter().enumerate() {
+        for (source, (amount, mode)) in fm_mod
+            .amount_params()
+            .into_iter()
+            .zip(fm_mod.mode_params())
+            .enumerate()
+        {
+            let chance = if source == target { 0.1 } else { 0.2 };
+            matrix.add(amount, Range::sparse(amount, chance, 0.6));
+            matrix.add(
+                mode,
+                Range::choices(
+                    mode,
+                    [MatrixMode::Phase; 6].into_iter().chain([
+                        MatrixMode::LinearFm,
+                        MatrixMode::Sync,
+                        MatrixMode::Ring,
+                    ]),
+                ),
+            );
+        }
+    }
+
+    let mut envelopes = Genes {
+        genes: &mut genes,
+        group: Group::Envelopes,
+    };
+    envelopes.add(
+        &params.global_attack,
+        Range::between(&params.global_attack, 0.0, 0.5),
+    );
+    envelopes.add(
+        &params.global_decay,
+        Range::between(&params.global_decay, 0.1, 4.0),
+    );
+    envelopes.add(&params.global_sustain, Range::full(&params.global_sustain));
+    envelopes.add(
+        &params.global_release,
+        Range::between(&params.global_release, 0.05, 2.0),
+    );
+    for curve in [
+        &params.global_attack_curve,
+        &params.global_decay_curve,
+        &params.global_release_curve,
+    ] {
+        envelopes.add(curve, Range::between(curve, 0.5, 4.0));
+    }
+
+    let mut filter = Genes {
+        genes: &mut genes,
+        group: Group::Filter,
+    };
+    filter.add(&params.filter_type, Range::full(&params.filter_type));
+    filter.add(
+        &params.filter_cutoff,
+        Range::between(&params.filter_cutoff, 200.0, 22000.0),
+    );
+    filter.add(
+        &params.filter_resonance,
+        Range::between(&params.filter_resonance, 0.0, 0.7),
+    );
+    filter.add(
+        &params.filter_keytrack,
+        Range::full(&params.filter_keytrack),
+    );
+    filter.add(
+        &params.filter_envelope_amount,
+        Range::full(&params.filter_envelope_amount),
+    );
+    filter.add(
+        &params.filter_envelope_attack,
+        Range::between(&params.filter_envelope_attack, 0.0, 1.0),
+    );
+    filter.add(
+        &params.filter_envelope_decay,
+        Range::between(&params.filter_envelope_decay, 0.05, 3.0),
+    );
+    filter.add(
+        &params.filter_envelope_sustain,
+        Range::full(&params.filter_envelope_sustain),
+    );
+    filter.add(
+        &params.filter_envelope_release,
+        Range::between(&params.filter_envelope_release, 0.05, 2.0),
+    );
+
+    genes
+}
+
+/// The parameters of one oscillator the randomiser changes.
+pub fn oscillator_genes(osc: &OscillatorParams) -> Vec<Gene> {
+    let mut genes = Vec::new();
+
+    let mut pitch = Genes {
+        genes: &mut genes,
+        group: Group::Pitch,
+    };
+    pitch.add(
+        &osc.coarse,
+        Range::choices(
+            &osc.coarse,
+            [0.0, 0.0, 0.0, 0.0, 12.0, -12.0, 7.0, 19.0, 24.0],
+        ),
+    );
+    pitch.add(&osc.fine, Range::between(&osc.fine, -10.0, 10.0));
+    // Whole number ratios, favouring the low harmonics
+    pitch.add(
+        &osc.freq_mult,
+        Range::choices(
+            &osc.freq_mult,
+            [
+                1.0, 1.0, 1.0, 2.0, 2.0, 2.0, 3.0, 3.0, 4.0, 4.0, 5.0, 6.0, 7.0, 8.0,
+            ],
+        ),
+    );
+    pitch.add(
+        &osc.freq_div,
+        Range::choices(&osc.freq_div, [1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 2.0, 3.0, 4.0]),
+    );
+    pitch.add(&osc.hz_detune, Range::between(&osc.hz_detune, -2.0, 2.0));
+    pitch.add(
+        &osc.pitch_env_amount,
+        Range::choices(&osc.pitch_env_amount, [0.0, 0.0, 0.0, 12.0, -12.0, 24.0]),
+    );
+
+    let mut envelopes = Genes {
+        genes: &mut genes,
+        group: Group::Envelopes,
+    };
+    envelopes.add(
+        &osc.attack_level,
+        Range::between(&osc.attack_level, 0.0, 0.5),
+    );
+    envelopes.add(
+        &osc.release_level,
+        Range::between(&osc.release_level, 0.0, 0.2),
+    );
+    envelopes.add(&osc.delay, Range::between(&osc.delay, 0.0, 0.2));
+    envelopes.add(&osc.attack, Range::between(&osc.attack, 0.0, 1.5));
+    envelopes.add(&osc.hold, Range::between(&osc.hold, 0.0, 0.5));
+    envelopes.add(&osc.decay, Range::between(&osc.decay, 0.05, 4.0));
+    envelopes.add(&osc.sustain, Range::full(&osc.sustain));
+    envelopes.add(&osc.release, Range::between(&osc.release, 0.05, 3.0));
+    for curve in [
+        &osc.attack_curve,
+        &osc.decay_curve,
+        &osc.release_curve,
+        &osc.pitch_env_attack_curve,
+        &osc.pitch_env_decay_curve,
+        &osc.pitch_env_release_curve,
+    ] {
+        envelopes.add(curve, Range::between(curve, 0.5, 4.0));
+    }
+    for time in osc.multi_stage.time_params() {
+        envelopes.add(time, Range::between(time, 0.0, 1.0));
+    }
+    for level in osc.multi_stage.level_params() {
+        envelopes.add(level, Range::full(level));
+    }
+    envelopes.add(
+        &osc.pitch_env_attack,
+        Range::between(&osc.pitch_env_attack, 0.0, 0.5),
+    );
+    envelopes.add(
+        &osc.pitch_env_decay,
+        Range::between(&osc.pitch_env_decay, 0.01, 1.0),
+    );
+    envelopes.add(
+        &osc.pitch_env_sustain,
+        Range::between(&osc.pitch_env_sustain, 0.0, 0.2),
+    );
+    envelopes.add(
+        &osc.pitch_env_release,
+        Range::between(&osc.pitch_env_release, 0.02, 1.0),
+    );
+
+    let mut levels = Genes {
+        genes: &mut genes,
+        group: Group::Levels,
+    };
+    levels.add(&osc.amp, Range::full(&osc.amp));
+    levels.add(
+        &osc.velocity_sensitivity,
+        Range::between(&osc.velocity_sensitivity, 0.0, 1.0),
+    );
+    levels.add(
+        &osc.velocity_attack,
+        Range::between(&osc.velocity_attack, -0.5, 0.5),
+    );
+    levels.add(
+        &osc.velocity_feedback,
+        Range::between(&osc.velocity_feedback, -0.5, 0.5),
+    );
+    levels.add(&osc.keyscaling, Range::between(&osc.keyscaling, -0.5, 0.5));
+    levels.add(&osc.rate_scaling, Range::full(&osc.rate_scaling));
+    levels.add(&osc.breakpoint, Range::choices(&osc.breakpoint, 36..=84));
+    levels.add(&osc.left_depth, Range::full(&osc.left_depth));
+    levels.add(&osc.left_curve, Range::full(&osc.left_curve));
+    levels.add(&osc.right_depth, Range::full(&osc.right_depth));
+    levels.add(&osc.right_curve, Range::full(&osc.right_curve));
+
+    let mut timbre = Genes {
+        genes: &mut genes,
+        group: Group::Timbre,
+    };
+    timbre.add(&osc.feedback, Range::between(&osc.feedback, -0.5, 0.5));
+    // Mostly sines, and never the wavetable or audio input which depend on what's loaded
+    timbre.add(
+        &osc.waveform,
+        Range::choices(
+            &osc.waveform,
+            [Waveform::Sine; 6].into_iter().chain([
+                Waveform::HalfSine,
+                Waveform::AbsSine,
+                Waveform::QuarterSine,
+                Waveform::AlternatingSine,
+                Waveform::CamelSine,
+                Waveform::Saw,
+                Waveform::Square,
+                Waveform::Triangle,
+                Waveform::Noise,
+            ]),
+        ),
+    );
+    timbre.add(
+        &osc.wavetable_position,
+        Range::full(&osc.wavetable_position),
+    );
+    timbre.add(&osc.noise_type, Range::full(&osc.noise_type));
+    timbre.add(&osc.noise_tone, Range::full(&osc.noise_tone));
+    timbre.add(
+        &osc.waveshaper,
+        Range::choices(
+            &osc.waveshaper,
+            [Waveshaper::None; 3]
+                .into_iter()
+                .chain((0..Waveshaper::variants().len()).map(Waveshaper::from_index)),
+        ),
+    );
+    timbre.add(
+        &osc.waveshaper_amount,
+        Range::between(&osc.waveshaper_amount, 0.0, 50.0),
+    );
+    timbre.add(
+        &osc.phaseshaper,
+        Range::choices(
+            &osc.phaseshaper,
+            [Phaseshaper::None; 3]
+                .into_iter()
+                .chain((0..Phaseshaper::variants().len()).map(Phaseshaper::from_index)),
+        ),
+    );
+    timbre.add(
+        &osc.phaseshaper_amount,
+        Range::between(&osc.phaseshaper_amount, 0.0, 50.0),
+    );
+    timbre.add(&osc.phase_offset, Range::full(&osc.phase_offset));
+    timbre.add(
+        &osc.phase_rand,
+        Range::choices(&osc.phase_rand, [0.0, 0.0, 0.0, 100.0]),
+    );
+
+    genes
+}
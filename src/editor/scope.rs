@@ -0,0 +1,164 @@
+//! A live view of the output or of one oscillator, as a waveform or a spectrum.
+
+use nih_plug::prelude::util;
+use nih_plug_iced::canvas::{self, Cursor, Frame, Geometry, Path, Stroke};
+use nih_plug_iced::{Color, Point, Rectangle};
+use realfft::RealToComplex;
+
+use super::Message;
+
+/// Samples shown in the waveform view.
+const WAVEFORM_LENGTH: usize = 1024;
+const MIN_FREQUENCY: f32 = 20.0;
+const MAX_FREQUENCY: f32 = 20000.0;
+/// Bottom of the spectrum view, the top is 0 dB.
+const MIN_DB: f32 = -96.0;
+
+/// The last `WAVEFORM_LENGTH` samples starting at a rising zero crossing, so periodic signals
+/// stand still. Falls back to the newest samples if there is no crossing.
+fn triggered(samples: &[f32]) -> &[f32] {
+    let length = WAVEFORM_LENGTH.min(samples.len());
+    let latest = samples.len() - length;
+    (1..=latest)
+        .rev()
+        .find(|&i| samples[i - 1] <= 0.0 && samples[i] > 0.0)
+        .map_or(&samples[latest..], |start| &samples[start..start + length])
+}
+
+/// Magnitudes of the samples in dBFS, one per FFT bin, using a Hann window.
+pub fn spectrum(fft: &dyn RealToComplex<f32>, samples: &[f32]) -> Vec<f32> {
+    let length = samples.len();
+    let mut input: Vec<f32> = samples
+        .iter()
+        .enumerate()
+        .map(|(i, sample)| {
+            let window = 0.5 - 0.5 * (std::f32::consts::TAU * i as f32 / length as f32).cos();
+            sample * window
+        })
+        .collect();
+    let mut output = fft.make_output_vec();
+    if fft.process(&mut input, &mut output).is_err() {
+        return Vec::new();
+    }
+    // A full scale sine reads 0 dB, accounting for the window's gain of 0.5
+    let scale = 4.0 / length as f32;
+    output
+        .iter()
+        .map(|bin| util::gain_to_db(bin.norm() * scale))
+        .collect()
+}
+
+/// Draws either the waveform or the spectrum of the samples it's given.
+pub struct Scope<'a> {
+    samples: &'a [f32],
+    /// Spectrum in dB, shown instead of the waveform if there is one.
+    spectrum: Option<&'a [f32]>,
+    sample_rate: f32,
+    trigger: bool,
+}
+impl<'a> Scope<'a> {
+    pub fn waveform(samples: &'a [f32], trigger: bool) -> Self {
+        Self {
+            samples,
+            spectrum: None,
+            sample_rate: 0.0,
+            trigger,
+        }
+    }
+    pub fn spectrum(spectrum: &'a [f32], sample_rate: f32) -> Self {
+        Self {
+            samples: &[],
+            spectrum: Some(spectrum),
+            sample_rate,
+            trigger: false,
+        }
+    }
+
+    fn draw_waveform(&self, frame: &mut Frame, bounds: Rectangle) {
+        let samples = if self.trigger {
+            triggered(self.samples)
+        } else {
+            &self.samples[self.samples.len().saturating_sub(WAVEFORM_LENGTH)..]
+        };
+        let center = bounds.height * 0.5;
+        frame.stroke(
+            &Path::line(Point::new(0.0, center), Point::new(bounds.width, center)),
+            Stroke::default().with_color(Color::from_rgb8(210, 210, 210)),
+        );
+        let step = bounds.width / (samples.len().max(2) - 1) as f32;
+        let path = Path::new(|p| {
+            for (i, sample) in samples.iter().enumerate() {
+                let point = Point::new(
+                    i as f32 * step,
+                    center - sample.clamp(-1.0, 1.0) * center * 0.95,
+                );
+                if i == 0 {
+                    p.move_to(point);
+                } else {
+                    p.line_to(point);
+                }
+            }
+        });
+        frame.stroke(&path, Stroke::default());
+    }
+
+    fn draw_spectrum(&self, frame: &mut Frame, bounds: Rectangle, spectrum: &[f32]) {
+        let octaves = (MAX_FREQUENCY / MIN_FREQUENCY).log2();
+        let x = |frequency: f32| (frequency / MIN_FREQUENCY).log2() / octaves * bounds.width;
+        let y = |db: f32| (db / MIN_DB).clamp(0.0, 1.0) * bounds.height;
+
+        let grid = || Stroke::default().with_color(Color::from_rgb8(210, 210, 210));
+        for frequency in [100.0, 1000.0, 10000.0] {
+            frame.stroke(
+                &Path::line(
+                    Point::new(x(frequency), 0.0),
+                    Point::new(x(frequency), bounds.height),
+                ),
+                grid(),
+            );
+        }
+        for db in [-24.0, -48.0, -72.0] {
+            frame.stroke(
+                &Path::line(Point::new(0.0, y(db)), Point::new(bounds.width, y(db))),
+                grid(),
+            );
+        }
+
+        let bin_width = self.sample_rate / ((spectrum.len().max(2) - 1) * 2) as f32;
+        let path = Path::new(|p| {
+            let mut started = false;
+            for (bin, db) in spectrum.iter().enumerate().skip(1) {
+                let frequency = bin as f32 * bin_width;
+                if frequency < MIN_FREQUENCY {
+                    continue;
+                }
+                if frequency > MAX_FREQUENCY {
+                    break;
+                }
+                let point = Point::new(x(frequency), y(*db));
+                if started {
+                    p.line_to(point);
+                } else {
+                    p.move_to(point);
+                    started = true;
+                }
+            }
+        });
+        frame.stroke(&path, Stroke::default());
+    }
+}
+
+impl canvas::Program<Message> for Scope<'_> {
+    fn draw(&self, bounds: Rectangle, _cursor: Cursor) -> Vec<Geometry> {
+        let mut frame = Frame::new(bounds.size());
+        match self.spectrum {
+            Some(spectrum) => self.draw_spectrum(&mut frame, bounds, spectrum),
+            None => self.draw_waveform(&mut frame, bounds),
+        }
+        frame.stroke(
+            &Path::rectangle(Point::ORIGIN, bounds.size()),
+            Stroke::default(),
+        );
+        vec![frame.into_geometry()]
+    }
+}
@@ -0,0 +1,98 @@
+//! An offline render of a whole voice, so the editor can show the waveform a note actually makes.
+
+use nih_plug::prelude::util;
+use nih_plug_iced::canvas::{self, Cursor, Frame, Geometry, Path, Stroke};
+use nih_plug_iced::{alignment, Color, Point, Rectangle};
+
+use crate::voice::{ModMatrix, OscParamsBatch, Voice, VoiceParams};
+use crate::wavetable::Wavetables;
+
+use super::Message;
+
+/// Sample rate the preview is rendered at.
+pub const SAMPLE_RATE: f32 = 48000.0;
+/// Cycles of the note's fundamental that get drawn.
+const CYCLES: f32 = 3.0;
+
+/// Plays a voice through the same engine code as the plugin, skipping the first `offset`
+/// seconds after the note starts and returning a few cycles from there.
+pub fn render(
+    osc_params: &OscParamsBatch,
+    voice_params: VoiceParams,
+    mod_matrix: &ModMatrix,
+    wavetables: &Wavetables,
+    note: u8,
+    offset: f32,
+) -> Vec<f32> {
+    let skip = (offset * voice_params.sample_rate) as usize;
+    let length =
+        (CYCLES * voice_params.sample_rate / util::midi_note_to_freq(note)).ceil() as usize;
+    let mut voice = Voice::new(note, 0, 0, osc_params, 1.0, voice_params);
+    // The parameters don't change while rendering, so one block covers the whole preview
+    voice.block_update(osc_params, voice_params);
+    (0..skip + length)
+        .filter_map(|i| {
+            voice.sample_update(osc_params, voice_params);
+            let buses = voice.play(osc_params, &voice_params, mod_matrix, wavetables, 0.0);
+            (i >= skip).then(|| buses.iter().sum())
+        })
+        .collect()
+}
+
+/// Draws a rendered voice, scaled to fill the height, with its peak level.
+pub struct VoicePreview<'a> {
+    samples: &'a [f32],
+}
+impl<'a> VoicePreview<'a> {
+    pub fn new(samples: &'a [f32]) -> Self {
+        Self { samples }
+    }
+}
+
+impl<'a> canvas::Program<Message> for VoicePreview<'a> {
+    fn draw(&self, bounds: Rectangle, _cursor: Cursor) -> Vec<Geometry> {
+        let mut frame = Frame::new(bounds.size());
+        let peak = self
+            .samples
+            .iter()
+            .fold(0.0f32, |peak, sample| peak.max(sample.abs()));
+        let center = bounds.height * 0.5;
+        frame.stroke(
+            &Path::line(Point::new(0.0, center), Point::new(bounds.width, center)),
+            Stroke::default().with_color(Color::from_rgb8(210, 210, 210)),
+        );
+        if peak > util::MINUS_INFINITY_GAIN {
+            let scale = center * 0.95 / peak;
+            let step = bounds.width / (self.samples.len().max(2) - 1) as f32;
+            let path = Path::new(|p| {
+                for (i, sample) in self.samples.iter().enumerate() {
+                    let point = Point::new(i as f32 * step, center - sample * scale);
+                    if i == 0 {
+                        p.move_to(point);
+                    } else {
+                        p.line_to(point);
+                    }
+                }
+            });
+            frame.stroke(&path, Stroke::default());
+        }
+        frame.fill_text(canvas::Text {
+            content: if peak > util::MINUS_INFINITY_GAIN {
+                format!("Peak {:.1} dB", util::gain_to_db(peak))
+            } else {
+                "Silent".to_string()
+            },
+            position: Point::new(bounds.width - 4.0, 2.0),
+            color: Color::from_rgb8(120, 120, 120),
+            size: 12.0,
+            horizontal_alignment: alignment::Horizontal::Right,
+            vertical_alignment: alignment::Vertical::Top,
+            ..Default::default()
+        });
+        frame.stroke(
+            &Path::rectangle(Point::ORIGIN, bounds.size()),
+            Stroke::default(),
+        );
+        vec![frame.into_geometry()]
+    }
+}
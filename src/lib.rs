@@ -1,28 +1,72 @@
+use dsp::follower::EnvelopeFollower;
+use macros::MacroOffsets;
+use metering::{Meters, OutputLevels};
+use midi_learn::{CcOverrides, CcValues};
 use nih_plug::prelude::*;
-use parameters::SynthPluginParams;
+use parameters::{SynthPluginParams, SMOOTH_TIME};
+use std::collections::HashMap;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
-use voice::{GlobalParams, OscParams, OscParamsBatch, VoiceList, VoiceParams};
-use wide::f32x8;
+use voice::{GlobalParams, SidechainMode, VoiceList, OUTPUT_BUSES};
+use wavetable::Wavetables;
 
+mod algorithm;
 mod dsp;
 mod editor;
+mod macros;
+mod metering;
+mod midi_learn;
 mod parameters;
 mod svf_simper;
 mod voice;
+mod wavetable;
 
 struct SynthPlugin {
     params: Arc<SynthPluginParams>,
+    /// Every parameter by ID, to look up what the macros and MIDI controllers are mapped to.
+    param_ids: HashMap<String, ParamPtr>,
     sample_rate: f32,
 
     voices: VoiceList,
+    /// The audio thread's copy of the loaded wavetables, taken from the parameters' bank.
+    wavetables: Wavetables,
+    wavetable_version: u64,
+    macro_offsets: MacroOffsets,
+    macro_mappings_version: u64,
+    sidechain_follower: EnvelopeFollower,
+    /// Levels shared with the editor.
+    meters: Arc<Meters>,
+    output_levels: OutputLevels,
+    /// MIDI controllers received, for the editor to apply to the parameters they're mapped to
+    /// while it's open.
+    cc_values: Arc<CcValues>,
+    /// Applies the MIDI controllers while the editor is closed.
+    cc_overrides: CcOverrides,
+    cc_mappings_version: u64,
 }
 
 impl Default for SynthPlugin {
     fn default() -> Self {
+        let params = Arc::new(SynthPluginParams::default());
         Self {
-            params: Arc::new(SynthPluginParams::default()),
+            param_ids: params
+                .param_map()
+                .into_iter()
+                .map(|(id, param, _)| (id, param))
+                .collect(),
+            params,
             sample_rate: 1.0,
             voices: VoiceList::new(),
+            wavetables: Default::default(),
+            wavetable_version: 0,
+            macro_offsets: MacroOffsets::default(),
+            macro_mappings_version: 0,
+            sidechain_follower: EnvelopeFollower::default(),
+            meters: Arc::new(Meters::default()),
+            output_levels: OutputLevels::new(1.0),
+            cc_values: Arc::new(CcValues::default()),
+            cc_overrides: CcOverrides::default(),
+            cc_mappings_version: 0,
         }
     }
 }
@@ -37,9 +81,25 @@ impl Plugin for SynthPlugin {
 
     const AUDIO_IO_LAYOUTS: &'static [AudioIOLayout] = &[
         AudioIOLayout {
-            // This is also the default and can be omitted here
             main_input_channels: None,
             main_output_channels: NonZeroU32::new(2),
+            aux_input_ports: &[new_nonzero_u32(2)],
+            aux_output_ports: &[new_nonzero_u32(2); OUTPUT_BUSES - 1],
+            names: PortNames {
+                aux_inputs: &["Sidechain"],
+                aux_outputs: &["Aux 1", "Aux 2", "Aux 3"],
+                ..PortNames::const_default()
+            },
+            ..AudioIOLayout::const_default()
+        },
+        AudioIOLayout {
+            main_input_channels: None,
+            main_output_channels: NonZeroU32::new(2),
+            aux_input_ports: &[new_nonzero_u32(2)],
+            names: PortNames {
+                aux_inputs: &["Sidechain"],
+                ..PortNames::const_default()
+            },
             ..AudioIOLayout::const_default()
         },
         AudioIOLayout {
@@ -66,7 +126,12 @@ impl Plugin for SynthPlugin {
     }
 
     fn editor(&mut self, _async_executor: AsyncExecutor<Self>) -> Option<Box<dyn Editor>> {
-        editor::create(self.params.clone(), self.params.editor_state.clone())
+        editor::create(
+            self.params.clone(),
+            self.meters.clone(),
+            self.cc_values.clone(),
+            self.params.editor_state.clone(),
+        )
     }
 
     fn initialize(
@@ -79,112 +144,92 @@ impl Plugin for SynthPlugin {
         // The `reset()` function is always called right after this function. You can remove this
         // function if you do not need it.
         self.sample_rate = buffer_config.sample_rate;
+        self.meters.sample_rate.store(self.sample_rate);
+        // Only the sources are saved, so the band-limited tables need to be rebuilt after loading
+        // a state
+        if let Ok(mut wavetables) = self.params.wavetables.write() {
+            wavetables.rebuild();
+        }
+        // Restoring a state replaces the whole bank, so the tables the audio thread still holds
+        // may belong to a bank that's gone. They're dropped here rather than in `process()`.
+        self.wavetables = Default::default();
+        self.wavetable_version = 0;
         true
     }
 
-    fn reset(&mut self) {}
+    fn reset(&mut self) {
+        self.sidechain_follower = EnvelopeFollower::default();
+        self.output_levels = OutputLevels::new(self.sample_rate);
+    }
 
     fn process(
         &mut self,
         buffer: &mut Buffer,
-        _aux: &mut AuxiliaryBuffers,
+        aux: &mut AuxiliaryBuffers,
         context: &mut impl ProcessContext<Self>,
     ) -> ProcessStatus {
         let mut next_event = context.next_event();
         let block_size = buffer.samples() as u32;
-        let osc_params = [
-            self.params.osc1_params.to_osc_params(
-                self.sample_rate,
-                self.params.octave_stretch.value(),
-                self.params.portamento.value(),
-                block_size,
-            ),
-            self.params.osc2_params.to_osc_params(
-                self.sample_rate,
-                self.params.octave_stretch.value(),
-                self.params.portamento.value(),
-                block_size,
-            ),
-            self.params.osc3_params.to_osc_params(
-                self.sample_rate,
-                self.params.octave_stretch.value(),
-                self.params.portamento.value(),
-                block_size,
-            ),
-            self.params.osc4_params.to_osc_params(
-                self.sample_rate,
-                self.params.octave_stretch.value(),
-                self.params.portamento.value(),
-                block_size,
-            ),
-            self.params.osc5_params.to_osc_params(
-                self.sample_rate,
-                self.params.octave_stretch.value(),
-                self.params.portamento.value(),
-                block_size,
-            ),
-            self.params.osc6_params.to_osc_params(
-                self.sample_rate,
-                self.params.octave_stretch.value(),
-                self.params.portamento.value(),
-                block_size,
-            ),
-            self.params.osc7_params.to_osc_params(
-                self.sample_rate,
-                self.params.octave_stretch.value(),
-                self.params.portamento.value(),
-                block_size,
-            ),
-            self.params.osc8_params.to_osc_params(
-                self.sample_rate,
-                self.params.octave_stretch.value(),
-                self.params.portamento.value(),
-                block_size,
-            ),
-        ];
-
-        let mut osc_params = OscParamsBatch::from(osc_params);
-        osc_params.coarse += f32x8::splat(self.params.global_coarse.value());
-
-        let mut pm_matrix = [
-            f32x8::from(self.params.osc1_fm_mod.to_array()),
-            f32x8::from(self.params.osc2_fm_mod.to_array()),
-            f32x8::from(self.params.osc3_fm_mod.to_array()),
-            f32x8::from(self.params.osc4_fm_mod.to_array()),
-            f32x8::from(self.params.osc5_fm_mod.to_array()),
-            f32x8::from(self.params.osc6_fm_mod.to_array()),
-            f32x8::from(self.params.osc7_fm_mod.to_array()),
-            f32x8::from(self.params.osc8_fm_mod.to_array()),
-        ];
-        pm_matrix.iter_mut().for_each(|x| *x = *x * 6.0);
-        let voice_params = VoiceParams {
-            sample_rate: self.sample_rate,
-            filter_enabled: self.params.filter_enabled.value(),
-            filter_type: self.params.filter_type.value(),
-            filter_cutoff: self.params.filter_cutoff.value(),
-            filter_resonance: self.params.filter_resonance.value(),
-            filter_envelope_amount: self.params.filter_envelope_amount.value(),
-            filter_attack: self.params.filter_envelope_attack.value(),
-            filter_decay: self.params.filter_envelope_decay.value(),
-            filter_sustain: self.params.filter_envelope_sustain.value(),
-            filter_release: self.params.filter_envelope_release.value(),
-            filter_keytrack: self.params.filter_keytrack.value(),
-            global_attack: self.params.global_attack.value(),
-            global_decay: self.params.global_decay.value(),
-            global_sustain: self.params.global_sustain.value(),
-            global_release: self.params.global_release.value(),
-        };
+        // Pick up newly loaded wavetables. If the editor holds the lock we'll try again next block
+        if let Ok(wavetables) = self.params.wavetables.try_read() {
+            if wavetables.version != self.wavetable_version {
+                // The bank keeps the replaced tables alive until it's told they aren't used here
+                // anymore, so this never frees one
+                self.wavetables = wavetables.tables.clone();
+                self.wavetable_version = wavetables.version;
+                wavetables.acknowledge(self.wavetable_version);
+            }
+        }
+        // Mappings are only looked up again when they changed. If the editor holds the lock the
+        // last mappings read are used again
+        if let Ok(mappings) = self.params.macro_mappings.try_read() {
+            if mappings.version() != self.macro_mappings_version {
+                let param_ids = &self.param_ids;
+                self.macro_offsets
+                    .set_mappings(&mappings, |id| param_ids.get(id).copied());
+                self.macro_mappings_version = mappings.version();
+            }
+        }
+        self.macro_offsets.update(
+            self.params
+                .macros()
+                .map(|param| param.smoothed.next_step(block_size)),
+        );
+        if let Ok(mappings) = self.params.cc_mappings.try_read() {
+            if mappings.version() != self.cc_mappings_version {
+                let param_ids = &self.param_ids;
+                self.cc_overrides
+                    .set_mappings(&mappings, |id| param_ids.get(id).copied());
+                self.cc_mappings_version = mappings.version();
+            }
+        }
+        // The editor turns the controllers into parameter changes while it's open, without it
+        // they're applied here
+        if !self.params.editor_state.is_open() {
+            self.cc_overrides
+                .receive(&self.cc_values, self.sample_rate * SMOOTH_TIME / 1000.0);
+        }
+        self.cc_overrides.update(block_size);
+        self.macro_offsets.set_overrides(self.cc_overrides.values());
+        let (osc_params, voice_params, mod_matrix) =
+            self.params
+                .engine_params(self.sample_rate, Some(block_size), &self.macro_offsets);
         self.voices.global_params = GlobalParams {
-            legato: self.params.legato.value(),
-            voice_count: self.params.voice_count.value() as usize,
-            unison_count: self.params.unison_count.value() as usize,
-            unison_detune: self.params.unison_detune.value(),
-            bend_range: self.params.bend_range.value(),
+            legato: self.macro_offsets.value(&self.params.legato),
+            voice_count: self.macro_offsets.value(&self.params.voice_count) as usize,
+            unison_count: self.macro_offsets.value(&self.params.unison_count) as usize,
+            unison_detune: self.macro_offsets.value(&self.params.unison_detune),
+            bend_range: self.macro_offsets.value(&self.params.bend_range),
+            velocity_curve: self.macro_offsets.value(&self.params.velocity_curve),
+            velocity_curve_amount: self.macro_offsets.value(&self.params.velocity_curve_amount),
         };
         self.voices.block_update(&osc_params, voice_params);
+        // Metering is only needed while someone can see it
+        let metering = self.params.editor_state.is_open();
+        let scope_source = self.meters.scope_source.load(Ordering::Relaxed);
         for (sample_id, channel_samples) in buffer.iter_samples().enumerate() {
             // Smoothing is optionally built into the parameters themselves
-            let gain = self.params.gain.smoothed.next();
+            let gain = self.macro_offsets.next(&self.params.gain);
 
             while let Some(event) = next_event {
                 if event.timing() != sample_id as u32 {
@@ -208,20 +253,79 @@ impl Plugin for SynthPlugin {
                         let value = (value - 0.5) * 2.0;
                         self.voices.notes.pitch_bend = value;
                     }
+                    NoteEvent::MidiCC { cc, value, .. } => self.cc_values.receive(cc, value),
                     _ => (),
                 }
 
                 next_event = context.next_event();
             }
 
+            let sidechain_gain = self.macro_offsets.next(&self.params.sidechain_gain);
+            let sidechain = match aux.inputs.first() {
+                Some(input) if input.channels() > 0 => {
+                    let channels = input.as_slice_immutable();
+                    channels
+                        .iter()
+                        .map(|channel| channel[sample_id])
+                        .sum::<f32>()
+                        / channels.len() as f32
+                        * sidechain_gain
+                }
+                _ => 0.0,
+            };
+            let sidechain = match self.macro_offsets.value(&self.params.sidechain_mode) {
+                SidechainMode::Audio => sidechain,
+                SidechainMode::Follower => self.sidechain_follower.process(
+                    sidechain,
+                    self.macro_offsets.value(&self.params.sidechain_attack),
+                    self.macro_offsets.value(&self.params.sidechain_release),
+                    self.sample_rate,
+                ),
+            };
+
             self.voices.sample_update(&osc_params, voice_params);
-            let output = self.voices.play(&osc_params, &voice_params, pm_matrix);
+            let buses = self.voices.play(
+                &osc_params,
+                &voice_params,
+                &mod_matrix,
+                &self.wavetables,
+                sidechain,
+            );
 
+            // Aux buses are mixed into the main output when the host's layout has no port for them
+            let mut output = buses[0];
+            for (port, &bus) in buses[1..].iter().enumerate() {
+                match aux.outputs.get_mut(port) {
+                    Some(port) => {
+                        for channel in port.as_slice() {
+                            channel[sample_id] = bus * gain;
+                        }
+                    }
+                    None => output += bus,
+                }
+            }
             for sample in channel_samples {
                 *sample = output * gain;
             }
+            self.output_levels.process(output * gain);
+            if metering {
+                let scope_sample = match scope_source {
+                    0 => output * gain,
+                    oscillator => self
+                        .voices
+                        .newest_oscillator_output((oscillator - 1).min(7)),
+                };
+                self.meters.scope.push(scope_sample);
+            }
         }
         self.voices.remove_voices(&osc_params, &voice_params);
+        if metering {
+            self.meters.publish(
+                &self.output_levels,
+                self.voices.voices.len(),
+                self.voices.envelope_levels(),
+            );
+        }
         ProcessStatus::KeepAlive
     }
 }
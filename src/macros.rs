@@ -0,0 +1,337 @@
+use crate::midi_learn::MAX_CC_MAPPINGS;
+use nih_plug::prelude::{FloatParam, Param, ParamPtr};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::hash::{Hash, Hasher};
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+pub const MACRO_COUNT: usize = 8;
+/// Most mappings the audio thread applies, so it never needs to allocate for more.
+pub const MAX_MAPPINGS: usize = 64;
+/// Slots in [`MacroOffsets`]' lookup table, twice as many as the parameters it can hold so
+/// lookups rarely have to step past another parameter.
+const LOOKUP_SLOTS: usize = 2 * (MAX_MAPPINGS + MAX_CC_MAPPINGS);
+
+/// Source of [`Mappings::version`]s. Restoring a saved state replaces the whole list, so versions
+/// need to be unique across lists rather than just incrementing.
+static NEXT_VERSION: AtomicU64 = AtomicU64::new(1);
+
+/// A list of mappings stored with the plugin state. Every change gives it a new version, so the
+/// audio thread only looks their parameters up again when they changed. It's saved as a plain
+/// list.
+#[derive(Debug, Clone)]
+pub struct Mappings<T> {
+    list: Vec<T>,
+    version: u64,
+}
+impl<T> Mappings<T> {
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+}
+impl<T> From<Vec<T>> for Mappings<T> {
+    fn from(list: Vec<T>) -> Self {
+        Self {
+            list,
+            version: NEXT_VERSION.fetch_add(1, Ordering::Relaxed),
+        }
+    }
+}
+impl<T> Default for Mappings<T> {
+    fn default() -> Self {
+        Vec::new().into()
+    }
+}
+impl<T> Deref for Mappings<T> {
+    type Target = Vec<T>;
+
+    fn deref(&self) -> &Vec<T> {
+        &self.list
+    }
+}
+impl<T> DerefMut for Mappings<T> {
+    /// Any access that could change the list counts as a change.
+    fn deref_mut(&mut self) -> &mut Vec<T> {
+        self.version = NEXT_VERSION.fetch_add(1, Ordering::Relaxed);
+        &mut self.list
+    }
+}
+impl<T: Serialize> Serialize for Mappings<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.list.serialize(serializer)
+    }
+}
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Mappings<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Vec::deserialize(deserializer).map(Self::from)
+    }
+}
+
+/// Maps a macro onto a parameter. The macro's value is shaped by `curve` and moves the parameter
+/// from `min` to `max`, both as offsets from the parameter's own value in fractions of its
+/// normalized range.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MacroMapping {
+    pub macro_index: usize,
+    /// The parameter's ID, which stays the same between versions.
+    pub param: String,
+    #[serde(flatten)]
+    pub shape: MacroShape,
+}
+
+/// How a macro's value turns into an offset.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct MacroShape {
+    pub min: f32,
+    pub max: f32,
+    /// Exponent applied to the macro value, 1 is linear.
+    pub curve: f32,
+}
+impl MacroShape {
+    fn offset(&self, value: f32) -> f32 {
+        self.min + (self.max - self.min) * value.clamp(0.0, 1.0).powf(self.curve)
+    }
+}
+
+/// A mapping with its parameter looked up, and its offset for the current block.
+#[derive(Debug, Clone, Copy)]
+struct Target {
+    param: ParamPtr,
+    macro_index: usize,
+    shape: MacroShape,
+    offset: f32,
+}
+
+/// Everything the macros and MIDI controllers do to one parameter this block.
+#[derive(Debug, Clone, Copy)]
+struct Entry {
+    param: ParamPtr,
+    /// Sum of the offsets of every mapping that moves it.
+    offset: f32,
+    overridden: Option<f32>,
+}
+
+/// Hashes parameter pointers for the lookup table. They're addresses, so a multiply spreads them
+/// well enough.
+#[derive(Default)]
+struct PtrHasher(u64);
+impl Hasher for PtrHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.write_u64(byte.into());
+        }
+    }
+    fn write_u64(&mut self, value: u64) {
+        self.0 = (self.0.rotate_left(5) ^ value).wrapping_mul(0x517c_c1b7_2722_0a95);
+    }
+    fn write_usize(&mut self, value: usize) {
+        self.write_u64(value as u64);
+    }
+}
+
+/// The slot holding `param`, or the empty one it would go in. There's always an empty slot since
+/// the table is twice as large as it ever needs to be.
+fn lookup_slot(lookup: &[Option<Entry>], param: ParamPtr) -> usize {
+    let mut hasher = PtrHasher::default();
+    param.hash(&mut hasher);
+    let mut slot = (hasher.finish() >> 32) as usize % LOOKUP_SLOTS;
+    while matches!(lookup[slot], Some(entry) if entry.param != param) {
+        slot = (slot + 1) % LOOKUP_SLOTS;
+    }
+    slot
+}
+
+fn lookup_insert(lookup: &mut [Option<Entry>], param: ParamPtr) -> &mut Entry {
+    let slot = lookup_slot(lookup, param);
+    lookup[slot].get_or_insert(Entry {
+        param,
+        offset: 0.0,
+        overridden: None,
+    })
+}
+
+/// The offsets the macros add to the parameters, as the engine reads them. The audio thread
+/// keeps one around with the last mappings it could read, so the macros keep working while the
+/// editor holds the lock.
+#[derive(Debug)]
+pub struct MacroOffsets {
+    targets: Vec<Target>,
+    /// Normalized values read instead of the parameters' own, set by MIDI controllers while the
+    /// editor is closed. The offsets are added to these.
+    overrides: Vec<(ParamPtr, f32)>,
+    /// The targets and overrides by parameter, rebuilt whenever they change so reading a
+    /// parameter is a single lookup.
+    lookup: Vec<Option<Entry>>,
+    /// Whether anything is in `lookup`, so parameters can skip it when nothing's mapped.
+    empty: bool,
+}
+impl Default for MacroOffsets {
+    fn default() -> Self {
+        Self {
+            targets: Vec::with_capacity(MAX_MAPPINGS),
+            overrides: Vec::with_capacity(MAX_CC_MAPPINGS),
+            lookup: vec![None; LOOKUP_SLOTS],
+            empty: true,
+        }
+    }
+}
+impl MacroOffsets {
+    /// Replaces the mappings, looking up their parameters with `find`. Mappings come from saved
+    /// states, so ones with unknown parameters or macros are left out rather than trusted.
+    pub fn set_mappings(
+        &mut self,
+        mappings: &[MacroMapping],
+        find: impl Fn(&str) -> Option<ParamPtr>,
+    ) {
+        self.targets.clear();
+        let valid = mappings
+            .iter()
+            .filter(|mapping| mapping.macro_index < MACRO_COUNT)
+            .filter_map(|mapping| Some((find(&mapping.param)?, mapping)));
+        for (param, mapping) in valid.take(MAX_MAPPINGS) {
+            self.targets.push(Target {
+                param,
+                macro_index: mapping.macro_index,
+                shape: mapping.shape,
+                offset: 0.0,
+            });
+        }
+        self.rebuild();
+    }
+    /// Works out every mapping's offset from the macros' values.
+    pub fn update(&mut self, macros: [f32; MACRO_COUNT]) {
+        for target in &mut self.targets {
+            target.offset = target.shape.offset(macros[target.macro_index]);
+        }
+        self.rebuild();
+    }
+    /// Replaces the values read instead of the parameters' own.
+    pub fn set_overrides(&mut self, overrides: impl Iterator<Item = (ParamPtr, f32)>) {
+        self.overrides.clear();
+        self.overrides.extend(overrides.take(MAX_CC_MAPPINGS));
+        self.rebuild();
+    }
+    fn rebuild(&mut self) {
+        self.lookup.fill(None);
+        for target in &self.targets {
+            lookup_insert(&mut self.lookup, target.param).offset += target.offset;
+        }
+        for &(param, value) in &self.overrides {
+            lookup_insert(&mut self.lookup, param).overridden = Some(value);
+        }
+        self.empty = self.targets.is_empty() && self.overrides.is_empty();
+    }
+    /// `param`'s override and offset.
+    fn entry(&self, param: ParamPtr) -> (Option<f32>, f32) {
+        if self.empty {
+            return (None, 0.0);
+        }
+        match self.lookup[lookup_slot(&self.lookup, param)] {
+            Some(entry) => (entry.overridden, entry.offset),
+            None => (None, 0.0),
+        }
+    }
+    /// The normalized offset of `param`, from every mapping that moves it.
+    pub fn offset(&self, param: ParamPtr) -> f32 {
+        self.entry(param).1
+    }
+    /// `param`'s value with its offset added.
+    pub fn value<P: Param>(&self, param: &P) -> P::Plain {
+        match self.entry(param.as_ptr()) {
+            (None, 0.0) => param.modulated_plain_value(),
+            (value, offset) => {
+                let value = value.unwrap_or_else(|| param.modulated_normalized_value());
+                param.preview_plain((value + offset).clamp(0.0, 1.0))
+            }
+        }
+    }
+    /// `param`'s smoothed value advanced by `block_size` samples, with its offset added. Without
+    /// a block size the smoother isn't touched and the value it's heading to is used, so the
+    /// editor can read the parameters without disturbing the audio thread's smoothing.
+    pub fn smoothed(&self, param: &FloatParam, block_size: Option<u32>) -> f32 {
+        let value = match block_size {
+            Some(block_size) => param.smoothed.next_step(block_size),
+            None => param.modulated_plain_value(),
+        };
+        self.add_offset(param, value)
+    }
+    /// `param`'s smoothed value advanced by one sample, with its offset added.
+    pub fn next(&self, param: &FloatParam) -> f32 {
+        self.add_offset(param, param.smoothed.next())
+    }
+    fn add_offset(&self, param: &FloatParam, value: f32) -> f32 {
+        // Overridden values are smoothed where they're set, the smoother still moves on so it's
+        // in the right place once the override is dropped
+        match self.entry(param.as_ptr()) {
+            (None, 0.0) => value,
+            (overridden, offset) => {
+                let value = overridden.unwrap_or_else(|| param.preview_normalized(value));
+                param.preview_plain((value + offset).clamp(0.0, 1.0))
+            }
+        }
+    }
+}
+
+mod test {
+    use super::*;
+    use nih_plug::prelude::FloatRange;
+
+    #[test]
+    fn test_mapping_offset() {
+        let shape = MacroShape {
+            min: 0.0,
+            max: 0.5,
+            curve: 2.0,
+        };
+        assert_eq!(shape.offset(0.0), 0.0);
+        assert_eq!(shape.offset(0.5), 0.125);
+        assert_eq!(shape.offset(1.0), 0.5);
+    }
+
+    #[test]
+    fn test_offsets() {
+        let range = || FloatRange::Linear { min: 0.0, max: 1.0 };
+        let params = [
+            FloatParam::new("A", 0.25, range()),
+            FloatParam::new("B", 0.5, range()),
+            FloatParam::new("C", 0.75, range()),
+        ];
+        let ids = ["a", "b", "c"];
+        let mapping = |macro_index, param: &str, max| MacroMapping {
+            macro_index,
+            param: param.to_string(),
+            shape: MacroShape {
+                min: 0.0,
+                max,
+                curve: 1.0,
+            },
+        };
+        let mut offsets = MacroOffsets::default();
+        offsets.set_mappings(
+            &[
+                mapping(0, "a", 0.5),
+                mapping(1, "a", 0.25),
+                mapping(0, "b", -0.25),
+            ],
+            |id| Some(params[ids.iter().position(|other| *other == id)?].as_ptr()),
+        );
+        offsets.update([1.0, 0.5, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0]);
+        offsets.set_overrides([(params[1].as_ptr(), 0.0)].into_iter());
+        assert_eq!(offsets.value(&params[0]), 0.875);
+        assert_eq!(offsets.value(&params[1]), 0.0);
+        assert_eq!(offsets.value(&params[2]), 0.75);
+    }
+
+    #[test]
+    fn test_mappings_version() {
+        let mut mappings = Mappings::<MacroMapping>::default();
+        let version = mappings.version();
+        assert_eq!(mappings.len(), 0);
+        assert_eq!(mappings.version(), version);
+        mappings.clear();
+        assert_ne!(mappings.version(), version);
+    }
+}
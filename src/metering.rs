@@ -0,0 +1,149 @@
+//! Levels measured on the audio thread and shown by the editor.
+
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+
+/// Time for the peak level to fall by 12 dB.
+const PEAK_DECAY_MS: f32 = 150.0;
+/// Averaging time of the RMS level.
+const RMS_WINDOW_MS: f32 = 300.0;
+/// Samples kept for the scope and spectrum, a power of two for the FFT.
+pub const SCOPE_SIZE: usize = 4096;
+
+/// An `f32` stored as its bits, so it can be shared between threads without locking.
+#[derive(Debug, Default)]
+pub struct AtomicF32(AtomicU32);
+impl AtomicF32 {
+    pub fn load(&self) -> f32 {
+        f32::from_bits(self.0.load(Ordering::Relaxed))
+    }
+    pub fn store(&self, value: f32) {
+        self.0.store(value.to_bits(), Ordering::Relaxed)
+    }
+}
+
+/// Levels published by the audio thread for the editor.
+#[derive(Debug, Default)]
+pub struct Meters {
+    /// Output peak amplitude.
+    pub peak: AtomicF32,
+    /// Output RMS amplitude.
+    pub rms: AtomicF32,
+    pub active_voices: AtomicUsize,
+    /// Highest envelope level of each oscillator across all voices.
+    pub envelopes: [AtomicF32; 8],
+    pub scope: ScopeBuffer,
+    /// What the scope shows, 0 for the output or `n` for oscillator `n` of the newest voice.
+    /// Set by the editor.
+    pub scope_source: AtomicUsize,
+    pub sample_rate: AtomicF32,
+}
+impl Meters {
+    pub fn publish(&self, levels: &OutputLevels, active_voices: usize, envelopes: [f32; 8]) {
+        self.peak.store(levels.peak);
+        self.rms.store(levels.mean_square.sqrt());
+        self.active_voices.store(active_voices, Ordering::Relaxed);
+        for (meter, level) in self.envelopes.iter().zip(envelopes) {
+            meter.store(level);
+        }
+    }
+}
+
+/// The most recent samples of the scope's source. The audio thread writes single samples and the
+/// editor copies the whole buffer, so a copy can tear where the writer is, which isn't visible
+/// on a scope.
+#[derive(Debug)]
+pub struct ScopeBuffer {
+    samples: [AtomicF32; SCOPE_SIZE],
+    /// Samples written so far, wrapping around. The next one goes to this modulo `SCOPE_SIZE`.
+    written: AtomicUsize,
+}
+impl Default for ScopeBuffer {
+    fn default() -> Self {
+        Self {
+            samples: std::array::from_fn(|_| AtomicF32::default()),
+            written: AtomicUsize::new(0),
+        }
+    }
+}
+impl ScopeBuffer {
+    pub fn push(&self, sample: f32) {
+        let written = self.written.load(Ordering::Relaxed);
+        self.samples[written % SCOPE_SIZE].store(sample);
+        self.written
+            .store(written.wrapping_add(1), Ordering::Relaxed);
+    }
+    /// Changes whenever a sample is pushed, so readers can tell when there's something new.
+    pub fn written(&self) -> usize {
+        self.written.load(Ordering::Relaxed)
+    }
+    /// Copies the buffer out, oldest sample first.
+    pub fn read(&self) -> Vec<f32> {
+        let position = self.written() % SCOPE_SIZE;
+        (0..SCOPE_SIZE)
+            .map(|i| self.samples[(position + i) % SCOPE_SIZE].load())
+            .collect()
+    }
+}
+
+/// Audio thread state of the output meters.
+#[derive(Debug, Clone, Copy)]
+pub struct OutputLevels {
+    peak: f32,
+    mean_square: f32,
+    peak_decay: f32,
+    rms_decay: f32,
+}
+impl OutputLevels {
+    pub fn new(sample_rate: f32) -> Self {
+        Self {
+            peak: 0.0,
+            mean_square: 0.0,
+            peak_decay: 0.25f32.powf((sample_rate * PEAK_DECAY_MS / 1000.0).recip()),
+            rms_decay: (-1.0 / (sample_rate * RMS_WINDOW_MS / 1000.0)).exp(),
+        }
+    }
+    pub fn process(&mut self, sample: f32) {
+        let amplitude = sample.abs();
+        self.peak = if amplitude > self.peak {
+            amplitude
+        } else {
+            self.peak * self.peak_decay
+        };
+        self.mean_square = sample * sample + (self.mean_square - sample * sample) * self.rms_decay;
+    }
+}
+
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_output_levels() {
+        let sample_rate = 48000.0;
+        let mut levels = OutputLevels::new(sample_rate);
+        levels.process(1.0);
+        assert_eq!(levels.peak, 1.0);
+        for _ in 0..(sample_rate * PEAK_DECAY_MS / 1000.0) as usize {
+            levels.process(0.0);
+        }
+        assert!((levels.peak - 0.25).abs() < 0.01);
+
+        // A full scale square wave settles at an RMS of 1
+        let mut levels = OutputLevels::new(sample_rate);
+        for i in 0..sample_rate as usize * 5 {
+            levels.process(if i % 2 == 0 { 1.0 } else { -1.0 });
+        }
+        assert!((levels.mean_square.sqrt() - 1.0).abs() < 0.001);
+    }
+
+    #[test]
+    fn test_scope_buffer() {
+        let scope = ScopeBuffer::default();
+        for i in 0..SCOPE_SIZE + 10 {
+            scope.push(i as f32);
+        }
+        let samples = scope.read();
+        assert_eq!(samples[0], 10.0);
+        assert_eq!(samples[SCOPE_SIZE - 1], (SCOPE_SIZE + 9) as f32);
+        assert_eq!(scope.written(), SCOPE_SIZE + 10);
+    }
+}
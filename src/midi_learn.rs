@@ -0,0 +1,339 @@
+//! MIDI controllers mapped onto parameters. While the editor is open it turns the controllers the
+//! audio thread received into parameter changes, so the host sees them as gestures and can record
+//! them as automation. While it's closed the audio thread applies them itself, overriding the
+//! parameters' values without changing them.
+
+use nih_plug::prelude::ParamPtr;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, AtomicI32, AtomicU8, Ordering};
+
+pub const CC_COUNT: usize = 128;
+/// Most mappings the audio thread applies, so it never needs to allocate for more.
+pub const MAX_CC_MAPPINGS: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CcMode {
+    /// The controller's position sets the parameter.
+    Absolute,
+    /// The controller sends increments in two's complement, 1 to 63 up and 127 down to 65 down,
+    /// as sent by endless encoders.
+    Relative,
+}
+impl CcMode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            CcMode::Absolute => "Absolute",
+            CcMode::Relative => "Relative",
+        }
+    }
+}
+
+/// Maps a controller onto a parameter.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CcMapping {
+    pub cc: u8,
+    /// The parameter's ID, which stays the same between versions.
+    pub param: String,
+    #[serde(flatten)]
+    pub range: CcRange,
+}
+impl CcMapping {
+    pub fn new(cc: u8, param: String) -> Self {
+        Self {
+            cc,
+            param,
+            range: CcRange {
+                min: 0.0,
+                max: 1.0,
+                mode: CcMode::Absolute,
+            },
+        }
+    }
+}
+
+/// How a controller moves a parameter, between `min` and `max`, both normalized. `min` can be
+/// above `max` to invert the controller.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct CcRange {
+    pub min: f32,
+    pub max: f32,
+    pub mode: CcMode,
+}
+impl CcRange {
+    /// The parameter's new normalized value after the controller moved, from its `current`
+    /// value. Relative controllers move stepped parameters a whole step per increment, given
+    /// the parameter's `step_count`.
+    pub fn value(
+        &self,
+        current: f32,
+        position: u8,
+        increments: i32,
+        step_count: Option<usize>,
+    ) -> f32 {
+        let value = match self.mode {
+            CcMode::Absolute => self.min + (self.max - self.min) * position as f32 / 127.0,
+            CcMode::Relative => {
+                let step = match step_count {
+                    Some(steps) if steps > 0 => (self.max - self.min).signum() / steps as f32,
+                    _ => (self.max - self.min) / 127.0,
+                };
+                let (low, high) = (self.min.min(self.max), self.min.max(self.max));
+                (current + step * increments as f32).clamp(low, high)
+            }
+        };
+        value.clamp(0.0, 1.0)
+    }
+}
+
+/// A controller that moved since the changes were last taken.
+#[derive(Debug, Clone, Copy)]
+pub struct CcChange {
+    pub cc: u8,
+    /// The last value received.
+    pub position: u8,
+    /// The sum of the values received, read as relative increments.
+    pub increments: i32,
+}
+
+/// Controllers received by the audio thread, waiting for the editor, or for [`CcOverrides`]
+/// while the editor is closed.
+#[derive(Debug)]
+pub struct CcValues {
+    positions: [AtomicU8; CC_COUNT],
+    increments: [AtomicI32; CC_COUNT],
+    changed: [AtomicBool; CC_COUNT],
+}
+impl Default for CcValues {
+    fn default() -> Self {
+        Self {
+            positions: std::array::from_fn(|_| AtomicU8::new(0)),
+            increments: std::array::from_fn(|_| AtomicI32::new(0)),
+            changed: std::array::from_fn(|_| AtomicBool::new(false)),
+        }
+    }
+}
+impl CcValues {
+    /// Records a controller change, with `value` normalized as NIH-plug gives it.
+    pub fn receive(&self, cc: u8, value: f32) {
+        let index = cc as usize % CC_COUNT;
+        let position = (value.clamp(0.0, 1.0) * 127.0).round() as u8;
+        let increment = if position < 64 {
+            position as i32
+        } else {
+            position as i32 - 128
+        };
+        self.positions[index].store(position, Ordering::Relaxed);
+        self.increments[index].fetch_add(increment, Ordering::Relaxed);
+        self.changed[index].store(true, Ordering::Release);
+    }
+    /// Every controller that moved since the last call.
+    pub fn take_changes(&self) -> Vec<CcChange> {
+        let mut changes = Vec::new();
+        self.for_each_change(|change| changes.push(change));
+        changes
+    }
+    /// Like [`CcValues::take_changes()`], without allocating.
+    pub fn for_each_change(&self, mut f: impl FnMut(CcChange)) {
+        for index in 0..CC_COUNT {
+            if self.changed[index].swap(false, Ordering::Acquire) {
+                f(CcChange {
+                    cc: index as u8,
+                    position: self.positions[index].load(Ordering::Relaxed),
+                    increments: self.increments[index].swap(0, Ordering::Relaxed),
+                });
+            }
+        }
+    }
+}
+
+/// A value a controller set while the editor was closed, and how far the engine has moved
+/// towards it.
+#[derive(Debug, Clone, Copy)]
+struct Override {
+    target: f32,
+    current: f32,
+    /// How far `current` moves each sample.
+    step: f32,
+    /// The parameter's own value when the controller moved. The override is dropped once this
+    /// changes, so the host and the editor can take the parameter back.
+    param_value: f32,
+}
+
+/// A mapping with its parameter looked up.
+#[derive(Debug, Clone, Copy)]
+struct Target {
+    param: ParamPtr,
+    cc: u8,
+    range: CcRange,
+    value: Option<Override>,
+}
+
+/// The audio thread's side of the mappings, applying controllers while the editor is closed. The
+/// values only replace what the engine reads, so the host doesn't see or record them.
+#[derive(Debug)]
+pub struct CcOverrides {
+    targets: Vec<Target>,
+    /// Where the next mappings are looked up, swapped with `targets` to keep their values.
+    spare: Vec<Target>,
+}
+impl Default for CcOverrides {
+    fn default() -> Self {
+        Self {
+            targets: Vec::with_capacity(MAX_CC_MAPPINGS),
+            spare: Vec::with_capacity(MAX_CC_MAPPINGS),
+        }
+    }
+}
+impl CcOverrides {
+    /// Replaces the mappings, looking up their parameters with `find`. Mappings that are still
+    /// there keep the value their controller set.
+    pub fn set_mappings(
+        &mut self,
+        mappings: &[CcMapping],
+        find: impl Fn(&str) -> Option<ParamPtr>,
+    ) {
+        self.spare.clear();
+        let found = mappings
+            .iter()
+            .filter_map(|mapping| Some((find(&mapping.param)?, mapping)));
+        for (param, mapping) in found.take(MAX_CC_MAPPINGS) {
+            let value = self
+                .targets
+                .iter()
+                .find(|target| target.param == param && target.cc == mapping.cc)
+                .and_then(|target| target.value);
+            self.spare.push(Target {
+                param,
+                cc: mapping.cc,
+                range: mapping.range,
+                value,
+            });
+        }
+        std::mem::swap(&mut self.targets, &mut self.spare);
+    }
+    /// Applies the controllers that moved since the last call, moving to their values over
+    /// `smoothing` samples.
+    pub fn receive(&mut self, values: &CcValues, smoothing: f32) {
+        values.for_each_change(|change| {
+            for target in self
+                .targets
+                .iter_mut()
+                .filter(|target| target.cc == change.cc)
+            {
+                // SAFETY: The parameters are owned by the plugin, which outlives this call
+                let (param_value, step_count) = unsafe {
+                    (
+                        target.param.unmodulated_normalized_value(),
+                        target.param.step_count(),
+                    )
+                };
+                let last = target
+                    .value
+                    .filter(|value| value.param_value == param_value);
+                let current = last.map_or(param_value, |value| value.current);
+                let from = last.map_or(param_value, |value| value.target);
+                let value =
+                    target
+                        .range
+                        .value(from, change.position, change.increments, step_count);
+                target.value = Some(Override {
+                    target: value,
+                    current,
+                    // Stepped parameters jump straight to their new step
+                    step: match step_count {
+                        Some(_) => value - current,
+                        None => (value - current) / smoothing.max(1.0),
+                    },
+                    param_value,
+                });
+            }
+        });
+    }
+    /// Moves the values `block_size` samples towards their targets, and drops the ones whose
+    /// parameter changed since.
+    pub fn update(&mut self, block_size: u32) {
+        for target in &mut self.targets {
+            // SAFETY: The parameters are owned by the plugin, which outlives this call
+            let param_value = unsafe { target.param.unmodulated_normalized_value() };
+            target.value = target
+                .value
+                .filter(|value| value.param_value == param_value)
+                .map(|value| {
+                    let current = value.current + value.step * block_size as f32;
+                    Override {
+                        current: match value.step >= 0.0 {
+                            true => current.min(value.target),
+                            false => current.max(value.target),
+                        },
+                        ..value
+                    }
+                });
+        }
+    }
+    /// The normalized values the engine reads instead of the parameters' own.
+    pub fn values(&self) -> impl Iterator<Item = (ParamPtr, f32)> + '_ {
+        self.targets
+            .iter()
+            .filter_map(|target| Some((target.param, target.value?.current)))
+    }
+}
+
+mod test {
+    use super::*;
+    use nih_plug::prelude::{FloatParam, FloatRange, Param};
+
+    #[test]
+    fn test_cc_mapping() {
+        let mut range = CcMapping::new(1, "gain".to_string()).range;
+        assert_eq!(range.value(0.3, 127, 0, None), 1.0);
+        range.min = 0.8;
+        range.max = 0.2;
+        assert_eq!(range.value(0.3, 0, 0, None), 0.8);
+
+        range.mode = CcMode::Relative;
+        // Inverted, so incrementing moves down, and it stays in the range
+        assert!(range.value(0.5, 0, 1, None) < 0.5);
+        assert_eq!(range.value(0.5, 0, -127, None), 0.8);
+        // A whole step of a parameter with four steps
+        assert_eq!(range.value(0.5, 0, 1, Some(4)), 0.25);
+    }
+
+    #[test]
+    fn test_cc_overrides() {
+        let param = FloatParam::new("Gain", 0.5, FloatRange::Linear { min: 0.0, max: 1.0 });
+        let values = CcValues::default();
+        let mut overrides = CcOverrides::default();
+        overrides.set_mappings(&[CcMapping::new(1, "gain".to_string())], |id| {
+            (id == "gain").then(|| param.as_ptr())
+        });
+        assert_eq!(overrides.values().count(), 0);
+
+        // The value moves from the parameter's to the controller's over the smoothing time
+        values.receive(1, 1.0);
+        overrides.receive(&values, 10.0);
+        overrides.update(5);
+        assert_eq!(overrides.values().next(), Some((param.as_ptr(), 0.75)));
+        overrides.update(10);
+        assert_eq!(overrides.values().next(), Some((param.as_ptr(), 1.0)));
+
+        // Looking the mappings up again keeps the value
+        overrides.set_mappings(&[CcMapping::new(1, "gain".to_string())], |id| {
+            (id == "gain").then(|| param.as_ptr())
+        });
+        assert_eq!(overrides.values().count(), 1);
+    }
+
+    #[test]
+    fn test_cc_values() {
+        let values = CcValues::default();
+        values.receive(74, 1.0 / 127.0);
+        values.receive(74, 1.0 / 127.0);
+        values.receive(10, 126.0 / 127.0);
+        let changes = values.take_changes();
+        assert_eq!(changes.len(), 2);
+        assert_eq!((changes[0].cc, changes[0].increments), (10, -2));
+        assert_eq!((changes[1].cc, changes[1].position), (74, 1));
+        assert_eq!(changes[1].increments, 2);
+        assert!(values.take_changes().is_empty());
+    }
+}
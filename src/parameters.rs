@@ -1,12 +1,19 @@
 use nih_plug::prelude::*;
 use nih_plug_iced::IcedState;
 use std::f32::consts::PI;
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
 use wide::f32x8;
 
 use crate::editor;
+use crate::macros::{MacroMapping, MacroOffsets, Mappings, MACRO_COUNT};
+use crate::midi_learn::CcMapping;
 use crate::svf_simper::FilterType;
-use crate::voice::{LegatoMode, Phaseshaper, Waveshaper};
+use crate::voice::{
+    EnvelopeMode, FixedRange, FrequencyMode, KeyscaleCurve, LegatoMode, MatrixMode, ModMatrix,
+    NoiseType, OscParamsBatch, OutputBus, Phaseshaper, SidechainMode, VelocityCurve, VoiceParams,
+    Waveform, Waveshaper, MULTI_STAGE_POINTS,
+};
+use crate::wavetable::WavetableBank;
 
 const ATTACK_DECAY_RANGE: FloatRange = FloatRange::Skewed {
     min: 0.0,
@@ -28,12 +35,50 @@ const FM_RANGE: FloatRange = FloatRange::Skewed {
     max: 1.0,
     factor: 0.4,
 };
-const SMOOTH_TIME: f32 = 20.0;
+const PITCH_ENVELOPE_RANGE: FloatRange = FloatRange::SymmetricalSkewed {
+    min: -48.0,
+    max: 48.0,
+    factor: 0.5,
+    center: 0.0,
+};
+/// Envelope segment curve exponent. 1.0 is linear and sits in the middle of the range.
+const CURVE_RANGE: FloatRange = FloatRange::Skewed {
+    min: 0.1,
+    max: 10.0,
+    factor: 0.29,
+};
+/// Envelope follower attack and release times in seconds.
+const FOLLOWER_RANGE: FloatRange = FloatRange::Skewed {
+    min: 0.0001,
+    max: 1.0,
+    factor: 0.3,
+};
+/// How long parameter changes are smoothed over, in milliseconds.
+pub const SMOOTH_TIME: f32 = 20.0;
+
+fn macro_param(index: usize) -> FloatParam {
+    FloatParam::new(
+        format!("Macro {index}"),
+        0.0,
+        FloatRange::Linear { min: 0.0, max: 1.0 },
+    )
+    .with_unit("%")
+    .with_value_to_string(formatters::v2s_f32_percentage(0))
+    .with_string_to_value(formatters::s2v_f32_percentage())
+    // Macros move other parameters, which would step without smoothing
+    .with_smoother(SmoothingStyle::Linear(SMOOTH_TIME))
+}
+
+fn curve_param(name: impl Into<String>, default: f32) -> FloatParam {
+    FloatParam::new(name, default, CURVE_RANGE).with_value_to_string(formatters::v2s_f32_rounded(2))
+}
 
 #[derive(Params)]
 pub struct OscillatorParams {
     #[id = "amp"]
     pub amp: FloatParam,
+    #[id = "output_bus"]
+    pub output_bus: EnumParam<OutputBus>,
     #[id = "coarse"]
     pub coarse: FloatParam,
     #[id = "fine"]
@@ -42,6 +87,12 @@ pub struct OscillatorParams {
     pub freq_mult: FloatParam,
     #[id = "freq_div"]
     pub freq_div: FloatParam,
+    #[id = "frequency_mode"]
+    pub frequency_mode: EnumParam<FrequencyMode>,
+    #[id = "fixed_range"]
+    pub fixed_range: EnumParam<FixedRange>,
+    #[id = "fixed_fine"]
+    pub fixed_fine: IntParam,
     #[id = "hz_detune"]
     pub hz_detune: FloatParam,
     #[id = "phase_offset"]
@@ -64,12 +115,46 @@ pub struct OscillatorParams {
     pub sustain: FloatParam,
     #[id = "release"]
     pub release: FloatParam,
+    #[id = "attack_curve"]
+    pub attack_curve: FloatParam,
+    #[id = "decay_curve"]
+    pub decay_curve: FloatParam,
+    #[id = "release_curve"]
+    pub release_curve: FloatParam,
+    #[id = "envelope_mode"]
+    pub envelope_mode: EnumParam<EnvelopeMode>,
+    #[nested(id_prefix = "mseg_")]
+    pub multi_stage: MultiStageParams,
     #[id = "feedback"]
     pub feedback: FloatParam,
     #[id = "velocity_sensitivity"]
     pub velocity_sensitivity: FloatParam,
+    #[id = "velocity_attack"]
+    pub velocity_attack: FloatParam,
+    #[id = "velocity_feedback"]
+    pub velocity_feedback: FloatParam,
     #[id = "keyscaling"]
     pub keyscaling: FloatParam,
+    #[id = "rate_scaling"]
+    pub rate_scaling: FloatParam,
+    #[id = "breakpoint"]
+    pub breakpoint: IntParam,
+    #[id = "left_depth"]
+    pub left_depth: FloatParam,
+    #[id = "left_curve"]
+    pub left_curve: EnumParam<KeyscaleCurve>,
+    #[id = "right_depth"]
+    pub right_depth: FloatParam,
+    #[id = "right_curve"]
+    pub right_curve: EnumParam<KeyscaleCurve>,
+    #[id = "waveform"]
+    pub waveform: EnumParam<Waveform>,
+    #[id = "wavetable_position"]
+    pub wavetable_position: FloatParam,
+    #[id = "noise_type"]
+    pub noise_type: EnumParam<NoiseType>,
+    #[id = "noise_tone"]
+    pub noise_tone: FloatParam,
     #[id = "waveshaper"]
     pub waveshaper: EnumParam<Waveshaper>,
     #[id = "waveshaper_amount"]
@@ -78,6 +163,22 @@ pub struct OscillatorParams {
     pub phaseshaper: EnumParam<Phaseshaper>,
     #[id = "phaseshaper_amount"]
     pub phaseshaper_amount: FloatParam,
+    #[id = "pitch_env_amount"]
+    pub pitch_env_amount: FloatParam,
+    #[id = "pitch_env_attack"]
+    pub pitch_env_attack: FloatParam,
+    #[id = "pitch_env_decay"]
+    pub pitch_env_decay: FloatParam,
+    #[id = "pitch_env_sustain"]
+    pub pitch_env_sustain: FloatParam,
+    #[id = "pitch_env_release"]
+    pub pitch_env_release: FloatParam,
+    #[id = "pitch_env_attack_curve"]
+    pub pitch_env_attack_curve: FloatParam,
+    #[id = "pitch_env_decay_curve"]
+    pub pitch_env_decay_curve: FloatParam,
+    #[id = "pitch_env_release_curve"]
+    pub pitch_env_release_curve: FloatParam,
 }
 impl OscillatorParams {
     pub fn new(index: usize, default_amp: f32) -> Self {
@@ -92,6 +193,7 @@ impl OscillatorParams {
             )
             .with_unit("%")
             .with_smoother(SmoothingStyle::Linear(SMOOTH_TIME)),
+            output_bus: EnumParam::new(format!("Osc{} Output", index + 1), OutputBus::Main),
             coarse: FloatParam::new(
                 format!("Osc{} Coarse", index + 1),
                 0.0,
@@ -124,6 +226,19 @@ impl OscillatorParams {
             )
             .with_unit("x")
             .with_step_size(1.0),
+            frequency_mode: EnumParam::new(
+                format!("Osc{} Frequency Mode", index + 1),
+                FrequencyMode::Ratio,
+            ),
+            fixed_range: EnumParam::new(
+                format!("Osc{} Fixed Range", index + 1),
+                FixedRange::Hundred,
+            ),
+            fixed_fine: IntParam::new(
+                format!("Osc{} Fixed Fine", index + 1),
+                0,
+                IntRange::Linear { min: 0, max: 99 },
+            ),
             hz_detune: FloatParam::new(
                 format!("Osc{} +/- Hz", index + 1),
                 0.0,
@@ -179,6 +294,14 @@ impl OscillatorParams {
             ),
             release: FloatParam::new(format!("Osc{} Release", index + 1), 0.5, RELEASE_RANGE)
                 .with_unit(" s"),
+            attack_curve: curve_param(format!("Osc{} Atk. Curve", index + 1), 1.0),
+            decay_curve: curve_param(format!("Osc{} Dec. Curve", index + 1), 2.0),
+            release_curve: curve_param(format!("Osc{} Rls. Curve", index + 1), 2.0),
+            envelope_mode: EnumParam::new(
+                format!("Osc{} Env. Mode", index + 1),
+                EnvelopeMode::Dahdsr,
+            ),
+            multi_stage: MultiStageParams::new(index),
             feedback: FloatParam::new(
                 format!("Osc{} Feedback", index + 1),
                 0.0,
@@ -196,6 +319,22 @@ impl OscillatorParams {
                     max: 1.0,
                 },
             ),
+            velocity_attack: FloatParam::new(
+                format!("Osc{} Velo. Attack", index + 1),
+                0.0,
+                FloatRange::Linear {
+                    min: -1.0,
+                    max: 1.0,
+                },
+            ),
+            velocity_feedback: FloatParam::new(
+                format!("Osc{} Velo. Feedback", index + 1),
+                0.0,
+                FloatRange::Linear {
+                    min: -1.0,
+                    max: 1.0,
+                },
+            ),
             keyscaling: FloatParam::new(
                 format!("Osc{} Keyscaling", index + 1),
                 0.0,
@@ -204,6 +343,58 @@ impl OscillatorParams {
                     max: 1.0,
                 },
             ),
+            rate_scaling: FloatParam::new(
+                format!("Osc{} Rate Scaling", index + 1),
+                0.0,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            ),
+            breakpoint: IntParam::new(
+                format!("Osc{} Breakpoint", index + 1),
+                60,
+                IntRange::Linear { min: 0, max: 127 },
+            )
+            .with_value_to_string(formatters::v2s_i32_note_formatter())
+            .with_string_to_value(formatters::s2v_i32_note_formatter()),
+            left_depth: FloatParam::new(
+                format!("Osc{} L. Scale Depth", index + 1),
+                0.0,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            ),
+            left_curve: EnumParam::new(
+                format!("Osc{} L. Scale Curve", index + 1),
+                KeyscaleCurve::NegativeLinear,
+            ),
+            right_depth: FloatParam::new(
+                format!("Osc{} R. Scale Depth", index + 1),
+                0.0,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            ),
+            right_curve: EnumParam::new(
+                format!("Osc{} R. Scale Curve", index + 1),
+                KeyscaleCurve::NegativeLinear,
+            ),
+            waveform: EnumParam::new(format!("Osc{} Waveform", index + 1), Waveform::Sine),
+            wavetable_position: FloatParam::new(
+                format!("Osc{} Wavetable Position", index + 1),
+                0.0,
+                FloatRange::Linear {
+                    min: 0.0,
+                    max: 100.0,
+                },
+            )
+            .with_unit("%")
+            .with_smoother(SmoothingStyle::Linear(SMOOTH_TIME)),
+            noise_type: EnumParam::new(format!("Osc{} Noise Type", index + 1), NoiseType::White),
+            noise_tone: FloatParam::new(
+                format!("Osc{} Noise Tone", index + 1),
+                0.0,
+                FloatRange::Linear {
+                    min: -100.0,
+                    max: 100.0,
+                },
+            )
+            .with_unit("%")
+            .with_smoother(SmoothingStyle::Linear(SMOOTH_TIME)),
             waveshaper: EnumParam::new(format!("Osc{} Waveshaper", index + 1), Waveshaper::None),
             waveshaper_amount: FloatParam::new(
                 format!("Osc{} Waveshape Amount", index + 1),
@@ -228,46 +419,266 @@ impl OscillatorParams {
             )
             .with_unit("%")
             .with_smoother(SmoothingStyle::Linear(SMOOTH_TIME)),
+            pitch_env_amount: FloatParam::new(
+                format!("Osc{} Pitch Env. Amount", index + 1),
+                0.0,
+                PITCH_ENVELOPE_RANGE,
+            )
+            .with_unit(" st"),
+            pitch_env_attack: FloatParam::new(
+                format!("Osc{} Pitch Env. Attack", index + 1),
+                0.0,
+                ATTACK_DECAY_RANGE,
+            )
+            .with_unit(" s"),
+            pitch_env_decay: FloatParam::new(
+                format!("Osc{} Pitch Env. Decay", index + 1),
+                0.1,
+                ATTACK_DECAY_RANGE,
+            )
+            .with_unit(" s"),
+            pitch_env_sustain: FloatParam::new(
+                format!("Osc{} Pitch Env. Sustain", index + 1),
+                0.0,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            ),
+            pitch_env_release: FloatParam::new(
+                format!("Osc{} Pitch Env. Release", index + 1),
+                0.1,
+                RELEASE_RANGE,
+            )
+            .with_unit(" s"),
+            pitch_env_attack_curve: curve_param(
+                format!("Osc{} Pitch Env. Atk. Curve", index + 1),
+                1.0,
+            ),
+            pitch_env_decay_curve: curve_param(
+                format!("Osc{} Pitch Env. Dec. Curve", index + 1),
+                2.0,
+            ),
+            pitch_env_release_curve: curve_param(
+                format!("Osc{} Pitch Env. Rls. Curve", index + 1),
+                2.0,
+            ),
         }
     }
+    /// Every parameter of the oscillator. The order is the same for each oscillator, so the
+    /// lists of two oscillators can be zipped together to copy one onto the other.
+    pub fn param_ptrs(&self) -> Vec<ParamPtr> {
+        self.param_map()
+            .into_iter()
+            .map(|(_, param, _)| param)
+            .collect()
+    }
     pub fn to_osc_params(
         &self,
         sample_rate: f32,
         octave_stretch: f32,
         portamento_time: f32,
-        block_size: u32,
+        block_size: Option<u32>,
+        offsets: &MacroOffsets,
     ) -> crate::voice::OscParams {
         crate::voice::OscParams {
-            output_gain: self.amp.smoothed.next_step(block_size) / 100.0,
+            output_gain: offsets.smoothed(&self.amp, block_size) / 100.0,
+            output_bus: offsets.value(&self.output_bus),
             sample_rate,
-            coarse: self.coarse.value(),
-            fine: self.fine.value(),
-            frequency_mult: self.freq_mult.value() / self.freq_div.value(),
-            hz_detune: self.hz_detune.value(),
-            phase_offset: self.phase_offset.smoothed.next_step(block_size) / 180.0 * PI,
-            phase_rand: self.phase_rand.value(),
-            attack_level: self.attack_level.value(),
-            release_level: self.release_level.value(),
-            delay: self.delay.value(),
-            attack: self.attack.value(),
-            hold: self.hold.value(),
-            decay: self.decay.value(),
-            sustain: self.sustain.value(),
-            release: self.release.value(),
+            coarse: offsets.value(&self.coarse),
+            fine: offsets.value(&self.fine),
+            frequency_mult: offsets.value(&self.freq_mult) / offsets.value(&self.freq_div),
+            frequency_mode: offsets.value(&self.frequency_mode),
+            fixed_frequency: offsets
+                .value(&self.fixed_range)
+                .frequency(offsets.value(&self.fixed_fine)),
+            hz_detune: offsets.value(&self.hz_detune),
+            phase_offset: offsets.smoothed(&self.phase_offset, block_size) / 180.0 * PI,
+            phase_rand: offsets.value(&self.phase_rand),
+            attack_level: offsets.value(&self.attack_level),
+            release_level: offsets.value(&self.release_level),
+            delay: offsets.value(&self.delay),
+            attack: offsets.value(&self.attack),
+            hold: offsets.value(&self.hold),
+            decay: offsets.value(&self.decay),
+            sustain: offsets.value(&self.sustain),
+            release: offsets.value(&self.release),
+            attack_curve: offsets.value(&self.attack_curve),
+            decay_curve: offsets.value(&self.decay_curve),
+            release_curve: offsets.value(&self.release_curve),
+            envelope_mode: offsets.value(&self.envelope_mode),
+            multi_stage_times: self.multi_stage.times(offsets),
+            multi_stage_levels: self.multi_stage.levels(offsets),
+            multi_stage_sustain: offsets.value(&self.multi_stage.sustain_point) as usize - 1,
+            multi_stage_loop: match offsets.value(&self.multi_stage.loop_point) {
+                0 => None,
+                point => Some(point as usize - 1),
+            },
             feedback: {
-                let feedback = self.feedback.smoothed.next_step(block_size);
+                let feedback = offsets.smoothed(&self.feedback, block_size);
                 feedback.signum() * feedback.powi(2)
             },
-            velocity_sensitivity: self.velocity_sensitivity.value(),
-            keyscaling: self.keyscaling.value(),
+            velocity_sensitivity: offsets.value(&self.velocity_sensitivity),
+            velocity_attack: offsets.value(&self.velocity_attack),
+            velocity_feedback: offsets.value(&self.velocity_feedback),
+            keyscaling: offsets.value(&self.keyscaling),
+            rate_scaling: offsets.value(&self.rate_scaling),
+            breakpoint: offsets.value(&self.breakpoint) as f32,
+            left_depth: offsets.value(&self.left_depth),
+            left_curve: offsets.value(&self.left_curve),
+            right_depth: offsets.value(&self.right_depth),
+            right_curve: offsets.value(&self.right_curve),
             octave_stretch,
-            waveshaper: self.waveshaper.value(),
-            waveshaper_amount: self.waveshaper_amount.smoothed.next_step(block_size),
-            phaseshaper: self.phaseshaper.value(),
-            phaseshaper_amount: self.phaseshaper_amount.smoothed.next_step(block_size),
+            waveform: offsets.value(&self.waveform),
+            wavetable_position: offsets.smoothed(&self.wavetable_position, block_size) / 100.0,
+            noise_type: offsets.value(&self.noise_type),
+            noise_tone: offsets.smoothed(&self.noise_tone, block_size) / 100.0,
+            waveshaper: offsets.value(&self.waveshaper),
+            waveshaper_amount: offsets.smoothed(&self.waveshaper_amount, block_size),
+            phaseshaper: offsets.value(&self.phaseshaper),
+            phaseshaper_amount: offsets.smoothed(&self.phaseshaper_amount, block_size),
             portamento_time,
+            pitch_env_amount: offsets.value(&self.pitch_env_amount),
+            pitch_env_attack: offsets.value(&self.pitch_env_attack),
+            pitch_env_decay: offsets.value(&self.pitch_env_decay),
+            pitch_env_sustain: offsets.value(&self.pitch_env_sustain),
+            pitch_env_release: offsets.value(&self.pitch_env_release),
+            pitch_env_attack_curve: offsets.value(&self.pitch_env_attack_curve),
+            pitch_env_decay_curve: offsets.value(&self.pitch_env_decay_curve),
+            pitch_env_release_curve: offsets.value(&self.pitch_env_release_curve),
+        }
+    }
+}
+
+/// Points of the optional multi-stage envelope. Each point's level is reached `time` seconds
+/// after the previous point, starting from the oscillator's attack level.
+#[derive(Params)]
+pub struct MultiStageParams {
+    #[id = "time1"]
+    pub time1: FloatParam,
+    #[id = "time2"]
+    pub time2: FloatParam,
+    #[id = "time3"]
+    pub time3: FloatParam,
+    #[id = "time4"]
+    pub time4: FloatParam,
+    #[id = "time5"]
+    pub time5: FloatParam,
+    #[id = "time6"]
+    pub time6: FloatParam,
+    #[id = "time7"]
+    pub time7: FloatParam,
+    #[id = "time8"]
+    pub time8: FloatParam,
+    #[id = "level1"]
+    pub level1: FloatParam,
+    #[id = "level2"]
+    pub level2: FloatParam,
+    #[id = "level3"]
+    pub level3: FloatParam,
+    #[id = "level4"]
+    pub level4: FloatParam,
+    #[id = "level5"]
+    pub level5: FloatParam,
+    #[id = "level6"]
+    pub level6: FloatParam,
+    #[id = "level7"]
+    pub level7: FloatParam,
+    #[id = "level8"]
+    pub level8: FloatParam,
+    #[id = "sustain_point"]
+    pub sustain_point: IntParam,
+    #[id = "loop_point"]
+    pub loop_point: IntParam,
+}
+impl MultiStageParams {
+    pub fn new(index: usize) -> Self {
+        let time = |point: usize, default: f32| {
+            FloatParam::new(
+                format!("Osc{} MSEG Time {}", index + 1, point),
+                default,
+                ATTACK_DECAY_RANGE,
+            )
+            .with_unit(" s")
+        };
+        let level = |point: usize, default: f32| {
+            FloatParam::new(
+                format!("Osc{} MSEG Level {}", index + 1, point),
+                default,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            )
+        };
+        Self {
+            time1: time(1, 0.01),
+            time2: time(2, 0.3),
+            time3: time(3, 0.5),
+            time4: time(4, 0.0),
+            time5: time(5, 0.0),
+            time6: time(6, 0.0),
+            time7: time(7, 0.0),
+            time8: time(8, 0.0),
+            level1: level(1, 1.0),
+            level2: level(2, 0.5),
+            level3: level(3, 0.0),
+            level4: level(4, 0.0),
+            level5: level(5, 0.0),
+            level6: level(6, 0.0),
+            level7: level(7, 0.0),
+            level8: level(8, 0.0),
+            sustain_point: IntParam::new(
+                format!("Osc{} MSEG Sustain", index + 1),
+                2,
+                // The last point is where the release ends
+                IntRange::Linear {
+                    min: 1,
+                    max: MULTI_STAGE_POINTS as i32 - 1,
+                },
+            ),
+            loop_point: IntParam::new(
+                format!("Osc{} MSEG Loop", index + 1),
+                0,
+                IntRange::Linear {
+                    min: 0,
+                    max: MULTI_STAGE_POINTS as i32,
+                },
+            )
+            .with_value_to_string(Arc::new(|value| match value {
+                0 => String::from("Off"),
+                point => point.to_string(),
+            }))
+            .with_string_to_value(Arc::new(|string| match string.trim() {
+                "Off" | "off" => Some(0),
+                point => point.parse().ok(),
+            })),
         }
     }
+    pub fn time_params(&self) -> [&FloatParam; MULTI_STAGE_POINTS] {
+        [
+            &self.time1,
+            &self.time2,
+            &self.time3,
+            &self.time4,
+            &self.time5,
+            &self.time6,
+            &self.time7,
+            &self.time8,
+        ]
+    }
+    pub fn level_params(&self) -> [&FloatParam; MULTI_STAGE_POINTS] {
+        [
+            &self.level1,
+            &self.level2,
+            &self.level3,
+            &self.level4,
+            &self.level5,
+            &self.level6,
+            &self.level7,
+            &self.level8,
+        ]
+    }
+    pub fn times(&self, offsets: &MacroOffsets) -> [f32; MULTI_STAGE_POINTS] {
+        self.time_params().map(|param| offsets.value(param))
+    }
+    pub fn levels(&self, offsets: &MacroOffsets) -> [f32; MULTI_STAGE_POINTS] {
+        self.level_params().map(|param| offsets.value(param))
+    }
 }
 
 #[derive(Params)]
@@ -288,6 +699,24 @@ pub struct OscMod {
     pub by_osc7: FloatParam,
     #[id = "by_osc8"]
     pub by_osc8: FloatParam,
+    #[id = "mode_by_osc1"]
+    pub mode_by_osc1: EnumParam<MatrixMode>,
+    #[id = "mode_by_osc2"]
+    pub mode_by_osc2: EnumParam<MatrixMode>,
+    #[id = "mode_by_osc3"]
+    pub mode_by_osc3: EnumParam<MatrixMode>,
+    #[id = "mode_by_osc4"]
+    pub mode_by_osc4: EnumParam<MatrixMode>,
+    #[id = "mode_by_osc5"]
+    pub mode_by_osc5: EnumParam<MatrixMode>,
+    #[id = "mode_by_osc6"]
+    pub mode_by_osc6: EnumParam<MatrixMode>,
+    #[id = "mode_by_osc7"]
+    pub mode_by_osc7: EnumParam<MatrixMode>,
+    #[id = "mode_by_osc8"]
+    pub mode_by_osc8: EnumParam<MatrixMode>,
+    #[id = "by_input"]
+    pub by_input: FloatParam,
 }
 impl OscMod {
     pub fn new(target_id: usize) -> Self {
@@ -300,26 +729,79 @@ impl OscMod {
             by_osc6: FloatParam::new(format!("Mod Osc{target_id} by Osc6"), 0.0, FM_RANGE),
             by_osc7: FloatParam::new(format!("Mod Osc{target_id} by Osc7"), 0.0, FM_RANGE),
             by_osc8: FloatParam::new(format!("Mod Osc{target_id} by Osc8"), 0.0, FM_RANGE),
+            mode_by_osc1: EnumParam::new(format!("Mode Osc{target_id} by Osc1"), MatrixMode::Phase),
+            mode_by_osc2: EnumParam::new(format!("Mode Osc{target_id} by Osc2"), MatrixMode::Phase),
+            mode_by_osc3: EnumParam::new(format!("Mode Osc{target_id} by Osc3"), MatrixMode::Phase),
+            mode_by_osc4: EnumParam::new(format!("Mode Osc{target_id} by Osc4"), MatrixMode::Phase),
+            mode_by_osc5: EnumParam::new(format!("Mode Osc{target_id} by Osc5"), MatrixMode::Phase),
+            mode_by_osc6: EnumParam::new(format!("Mode Osc{target_id} by Osc6"), MatrixMode::Phase),
+            mode_by_osc7: EnumParam::new(format!("Mode Osc{target_id} by Osc7"), MatrixMode::Phase),
+            mode_by_osc8: EnumParam::new(format!("Mode Osc{target_id} by Osc8"), MatrixMode::Phase),
+            by_input: FloatParam::new(format!("Mod Osc{target_id} by Input"), 0.0, FM_RANGE),
         }
     }
-    pub fn to_array(&self) -> [f32; 8] {
+    pub fn to_array(&self, offsets: &MacroOffsets) -> [f32; 8] {
+        self.amount_params().map(|param| offsets.value(param))
+    }
+    pub fn amount_params(&self) -> [&FloatParam; 8] {
         [
-            self.by_osc1.value(),
-            self.by_osc2.value(),
-            self.by_osc3.value(),
-            self.by_osc4.value(),
-            self.by_osc5.value(),
-            self.by_osc6.value(),
-            self.by_osc7.value(),
-            self.by_osc8.value(),
+            &self.by_osc1,
+            &self.by_osc2,
+            &self.by_osc3,
+            &self.by_osc4,
+            &self.by_osc5,
+            &self.by_osc6,
+            &self.by_osc7,
+            &self.by_osc8,
         ]
     }
+    pub fn mode_params(&self) -> [&EnumParam<MatrixMode>; 8] {
+        [
+            &self.mode_by_osc1,
+            &self.mode_by_osc2,
+            &self.mode_by_osc3,
+            &self.mode_by_osc4,
+            &self.mode_by_osc5,
+            &self.mode_by_osc6,
+            &self.mode_by_osc7,
+            &self.mode_by_osc8,
+        ]
+    }
+    pub fn modes(&self, offsets: &MacroOffsets) -> [MatrixMode; 8] {
+        self.mode_params().map(|param| offsets.value(param))
+    }
 }
 
 #[derive(Params)]
 pub struct SynthPluginParams {
     #[persist = "editor-state"]
     pub(crate) editor_state: Arc<IcedState>,
+    /// Wavetables loaded from disk, stored with the plugin state since the files may move.
+    #[persist = "wavetables"]
+    pub(crate) wavetables: Arc<RwLock<WavetableBank>>,
+    /// What each macro controls, saved with the patch.
+    #[persist = "macro-mappings"]
+    pub(crate) macro_mappings: Arc<RwLock<Mappings<MacroMapping>>>,
+    /// MIDI controllers learned by the parameters, also saved with the patch.
+    #[persist = "midi-cc-mappings"]
+    pub(crate) cc_mappings: Arc<RwLock<Mappings<CcMapping>>>,
+
+    #[id = "macro1"]
+    pub macro1: FloatParam,
+    #[id = "macro2"]
+    pub macro2: FloatParam,
+    #[id = "macro3"]
+    pub macro3: FloatParam,
+    #[id = "macro4"]
+    pub macro4: FloatParam,
+    #[id = "macro5"]
+    pub macro5: FloatParam,
+    #[id = "macro6"]
+    pub macro6: FloatParam,
+    #[id = "macro7"]
+    pub macro7: FloatParam,
+    #[id = "macro8"]
+    pub macro8: FloatParam,
 
     #[id = "gain"]
     pub gain: FloatParam,
@@ -340,6 +822,21 @@ pub struct SynthPluginParams {
     pub legato: EnumParam<LegatoMode>,
     #[id = "portamento"]
     pub portamento: FloatParam,
+    #[id = "velocity_curve"]
+    pub velocity_curve: EnumParam<VelocityCurve>,
+    #[id = "velocity_curve_amount"]
+    pub velocity_curve_amount: FloatParam,
+    #[id = "zero_delay"]
+    pub zero_delay: BoolParam,
+
+    #[id = "sidechain_gain"]
+    pub sidechain_gain: FloatParam,
+    #[id = "sidechain_mode"]
+    pub sidechain_mode: EnumParam<SidechainMode>,
+    #[id = "sidechain_attack"]
+    pub sidechain_attack: FloatParam,
+    #[id = "sidechain_release"]
+    pub sidechain_release: FloatParam,
 
     #[nested(group = "mod", id_prefix = "mod_osc1_")]
     pub osc1_fm_mod: OscMod,
@@ -395,6 +892,14 @@ pub struct SynthPluginParams {
     pub filter_envelope_sustain: FloatParam,
     #[id = "filter_envelope_release"]
     pub filter_envelope_release: FloatParam,
+    #[id = "filter_envelope_attack_curve"]
+    pub filter_envelope_attack_curve: FloatParam,
+    #[id = "filter_envelope_decay_curve"]
+    pub filter_envelope_decay_curve: FloatParam,
+    #[id = "filter_envelope_release_curve"]
+    pub filter_envelope_release_curve: FloatParam,
+    #[id = "filter_envelope_rate_scaling"]
+    pub filter_envelope_rate_scaling: FloatParam,
 
     #[id = "global_attack"]
     pub global_attack: FloatParam,
@@ -404,12 +909,50 @@ pub struct SynthPluginParams {
     pub global_sustain: FloatParam,
     #[id = "global_release"]
     pub global_release: FloatParam,
+    #[id = "global_attack_curve"]
+    pub global_attack_curve: FloatParam,
+    #[id = "global_decay_curve"]
+    pub global_decay_curve: FloatParam,
+    #[id = "global_release_curve"]
+    pub global_release_curve: FloatParam,
+    #[id = "global_rate_scaling"]
+    pub global_rate_scaling: FloatParam,
+
+    #[id = "pitch_envelope_amount"]
+    pub pitch_envelope_amount: FloatParam,
+    #[id = "pitch_envelope_attack"]
+    pub pitch_envelope_attack: FloatParam,
+    #[id = "pitch_envelope_decay"]
+    pub pitch_envelope_decay: FloatParam,
+    #[id = "pitch_envelope_sustain"]
+    pub pitch_envelope_sustain: FloatParam,
+    #[id = "pitch_envelope_release"]
+    pub pitch_envelope_release: FloatParam,
+    #[id = "pitch_envelope_attack_curve"]
+    pub pitch_envelope_attack_curve: FloatParam,
+    #[id = "pitch_envelope_decay_curve"]
+    pub pitch_envelope_decay_curve: FloatParam,
+    #[id = "pitch_envelope_release_curve"]
+    pub pitch_envelope_release_curve: FloatParam,
 }
 
 impl Default for SynthPluginParams {
     fn default() -> Self {
         Self {
             editor_state: editor::default_state(),
+            wavetables: Default::default(),
+            macro_mappings: Default::default(),
+            cc_mappings: Default::default(),
+
+            macro1: macro_param(1),
+            macro2: macro_param(2),
+            macro3: macro_param(3),
+            macro4: macro_param(4),
+            macro5: macro_param(5),
+            macro6: macro_param(6),
+            macro7: macro_param(7),
+            macro8: macro_param(8),
+
             // This gain is stored as linear gain. NIH-plug comes with useful conversion functions
             // to treat these kinds of parameters as if we were dealing with decibels. Storing this
             // as decibels is easier to work with, but requires a conversion for every sample.
@@ -483,6 +1026,35 @@ impl Default for SynthPluginParams {
                 },
             )
             .with_unit(" s"),
+            velocity_curve: EnumParam::new("Velocity Curve", VelocityCurve::Linear),
+            velocity_curve_amount: FloatParam::new(
+                "Custom Velocity Curve",
+                0.0,
+                FloatRange::Linear {
+                    min: -1.0,
+                    max: 1.0,
+                },
+            ),
+            zero_delay: BoolParam::new("Zero-Delay Matrix", false),
+
+            sidechain_gain: FloatParam::new(
+                "Sidechain Gain",
+                util::db_to_gain(0.0),
+                FloatRange::Skewed {
+                    min: util::db_to_gain(-30.0),
+                    max: util::db_to_gain(30.0),
+                    factor: FloatRange::gain_skew_factor(-30.0, 30.0),
+                },
+            )
+            .with_smoother(SmoothingStyle::Logarithmic(50.0))
+            .with_unit(" dB")
+            .with_value_to_string(formatters::v2s_f32_gain_to_db(2))
+            .with_string_to_value(formatters::s2v_f32_gain_to_db()),
+            sidechain_mode: EnumParam::new("Sidechain Mode", SidechainMode::Audio),
+            sidechain_attack: FloatParam::new("Sidechain Attack", 0.005, FOLLOWER_RANGE)
+                .with_unit(" s"),
+            sidechain_release: FloatParam::new("Sidechain Release", 0.1, FOLLOWER_RANGE)
+                .with_unit(" s"),
 
             osc1_fm_mod: OscMod::new(1),
             osc2_fm_mod: OscMod::new(2),
@@ -546,6 +1118,14 @@ impl Default for SynthPluginParams {
             ),
             filter_envelope_release: FloatParam::new("Filter Env. Release", 0.05, RELEASE_RANGE)
                 .with_unit(" s"),
+            filter_envelope_attack_curve: curve_param("Filter Env. Atk. Curve", 1.0),
+            filter_envelope_decay_curve: curve_param("Filter Env. Dec. Curve", 2.0),
+            filter_envelope_release_curve: curve_param("Filter Env. Rls. Curve", 2.0),
+            filter_envelope_rate_scaling: FloatParam::new(
+                "Filter Env. Rate Scaling",
+                0.0,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            ),
 
             global_attack: FloatParam::new("Global Attack", 0.0, ATTACK_DECAY_RANGE)
                 .with_unit(" s"),
@@ -556,6 +1136,135 @@ impl Default for SynthPluginParams {
                 FloatRange::Linear { min: 0.0, max: 1.0 },
             ),
             global_release: FloatParam::new("Global Release", 0.05, RELEASE_RANGE).with_unit(" s"),
+            global_attack_curve: curve_param("Global Atk. Curve", 1.0),
+            global_decay_curve: curve_param("Global Dec. Curve", 2.0),
+            global_release_curve: curve_param("Global Rls. Curve", 2.0),
+            global_rate_scaling: FloatParam::new(
+                "Global Rate Scaling",
+                0.0,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            ),
+
+            pitch_envelope_amount: FloatParam::new("Pitch Env. Amount", 0.0, PITCH_ENVELOPE_RANGE)
+                .with_unit(" st"),
+            pitch_envelope_attack: FloatParam::new("Pitch Env. Attack", 0.0, ATTACK_DECAY_RANGE)
+                .with_unit(" s"),
+            pitch_envelope_decay: FloatParam::new("Pitch Env. Decay", 0.1, ATTACK_DECAY_RANGE)
+                .with_unit(" s"),
+            pitch_envelope_sustain: FloatParam::new(
+                "Pitch Env. Sustain",
+                0.0,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            ),
+            pitch_envelope_release: FloatParam::new("Pitch Env. Release", 0.1, RELEASE_RANGE)
+                .with_unit(" s"),
+            pitch_envelope_attack_curve: curve_param("Pitch Env. Atk. Curve", 1.0),
+            pitch_envelope_decay_curve: curve_param("Pitch Env. Dec. Curve", 2.0),
+            pitch_envelope_release_curve: curve_param("Pitch Env. Rls. Curve", 2.0),
         }
     }
 }
+impl SynthPluginParams {
+    pub fn oscillators(&self) -> [&OscillatorParams; 8] {
+        [
+            &self.osc1_params,
+            &self.osc2_params,
+            &self.osc3_params,
+            &self.osc4_params,
+            &self.osc5_params,
+            &self.osc6_params,
+            &self.osc7_params,
+            &self.osc8_params,
+        ]
+    }
+    pub fn macros(&self) -> [&FloatParam; MACRO_COUNT] {
+        [
+            &self.macro1,
+            &self.macro2,
+            &self.macro3,
+            &self.macro4,
+            &self.macro5,
+            &self.macro6,
+            &self.macro7,
+            &self.macro8,
+        ]
+    }
+    /// Reads everything the voices need for one block, with the macros' `offsets` applied.
+    /// `block_size` is how far the smoothed parameters advance, `None` leaves them alone, see
+    /// [`MacroOffsets::smoothed()`].
+    pub fn engine_params(
+        &self,
+        sample_rate: f32,
+        block_size: Option<u32>,
+        offsets: &MacroOffsets,
+    ) -> (OscParamsBatch, VoiceParams, ModMatrix) {
+        let osc_params = self.oscillators().map(|osc| {
+            osc.to_osc_params(
+                sample_rate,
+                offsets.value(&self.octave_stretch),
+                offsets.value(&self.portamento),
+                block_size,
+                offsets,
+            )
+        });
+
+        let voice_params = VoiceParams {
+            sample_rate,
+            filter_enabled: offsets.value(&self.filter_enabled),
+            filter_type: offsets.value(&self.filter_type),
+            filter_cutoff: offsets.value(&self.filter_cutoff),
+            filter_resonance: offsets.value(&self.filter_resonance),
+            filter_envelope_amount: offsets.value(&self.filter_envelope_amount),
+            filter_attack: offsets.value(&self.filter_envelope_attack),
+            filter_decay: offsets.value(&self.filter_envelope_decay),
+            filter_sustain: offsets.value(&self.filter_envelope_sustain),
+            filter_release: offsets.value(&self.filter_envelope_release),
+            filter_attack_curve: offsets.value(&self.filter_envelope_attack_curve),
+            filter_decay_curve: offsets.value(&self.filter_envelope_decay_curve),
+            filter_release_curve: offsets.value(&self.filter_envelope_release_curve),
+            filter_keytrack: offsets.value(&self.filter_keytrack),
+            filter_rate_scaling: offsets.value(&self.filter_envelope_rate_scaling),
+            global_attack: offsets.value(&self.global_attack),
+            global_decay: offsets.value(&self.global_decay),
+            global_sustain: offsets.value(&self.global_sustain),
+            global_release: offsets.value(&self.global_release),
+            global_attack_curve: offsets.value(&self.global_attack_curve),
+            global_decay_curve: offsets.value(&self.global_decay_curve),
+            global_release_curve: offsets.value(&self.global_release_curve),
+            global_rate_scaling: offsets.value(&self.global_rate_scaling),
+            pitch_envelope_amount: offsets.value(&self.pitch_envelope_amount),
+            pitch_attack: offsets.value(&self.pitch_envelope_attack),
+            pitch_decay: offsets.value(&self.pitch_envelope_decay),
+            pitch_sustain: offsets.value(&self.pitch_envelope_sustain),
+            pitch_release: offsets.value(&self.pitch_envelope_release),
+            pitch_attack_curve: offsets.value(&self.pitch_envelope_attack_curve),
+            pitch_decay_curve: offsets.value(&self.pitch_envelope_decay_curve),
+            pitch_release_curve: offsets.value(&self.pitch_envelope_release_curve),
+        };
+        let matrix_amounts = self.fm_mods().map(|fm_mod| fm_mod.to_array(offsets));
+
+        let mut osc_params = OscParamsBatch::from(osc_params);
+        osc_params.coarse += f32x8::splat(offsets.value(&self.global_coarse));
+
+        let mod_matrix = ModMatrix::new(
+            matrix_amounts,
+            self.fm_mods().map(|fm_mod| fm_mod.modes(offsets)),
+            self.fm_mods().map(|fm_mod| offsets.value(&fm_mod.by_input)),
+            offsets.value(&self.zero_delay),
+        );
+        (osc_params, voice_params, mod_matrix)
+    }
+    /// The rows of the modulation matrix, indexed by target.
+    pub fn fm_mods(&self) -> [&OscMod; 8] {
+        [
+            &self.osc1_fm_mod,
+            &self.osc2_fm_mod,
+            &self.osc3_fm_mod,
+            &self.osc4_fm_mod,
+            &self.osc5_fm_mod,
+            &self.osc6_fm_mod,
+            &self.osc7_fm_mod,
+            &self.osc8_fm_mod,
+        ]
+    }
+}
@@ -89,6 +89,11 @@ impl SvfSimper {
         self.a2 = new.a2;
         self.a3 = new.a3;
     }
+    /// Clears the filter's state, leaving its settings.
+    pub fn reset(&mut self) {
+        self.ic1eq = 0.0;
+        self.ic2eq = 0.0;
+    }
     #[inline]
     pub fn process(&mut self, input: f32) -> f32 {
         let v3 = input - self.ic2eq;
@@ -9,8 +9,15 @@ use nih_plug::params::enums::Enum;
 use wide::*;
 
 use crate::{
-    dsp::{interpolation::{lerp, lerpx8}, approximation::{exp2_taylor5, exp2_taylor5_x8}},
+    algorithm::EvaluationOrder,
+    dsp::{
+        approximation::{exp2_taylor5, exp2_taylor5_x8},
+        bandlimit,
+        interpolation::{lerp, lerpx8},
+        noise::NoiseGenerator,
+    },
     svf_simper::{FilterType, SvfSimper, SvfSimperBatch},
+    wavetable::Wavetables,
 };
 
 #[derive(Debug, Clone, Copy, PartialEq, Default, Enum)]
@@ -20,6 +27,122 @@ pub enum LegatoMode {
     On,
 }
 
+/// Number of points in the multi-stage envelope.
+pub const MULTI_STAGE_POINTS: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Enum)]
+pub enum FrequencyMode {
+    /// Follows the note, pitch bend and glide.
+    #[default]
+    Ratio,
+    /// Fixed frequency set by the range and fine controls, like the DX7's FIXED mode.
+    Fixed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Enum)]
+pub enum FixedRange {
+    #[default]
+    #[name = "1 Hz"]
+    One,
+    #[name = "10 Hz"]
+    Ten,
+    #[name = "100 Hz"]
+    Hundred,
+    #[name = "1000 Hz"]
+    Thousand,
+}
+impl FixedRange {
+    /// Frequency for a DX7-style fine value from 0 to 99, which spans a decade above the range.
+    pub fn frequency(&self, fine: i32) -> f32 {
+        let range = match self {
+            FixedRange::One => 1.0,
+            FixedRange::Ten => 10.0,
+            FixedRange::Hundred => 100.0,
+            FixedRange::Thousand => 1000.0,
+        };
+        range * 10.0f32.powf(fine as f32 / 100.0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Enum)]
+pub enum EnvelopeMode {
+    #[default]
+    #[name = "DAHDSR"]
+    Dahdsr,
+    #[name = "Multi-Stage"]
+    MultiStage,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Enum)]
+pub enum VelocityCurve {
+    #[default]
+    Linear,
+    Soft,
+    Hard,
+    #[name = "S-Curve"]
+    SCurve,
+    Custom,
+}
+impl VelocityCurve {
+    /// Maps a 0-1 MIDI velocity through the curve. `amount` only affects the custom curve, which
+    /// is a power curve from `velocity^0.25` at -1 to `velocity^4` at +1.
+    pub fn apply(&self, velocity: f32, amount: f32) -> f32 {
+        let velocity = velocity.clamp(0.0, 1.0);
+        match self {
+            VelocityCurve::Linear => velocity,
+            VelocityCurve::Soft => velocity.sqrt(),
+            VelocityCurve::Hard => velocity * velocity,
+            VelocityCurve::SCurve => velocity * velocity * (3.0 - 2.0 * velocity),
+            VelocityCurve::Custom => velocity.powf((amount * 2.0).exp2()),
+        }
+    }
+}
+
+/// Shape of the level scaling on one side of an oscillator's breakpoint, as on the DX7.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Enum)]
+pub enum KeyscaleCurve {
+    #[default]
+    #[name = "-Lin"]
+    NegativeLinear,
+    #[name = "-Exp"]
+    NegativeExponential,
+    #[name = "+Exp"]
+    PositiveExponential,
+    #[name = "+Lin"]
+    PositiveLinear,
+}
+impl KeyscaleCurve {
+    /// Level change in decibels `octaves` away from the breakpoint. A `depth` of 1 gives 12 dB
+    /// per octave on the linear curves.
+    pub fn gain_db(&self, octaves: f32, depth: f32) -> f32 {
+        match self {
+            KeyscaleCurve::NegativeLinear => -depth * 12.0 * octaves,
+            KeyscaleCurve::NegativeExponential => -depth * 6.0 * (octaves.exp2() - 1.0),
+            KeyscaleCurve::PositiveExponential => depth * 6.0 * (octaves.exp2() - 1.0),
+            KeyscaleCurve::PositiveLinear => depth * 12.0 * octaves,
+        }
+    }
+}
+
+/// Gain from breakpoint level scaling, using the left depth and curve below the breakpoint and
+/// the right ones above it.
+pub fn breakpoint_gain(
+    midi_id: u8,
+    breakpoint: f32,
+    left_depth: f32,
+    left_curve: KeyscaleCurve,
+    right_depth: f32,
+    right_curve: KeyscaleCurve,
+) -> f32 {
+    let octaves = (midi_id as f32 - breakpoint) / 12.0;
+    let db = if octaves < 0.0 {
+        left_curve.gain_db(-octaves, left_depth)
+    } else {
+        right_curve.gain_db(octaves, right_depth)
+    };
+    10.0f32.powf(db.clamp(-96.0, 24.0) / 20.0)
+}
+
 #[derive(Default, Debug, Clone, Copy)]
 pub struct MidiNote {
     pub midi_index: u8,
@@ -106,12 +229,171 @@ impl Notes {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Default, Enum)]
+pub enum MatrixMode {
+    /// Phase modulation, the classic DX-style "FM".
+    #[default]
+    #[name = "PM"]
+    Phase,
+    /// Linear through-zero frequency modulation.
+    #[name = "FM"]
+    LinearFm,
+    /// Resets the target's phase whenever the source wraps around.
+    Sync,
+    /// Multiplies the target's output by the source.
+    Ring,
+}
+
+/// The modulation matrix for a block, split up by mode. Every array is indexed by target, with one
+/// lane per source.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ModMatrix {
+    pub phase: [f32x8; 8],
+    pub linear_fm: [f32x8; 8],
+    pub ring: [f32x8; 8],
+    /// Masks of the sources which hard sync each target.
+    pub sync: [f32x8; 8],
+    /// Phase modulation of each target by the sidechain input.
+    pub external: f32x8,
+    /// Set when the matrix is evaluated in order instead of with a 1-sample delay.
+    pub order: Option<EvaluationOrder>,
+    /// Whether any cell uses ring modulation or hard sync, so [`ModMatrix::input`] can skip
+    /// them when none do.
+    has_ring: bool,
+    has_sync: bool,
+}
+impl ModMatrix {
+    /// `external` is the phase modulation amount of each target by the sidechain input.
+    pub fn new(
+        amounts: [[f32; 8]; 8],
+        modes: [[MatrixMode; 8]; 8],
+        external: [f32; 8],
+        zero_delay: bool,
+    ) -> Self {
+        let amounts_for = |target: usize, mode: MatrixMode, scale: f32| {
+            f32x8::from(array::from_fn(|source| {
+                if modes[target][source] == mode {
+                    amounts[target][source] * scale
+                } else {
+                    0.0
+                }
+            }))
+        };
+        let uses = |mode: MatrixMode| {
+            (0..8).any(|target| {
+                (0..8).any(|source| {
+                    modes[target][source] == mode && amounts[target][source] != 0.0
+                })
+            })
+        };
+        Self {
+            has_ring: uses(MatrixMode::Ring),
+            has_sync: uses(MatrixMode::Sync),
+            phase: array::from_fn(|target| amounts_for(target, MatrixMode::Phase, 6.0)),
+            linear_fm: array::from_fn(|target| amounts_for(target, MatrixMode::LinearFm, 6.0)),
+            ring: array::from_fn(|target| amounts_for(target, MatrixMode::Ring, 1.0)),
+            sync: array::from_fn(|target| amounts_for(target, MatrixMode::Sync, 1.0).cmp_gt(0.0)),
+            external: f32x8::from(external) * 6.0,
+            // Sync only looks at the previous sample's phase wraps, so it doesn't need ordering
+            order: zero_delay.then(|| {
+                EvaluationOrder::new(array::from_fn(|target| {
+                    array::from_fn(|source| {
+                        amounts[target][source] > 0.0 && modes[target][source] != MatrixMode::Sync
+                    })
+                }))
+            }),
+        }
+    }
+    /// Combines the sources' previous outputs and phase wraps into each target's modulation.
+    /// `external` is the current sample of the sidechain input.
+    pub fn input(&self, previous: f32x8, wrapped: f32x8, external: f32) -> MatrixInput {
+        self.input_from([previous; 8], wrapped, external)
+    }
+    /// Like [`ModMatrix::input`], with `sources[target]` holding the source outputs that
+    /// `target` reads.
+    pub fn input_from(&self, sources: [f32x8; 8], wrapped: f32x8, external: f32) -> MatrixInput {
+        let ring = if self.has_ring {
+            // Each source fades from no effect to full ring modulation
+            f32x8::from(array::from_fn(|i| {
+                (1.0 - self.ring[i] + self.ring[i] * sources[i])
+                    .to_array()
+                    .iter()
+                    .product()
+            }))
+        } else {
+            f32x8::splat(1.0)
+        };
+        let sync = if self.has_sync {
+            f32x8::from(array::from_fn(|i| {
+                if (self.sync[i] & wrapped).any() {
+                    1.0
+                } else {
+                    0.0
+                }
+            }))
+            .cmp_eq(1.0)
+        } else {
+            f32x8::splat(0.0)
+        };
+        MatrixInput {
+            phase: f32x8::from(array::from_fn(|i| (self.phase[i] * sources[i]).reduce_add()))
+                + self.external * external,
+            linear_fm: f32x8::from(array::from_fn(|i| {
+                (self.linear_fm[i] * sources[i]).reduce_add()
+            })),
+            ring,
+            sync,
+            audio: f32x8::splat(external),
+        }
+    }
+}
+
+/// What the modulation matrix does to each oscillator for one sample.
+#[derive(Debug, Clone, Copy)]
+pub struct MatrixInput {
+    /// Phase offset in cycles.
+    pub phase: f32x8,
+    /// Frequency deviation as a multiple of the oscillator's own frequency.
+    pub linear_fm: f32x8,
+    /// Output gain from ring modulation.
+    pub ring: f32x8,
+    /// Mask of the oscillators whose phase gets reset.
+    pub sync: f32x8,
+    /// Sidechain input, played by oscillators with the Audio In waveform.
+    pub audio: f32x8,
+}
+impl Default for MatrixInput {
+    fn default() -> Self {
+        Self {
+            phase: f32x8::splat(0.0),
+            linear_fm: f32x8::splat(0.0),
+            ring: f32x8::splat(1.0),
+            sync: f32x8::splat(0.0),
+            audio: f32x8::splat(0.0),
+        }
+    }
+}
+impl MatrixInput {
+    /// Takes the oscillators in `mask` from `other`.
+    fn blend(self, mask: f32x8, other: &Self) -> Self {
+        Self {
+            phase: mask.blend(other.phase, self.phase),
+            linear_fm: mask.blend(other.linear_fm, self.linear_fm),
+            ring: mask.blend(other.ring, self.ring),
+            sync: mask.blend(other.sync, self.sync),
+            audio: mask.blend(other.audio, self.audio),
+        }
+    }
+}
+
 pub struct GlobalParams {
     pub legato: LegatoMode,
     pub voice_count: usize,
     pub unison_count: usize,
     pub unison_detune: f32,
     pub bend_range: f32,
+    pub velocity_curve: VelocityCurve,
+    pub velocity_curve_amount: f32,
 }
 impl Default for GlobalParams {
     fn default() -> Self {
@@ -121,6 +403,8 @@ impl Default for GlobalParams {
             unison_count: 1,
             unison_detune: 0.0,
             bend_range: 2.0,
+            velocity_curve: VelocityCurve::Linear,
+            velocity_curve_amount: 0.0,
         }
     }
 }
@@ -130,6 +414,8 @@ pub struct VoiceList {
     pub voices: Vec<Voice>,
     pub notes: Notes,
     pub global_params: GlobalParams,
+    /// Index of the most recently started voice, found again whenever voices start or end.
+    newest: Option<usize>,
 }
 
 impl VoiceList {
@@ -138,18 +424,38 @@ impl VoiceList {
             voices: Vec::with_capacity(MAX_VOICES),
             notes: Notes::new(),
             global_params: GlobalParams::default(),
+            newest: None,
         }
     }
     pub fn play(
         &mut self,
         osc_params: &OscParamsBatch,
         voice_params: &VoiceParams,
-        pm_matrix: [f32x8; 8],
-    ) -> f32 {
-        self.voices
-            .iter_mut()
-            .map(|v| v.play(osc_params, voice_params, pm_matrix))
-            .sum()
+        mod_matrix: &ModMatrix,
+        wavetables: &Wavetables,
+        sidechain: f32,
+    ) -> [f32; OUTPUT_BUSES] {
+        self.voices.iter_mut().fold([0.0; OUTPUT_BUSES], |mut buses, v| {
+            let voice = v.play(osc_params, voice_params, mod_matrix, wavetables, sidechain);
+            buses.iter_mut().zip(voice).for_each(|(bus, voice)| *bus += voice);
+            buses
+        })
+    }
+    /// Highest envelope level of each oscillator across every voice.
+    pub fn envelope_levels(&self) -> [f32; 8] {
+        let levels = self.voices.iter().fold(f32x8::splat(0.0), |levels, voice| {
+            levels.max(voice.oscillators.envelope_level)
+        });
+        levels.to_array()
+    }
+    /// Output of one oscillator of the most recently started voice.
+    pub fn newest_oscillator_output(&self, index: usize) -> f32 {
+        self.newest
+            .and_then(|newest| self.voices.get(newest))
+            .map_or(0.0, |voice| voice.oscillators.previous().as_array_ref()[index])
+    }
+    fn find_newest(&mut self) {
+        self.newest = (0..self.voices.len()).min_by_key(|&index| self.voices[index].age);
     }
     pub fn note_on(
         &mut self,
@@ -158,8 +464,13 @@ impl VoiceList {
         velocity: f32,
         voice_params: VoiceParams,
     ) {
+        let velocity = self
+            .global_params
+            .velocity_curve
+            .apply(velocity, self.global_params.velocity_curve_amount);
         let note_id = self.notes.add_note(midi_index, velocity);
         self.add_multiple_voices(midi_index, note_id, &osc_params, velocity, voice_params);
+        self.find_newest();
     }
     pub fn note_off(
         &mut self,
@@ -196,6 +507,7 @@ impl VoiceList {
                             .filter(|voice| voice.note_id == note.id)
                             .for_each(|voice| voice.move_to_new_note(voiceless_note.midi_index, voiceless_note.id, &osc_params)),
                     }
+                    self.find_newest();
                     return;
                 }
             }
@@ -319,8 +631,10 @@ impl VoiceList {
         .retain(|voice| !voice.is_done(osc_params, voice_params));
         let len = old_len - self.voices.len();
         self.voices.iter_mut().for_each(|voice| voice.age -= len as u32);
+        self.find_newest();
     }
     pub fn block_update(&mut self, osc_params: &OscParamsBatch, voice_params: VoiceParams) {
+        self.find_newest();
         for voice in self.voices.iter_mut() {
             let mut osc_params = osc_params.clone();
             osc_params.coarse +=
@@ -350,31 +664,55 @@ pub struct Voice {
     pub age: u32,
     pub super_index: usize,
     pub pitch_bend: f32,
-    pub filter: Option<SvfSimper>,
+    /// One filter per output bus.
+    pub filters: Option<[SvfSimper; OUTPUT_BUSES]>,
+    /// Which buses had oscillators routed to them on the last sample.
+    pub active_buses: [bool; OUTPUT_BUSES],
     pub time: u32,
     pub released_time: Option<u32>,
     pub amp_release_level: f32,
     pub filter_release_level: f32,
+    pub pitch_release_level: f32,
+    /// Envelope time multipliers from rate scaling, fixed at note start.
+    pub amp_time_scale: f32,
+    pub filter_time_scale: f32,
 }
 impl Voice {
     pub fn play(
         &mut self,
         params: &OscParamsBatch,
         voice_params: &VoiceParams,
-        pm_matrix: [f32x8; 8],
-    ) -> f32 {
+        mod_matrix: &ModMatrix,
+        wavetables: &Wavetables,
+        sidechain: f32,
+    ) -> [f32; OUTPUT_BUSES] {
         self.time += 1;
-        let matrix: [f32; 8] =
-            array::from_fn(|i| (pm_matrix[i] * self.oscillators.previous()).reduce_add());
-        let out = self
-            .oscillators
-            .step_with_envelope(params, f32x8::from(matrix))
-            .reduce_add();
-        (if let Some(filter) = self.filter.as_mut() {
-            filter.process(out)
-        } else {
-            out
-        }) * self.calc_amp_envelope(voice_params)
+        self.oscillators.pitch_offset = f32x8::splat(
+            voice_params.pitch_envelope_amount * self.calc_pitch_envelope(voice_params),
+        );
+        let out = self.oscillators.step_matrix(params, mod_matrix, wavetables, sidechain);
+        let amp = self.calc_amp_envelope(voice_params);
+        let mut buses = [0.0; OUTPUT_BUSES];
+        for (bus, mask) in params.bus_masks().into_iter().enumerate() {
+            // Skip unused buses so their filters don't cost anything
+            let active = mask.any();
+            let was_active = std::mem::replace(&mut self.active_buses[bus], active);
+            if !active {
+                continue;
+            }
+            let out = (out & mask).reduce_add();
+            buses[bus] = match self.filters.as_mut() {
+                Some(filters) => {
+                    // A skipped filter still holds the state from before the bus went unused
+                    if !was_active {
+                        filters[bus].reset();
+                    }
+                    filters[bus].process(out)
+                }
+                None => out,
+            } * amp;
+        }
+        buses
     }
     pub fn new(
         midi_id: u8,
@@ -391,7 +729,7 @@ impl Voice {
             age: 0,
             super_index,
             pitch_bend: 0.0,
-            filter: if voice_params.filter_enabled {
+            filters: if voice_params.filter_enabled {
                 let mut filter = SvfSimper::new(
                     Voice::calc_filter_cutoff(
                         midi_id,
@@ -400,7 +738,9 @@ impl Voice {
                             voice_params.sample_rate,
                             0,
                             voice_params.filter_attack,
+                            voice_params.filter_attack_curve,
                             voice_params.filter_decay,
+                            voice_params.filter_decay_curve,
                             voice_params.filter_sustain,
                         ),
                     ),
@@ -408,31 +748,48 @@ impl Voice {
                     voice_params.sample_rate,
                 );
                 filter.filter_type = voice_params.filter_type;
-                Some(filter)
+                Some([filter; OUTPUT_BUSES])
             } else {
                 None
             },
+            active_buses: [false; OUTPUT_BUSES],
             time: 0,
             released_time: None,
             amp_release_level: 0.0,
             filter_release_level: 0.0,
+            pitch_release_level: 0.0,
+            amp_time_scale: rate_scale(midi_id, voice_params.global_rate_scaling),
+            filter_time_scale: rate_scale(midi_id, voice_params.filter_rate_scaling),
         }
     }
     pub fn release(&mut self, params: &OscParamsBatch, voice_params: &VoiceParams) {
         self.amp_release_level = envelope(
-            voice_params.sample_rate,
+            voice_params.sample_rate * self.amp_time_scale,
             self.time,
             voice_params.global_attack,
+            voice_params.global_attack_curve,
             voice_params.global_decay,
+            voice_params.global_decay_curve,
             voice_params.global_sustain,
         );
         self.filter_release_level = envelope(
-            voice_params.sample_rate,
+            voice_params.sample_rate * self.filter_time_scale,
             self.time,
             voice_params.filter_attack,
+            voice_params.filter_attack_curve,
             voice_params.filter_decay,
+            voice_params.filter_decay_curve,
             voice_params.filter_sustain,
         );
+        self.pitch_release_level = envelope(
+            voice_params.sample_rate,
+            self.time,
+            voice_params.pitch_attack,
+            voice_params.pitch_attack_curve,
+            voice_params.pitch_decay,
+            voice_params.pitch_decay_curve,
+            voice_params.pitch_sustain,
+        );
         self.oscillators.release(params);
         self.released_time = Some(self.time);
     }
@@ -449,7 +806,8 @@ impl Voice {
     pub fn is_done(&self, osc_params: &OscParamsBatch, voice_params: &VoiceParams) -> bool {
         self.oscillators.is_done(osc_params)
             || if let Some(released_time) = self.time_since_release() {
-                (released_time as f32 / voice_params.sample_rate) >= voice_params.global_release
+                (released_time as f32 / (voice_params.sample_rate * self.amp_time_scale))
+                    >= voice_params.global_release
             } else {
                 false
             }
@@ -461,6 +819,7 @@ impl Voice {
         self.age = 0;
         self.released_time = None;
         self.oscillators.release_time = None;
+        self.oscillators.multi_stage.resume(osc_params);
         self.oscillators.lerp_new_pitch(&osc_params);
     }
     pub fn block_update(&mut self, osc_params: &OscParamsBatch, voice_params: VoiceParams) {
@@ -475,35 +834,39 @@ impl Voice {
                 &voice_params,
                 self.calc_filter_envelope(&voice_params),
             );
-            let filter = self.filter.get_or_insert(SvfSimper::new(
-                cutoff,
-                voice_params.filter_resonance,
-                voice_params.sample_rate,
-            ));
-            filter.set(
-                cutoff,
-                voice_params.filter_resonance,
-                voice_params.sample_rate,
+            let filters = self.filters.get_or_insert(
+                [SvfSimper::new(cutoff, voice_params.filter_resonance, voice_params.sample_rate);
+                    OUTPUT_BUSES],
             );
-            filter.set_filter_type(voice_params.filter_type);
+            for filter in filters.iter_mut() {
+                filter.set(
+                    cutoff,
+                    voice_params.filter_resonance,
+                    voice_params.sample_rate,
+                );
+                filter.set_filter_type(voice_params.filter_type);
+            }
         } else {
-            self.filter = None
+            self.filters = None
         }
     }
     fn calc_amp_envelope(&self, voice_params: &VoiceParams) -> f32 {
         if let Some(released_time) = self.released_time {
             release_envelope(
-                voice_params.sample_rate,
+                voice_params.sample_rate * self.amp_time_scale,
                 self.time - released_time,
                 voice_params.global_release,
+                voice_params.global_release_curve,
                 self.amp_release_level,
             )
         } else {
             envelope(
-                voice_params.sample_rate,
+                voice_params.sample_rate * self.amp_time_scale,
                 self.time,
                 voice_params.global_attack,
+                voice_params.global_attack_curve,
                 voice_params.global_decay,
+                voice_params.global_decay_curve,
                 voice_params.global_sustain,
             )
         }
@@ -511,21 +874,45 @@ impl Voice {
     fn calc_filter_envelope(&self, voice_params: &VoiceParams) -> f32 {
         if let Some(released_time) = self.released_time {
             release_envelope(
-                voice_params.sample_rate,
+                voice_params.sample_rate * self.filter_time_scale,
                 self.time - released_time,
                 voice_params.filter_release,
+                voice_params.filter_release_curve,
                 self.filter_release_level,
             )
         } else {
             envelope(
-                voice_params.sample_rate,
+                voice_params.sample_rate * self.filter_time_scale,
                 self.time,
                 voice_params.filter_attack,
+                voice_params.filter_attack_curve,
                 voice_params.filter_decay,
+                voice_params.filter_decay_curve,
                 voice_params.filter_sustain,
             )
         }
     }
+    fn calc_pitch_envelope(&self, voice_params: &VoiceParams) -> f32 {
+        if let Some(released_time) = self.released_time {
+            release_envelope(
+                voice_params.sample_rate,
+                self.time - released_time,
+                voice_params.pitch_release,
+                voice_params.pitch_release_curve,
+                self.pitch_release_level,
+            )
+        } else {
+            envelope(
+                voice_params.sample_rate,
+                self.time,
+                voice_params.pitch_attack,
+                voice_params.pitch_attack_curve,
+                voice_params.pitch_decay,
+                voice_params.pitch_decay_curve,
+                voice_params.pitch_sustain,
+            )
+        }
+    }
     fn calc_filter_cutoff(midi_id: u8, voice_params: &VoiceParams, envelope: f32) -> f32 {
         let keyscaling = (midi_id as f32 - 69.0) * voice_params.filter_keytrack / 12.0;
 
@@ -551,20 +938,40 @@ pub struct VoiceParams {
     pub filter_decay: f32,
     pub filter_sustain: f32,
     pub filter_release: f32,
+    pub filter_attack_curve: f32,
+    pub filter_decay_curve: f32,
+    pub filter_release_curve: f32,
     pub filter_keytrack: f32,
+    pub filter_rate_scaling: f32,
     pub global_attack: f32,
     pub global_decay: f32,
     pub global_sustain: f32,
     pub global_release: f32,
+    pub global_attack_curve: f32,
+    pub global_decay_curve: f32,
+    pub global_release_curve: f32,
+    pub global_rate_scaling: f32,
+    pub pitch_envelope_amount: f32,
+    pub pitch_attack: f32,
+    pub pitch_decay: f32,
+    pub pitch_sustain: f32,
+    pub pitch_release: f32,
+    pub pitch_attack_curve: f32,
+    pub pitch_decay_curve: f32,
+    pub pitch_release_curve: f32,
 }
 
 #[derive(Debug, Clone, Copy, Default)]
 pub struct OscParams {
     pub output_gain: f32,
+    pub output_bus: OutputBus,
     pub sample_rate: f32,
     pub coarse: f32,
     pub fine: f32,
     pub frequency_mult: f32,
+    pub frequency_mode: FrequencyMode,
+    /// Frequency in Hz used in fixed frequency mode.
+    pub fixed_frequency: f32,
     pub hz_detune: f32,
     pub phase_offset: f32,
     pub phase_rand: f32,
@@ -576,32 +983,201 @@ pub struct OscParams {
     pub decay: f32,
     pub sustain: f32,
     pub release: f32,
+    pub attack_curve: f32,
+    pub decay_curve: f32,
+    pub release_curve: f32,
+    pub envelope_mode: EnvelopeMode,
+    pub multi_stage_times: [f32; MULTI_STAGE_POINTS],
+    pub multi_stage_levels: [f32; MULTI_STAGE_POINTS],
+    /// Index of the point the multi-stage envelope holds at until release.
+    pub multi_stage_sustain: usize,
+    /// Index of the point the multi-stage envelope jumps back to after reaching the sustain point.
+    pub multi_stage_loop: Option<usize>,
     pub feedback: f32,
     pub velocity_sensitivity: f32,
+    pub velocity_attack: f32,
+    pub velocity_feedback: f32,
     pub keyscaling: f32,
+    pub rate_scaling: f32,
+    pub breakpoint: f32,
+    pub left_depth: f32,
+    pub left_curve: KeyscaleCurve,
+    pub right_depth: f32,
+    pub right_curve: KeyscaleCurve,
     pub octave_stretch: f32,
+    pub waveform: Waveform,
+    pub wavetable_position: f32,
+    pub noise_type: NoiseType,
+    /// Noise tone filter from -1 (darkest) to 1 (brightest).
+    pub noise_tone: f32,
     pub waveshaper: Waveshaper,
     pub waveshaper_amount: f32,
     pub phaseshaper: Phaseshaper,
     pub phaseshaper_amount: f32,
     pub portamento_time: f32,
+    pub pitch_env_amount: f32,
+    pub pitch_env_attack: f32,
+    pub pitch_env_decay: f32,
+    pub pitch_env_sustain: f32,
+    pub pitch_env_release: f32,
+    pub pitch_env_attack_curve: f32,
+    pub pitch_env_decay_curve: f32,
+    pub pitch_env_release_curve: f32,
+}
+
+/// Note that rate scaling starts from. Envelopes of notes at or below it keep their times.
+const RATE_SCALING_PIVOT: f32 = 69.0;
+/// Bitmask of every oscillator in a batch.
+const ALL_LANES: i32 = 0xff;
+
+/// Envelope time multiplier for rate scaling. With an `amount` of 1, envelope times halve with
+/// every octave above A4. Like the DX7's rate scaling it only ever speeds envelopes up, so low
+/// notes don't get endless releases.
+pub fn rate_scale(midi_id: u8, amount: f32) -> f32 {
+    exp2_taylor5(((RATE_SCALING_PIVOT - midi_id as f32) / 12.0 * amount).min(0.0))
 }
 
-pub fn envelope(sample_rate: f32, time: u32, attack: f32, decay: f32, sustain: f32) -> f32 {
+pub fn envelope(
+    sample_rate: f32,
+    time: u32,
+    attack: f32,
+    attack_curve: f32,
+    decay: f32,
+    decay_curve: f32,
+    sustain: f32,
+) -> f32 {
     let time = time as f32 / sample_rate;
     if time < attack {
-        time / attack
+        (time / attack).powf(attack_curve)
     } else if time < attack + decay {
-        (1.0 - ((time - attack) / decay)).powi(2) * (1.0 - sustain) + sustain
+        (1.0 - ((time - attack) / decay)).powf(decay_curve) * (1.0 - sustain) + sustain
     } else {
         sustain
     }
     .max(0.0)
 }
 
-pub fn release_envelope(sample_rate: f32, time: u32, release: f32, release_level: f32) -> f32 {
+pub fn release_envelope(
+    sample_rate: f32,
+    time: u32,
+    release: f32,
+    release_curve: f32,
+    release_level: f32,
+) -> f32 {
     let delta = time as f32 / sample_rate;
-    release_level * (1.0 - (delta as f32 / release)).max(0.0).powi(2)
+    release_level * (1.0 - (delta as f32 / release)).max(0.0).powf(release_curve)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Enum)]
+pub enum Waveform {
+    #[default]
+    Sine,
+    #[name = "Half Sine"]
+    HalfSine,
+    #[name = "Abs. Sine"]
+    AbsSine,
+    #[name = "Quarter Sine"]
+    QuarterSine,
+    #[name = "Alt. Sine"]
+    AlternatingSine,
+    #[name = "Camel Sine"]
+    CamelSine,
+    Saw,
+    Square,
+    Triangle,
+    Noise,
+    Wavetable,
+    #[name = "Audio In"]
+    AudioIn,
+}
+impl Waveform {
+    /// `phase` should be between 0 and 1, `phase_delta` is the phase increment per sample and is
+    /// used to band-limit the saw, square and triangle.
+    pub fn generate(&self, phase: f32, phase_delta: f32) -> f32 {
+        let sine = (phase * TAU).sin();
+        match self {
+            Waveform::Sine => sine,
+            Waveform::HalfSine => sine.max(0.0),
+            Waveform::AbsSine => sine.abs(),
+            Waveform::QuarterSine => {
+                if phase < 0.25 || (0.5..0.75).contains(&phase) {
+                    sine.abs()
+                } else {
+                    0.0
+                }
+            }
+            Waveform::AlternatingSine => {
+                if phase < 0.5 {
+                    (phase * 2.0 * TAU).sin()
+                } else {
+                    0.0
+                }
+            }
+            Waveform::CamelSine => {
+                if phase < 0.5 {
+                    (phase * 2.0 * TAU).sin().abs()
+                } else {
+                    0.0
+                }
+            }
+            Waveform::Saw => bandlimit::saw(phase, phase_delta),
+            Waveform::Square => bandlimit::square(phase, phase_delta),
+            Waveform::Triangle => bandlimit::triangle(phase, phase_delta),
+            // Noise needs per-oscillator state, so it's generated by the oscillator instead
+            Waveform::Noise => 0.0,
+            // Wavetables are read by the oscillator, this is only reached when none is loaded
+            Waveform::Wavetable => sine,
+            // The sidechain input is read by the oscillator
+            Waveform::AudioIn => 0.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Enum)]
+pub enum NoiseType {
+    #[default]
+    White,
+    Pink,
+    Brown,
+    Digital,
+}
+impl NoiseType {
+    pub fn generate(&self, noise: &mut NoiseGenerator, phase_delta: f32) -> f32 {
+        match self {
+            NoiseType::White => noise.white(),
+            NoiseType::Pink => noise.pink(),
+            NoiseType::Brown => noise.brown(),
+            NoiseType::Digital => noise.digital(phase_delta),
+        }
+    }
+}
+
+/// What the sidechain input is used as.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Enum)]
+pub enum SidechainMode {
+    /// The input signal itself.
+    #[default]
+    Audio,
+    /// The input's amplitude envelope.
+    #[name = "Envelope"]
+    Follower,
+}
+
+/// Main output plus the auxiliary outputs.
+pub const OUTPUT_BUSES: usize = 4;
+
+/// Which of the plugin's outputs an oscillator is heard on. Each bus gets its own copy of the
+/// voice filter and amp envelope.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Enum)]
+pub enum OutputBus {
+    #[default]
+    Main,
+    #[name = "Aux 1"]
+    Aux1,
+    #[name = "Aux 2"]
+    Aux2,
+    #[name = "Aux 3"]
+    Aux3,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Default, Enum)]
@@ -783,10 +1359,13 @@ impl Phaseshaper {
 #[derive(Debug, Clone, Copy)]
 pub struct OscParamsBatch {
     pub output_gain: f32x8,
+    pub output_bus: [OutputBus; 8],
     pub sample_rate: f32x8,
     pub coarse: f32x8,
     pub fine: f32x8,
     pub frequency_mult: f32x8,
+    pub frequency_mode: [FrequencyMode; 8],
+    pub fixed_frequency: f32x8,
     pub hz_detune: f32x8,
     pub phase_offset: f32x8,
     pub phase_rand: f32x8,
@@ -798,15 +1377,45 @@ pub struct OscParamsBatch {
     pub decay: f32x8,
     pub sustain: f32x8,
     pub release: f32x8,
+    pub attack_curve: f32x8,
+    pub decay_curve: f32x8,
+    pub release_curve: f32x8,
+    pub envelope_mode: [EnvelopeMode; 8],
+    /// Multi-stage envelope times, indexed by point with one lane per oscillator.
+    pub multi_stage_times: [f32x8; MULTI_STAGE_POINTS],
+    /// Multi-stage envelope levels, indexed by point with one lane per oscillator.
+    pub multi_stage_levels: [f32x8; MULTI_STAGE_POINTS],
+    pub multi_stage_sustain: [usize; 8],
+    pub multi_stage_loop: [Option<usize>; 8],
     pub feedback: f32x8,
     pub velocity_sensitivity: f32x8,
+    pub velocity_attack: f32x8,
+    pub velocity_feedback: f32x8,
     pub keyscaling: f32x8,
+    pub rate_scaling: f32x8,
+    pub breakpoint: f32x8,
+    pub left_depth: f32x8,
+    pub left_curve: [KeyscaleCurve; 8],
+    pub right_depth: f32x8,
+    pub right_curve: [KeyscaleCurve; 8],
     pub octave_stretch: f32x8,
+    pub waveform: [Waveform; 8],
+    pub wavetable_position: f32x8,
+    pub noise_type: [NoiseType; 8],
+    pub noise_tone: f32x8,
     pub waveshaper: [Waveshaper; 8],
     pub waveshaper_amount: f32x8,
     pub phaseshaper: [Phaseshaper; 8],
     pub phaseshaper_amount: f32x8,
     pub portamento_time: f32x8,
+    pub pitch_env_amount: f32x8,
+    pub pitch_env_attack: f32x8,
+    pub pitch_env_decay: f32x8,
+    pub pitch_env_sustain: f32x8,
+    pub pitch_env_release: f32x8,
+    pub pitch_env_attack_curve: f32x8,
+    pub pitch_env_decay_curve: f32x8,
+    pub pitch_env_release_curve: f32x8,
 }
 macro_rules! aos_to_soa {
     // The `tt` (token tree) designator is used for
@@ -828,10 +1437,13 @@ impl From<[OscParams; 8]> for OscParamsBatch {
     fn from(value: [OscParams; 8]) -> Self {
         Self {
             output_gain: f32x8::from(aos_to_soa!(value, output_gain)),
+            output_bus: aos_to_soa!(value, output_bus),
             sample_rate: f32x8::from(aos_to_soa!(value, sample_rate)),
             coarse: f32x8::from(aos_to_soa!(value, coarse)),
             fine: f32x8::from(aos_to_soa!(value, fine)),
             frequency_mult: f32x8::from(aos_to_soa!(value, frequency_mult)),
+            frequency_mode: aos_to_soa!(value, frequency_mode),
+            fixed_frequency: f32x8::from(aos_to_soa!(value, fixed_frequency)),
             hz_detune: f32x8::from(aos_to_soa!(value, hz_detune)),
             phase_offset: f32x8::from(aos_to_soa!(value, phase_offset)),
             phase_rand: f32x8::from(aos_to_soa!(value, phase_rand)),
@@ -843,18 +1455,78 @@ impl From<[OscParams; 8]> for OscParamsBatch {
             decay: f32x8::from(aos_to_soa!(value, decay)),
             sustain: f32x8::from(aos_to_soa!(value, sustain)),
             release: f32x8::from(aos_to_soa!(value, release)),
+            attack_curve: f32x8::from(aos_to_soa!(value, attack_curve)),
+            decay_curve: f32x8::from(aos_to_soa!(value, decay_curve)),
+            release_curve: f32x8::from(aos_to_soa!(value, release_curve)),
+            envelope_mode: aos_to_soa!(value, envelope_mode),
+            multi_stage_times: array::from_fn(|point| {
+                f32x8::from(value.map(|osc| osc.multi_stage_times[point]))
+            }),
+            multi_stage_levels: array::from_fn(|point| {
+                f32x8::from(value.map(|osc| osc.multi_stage_levels[point]))
+            }),
+            multi_stage_sustain: aos_to_soa!(value, multi_stage_sustain),
+            multi_stage_loop: aos_to_soa!(value, multi_stage_loop),
             feedback: f32x8::from(aos_to_soa!(value, feedback)),
             velocity_sensitivity: f32x8::from(aos_to_soa!(value, velocity_sensitivity)),
+            velocity_attack: f32x8::from(aos_to_soa!(value, velocity_attack)),
+            velocity_feedback: f32x8::from(aos_to_soa!(value, velocity_feedback)),
             keyscaling: f32x8::from(aos_to_soa!(value, keyscaling)),
+            rate_scaling: f32x8::from(aos_to_soa!(value, rate_scaling)),
+            breakpoint: f32x8::from(aos_to_soa!(value, breakpoint)),
+            left_depth: f32x8::from(aos_to_soa!(value, left_depth)),
+            left_curve: aos_to_soa!(value, left_curve),
+            right_depth: f32x8::from(aos_to_soa!(value, right_depth)),
+            right_curve: aos_to_soa!(value, right_curve),
             octave_stretch: f32x8::from(aos_to_soa!(value, octave_stretch)),
+            waveform: aos_to_soa!(value, waveform),
+            wavetable_position: f32x8::from(aos_to_soa!(value, wavetable_position)),
+            noise_type: aos_to_soa!(value, noise_type),
+            noise_tone: f32x8::from(aos_to_soa!(value, noise_tone)),
             waveshaper: aos_to_soa!(value, waveshaper),
             waveshaper_amount: f32x8::from(aos_to_soa!(value, waveshaper_amount)),
             phaseshaper: aos_to_soa!(value, phaseshaper),
             phaseshaper_amount: f32x8::from(aos_to_soa!(value, phaseshaper_amount)),
             portamento_time: f32x8::from(aos_to_soa!(value, portamento_time)),
+            pitch_env_amount: f32x8::from(aos_to_soa!(value, pitch_env_amount)),
+            pitch_env_attack: f32x8::from(aos_to_soa!(value, pitch_env_attack)),
+            pitch_env_decay: f32x8::from(aos_to_soa!(value, pitch_env_decay)),
+            pitch_env_sustain: f32x8::from(aos_to_soa!(value, pitch_env_sustain)),
+            pitch_env_release: f32x8::from(aos_to_soa!(value, pitch_env_release)),
+            pitch_env_attack_curve: f32x8::from(aos_to_soa!(value, pitch_env_attack_curve)),
+            pitch_env_decay_curve: f32x8::from(aos_to_soa!(value, pitch_env_decay_curve)),
+            pitch_env_release_curve: f32x8::from(aos_to_soa!(value, pitch_env_release_curve)),
         }
     }
 }
+impl OscParamsBatch {
+    /// Lanes of the oscillators which use the multi-stage envelope instead of the DAHDSR one.
+    pub fn multi_stage_mask(&self) -> f32x8 {
+        f32x8::from(
+            self.envelope_mode
+                .map(|mode| if mode == EnvelopeMode::MultiStage { 1.0 } else { 0.0 }),
+        )
+        .cmp_eq(1.0)
+    }
+    /// Lanes of the oscillators routed to each output bus.
+    pub fn bus_masks(&self) -> [f32x8; OUTPUT_BUSES] {
+        array::from_fn(|bus| {
+            f32x8::from(
+                self.output_bus
+                    .map(|output_bus| if output_bus as usize == bus { 1.0 } else { 0.0 }),
+            )
+            .cmp_eq(1.0)
+        })
+    }
+    /// Lanes of the oscillators in fixed frequency mode.
+    pub fn fixed_frequency_mask(&self) -> f32x8 {
+        f32x8::from(
+            self.frequency_mode
+                .map(|mode| if mode == FrequencyMode::Fixed { 1.0 } else { 0.0 }),
+        )
+        .cmp_eq(1.0)
+    }
+}
 
 #[derive(Debug, Clone, Copy)]
 pub struct OscillatorBatch {
@@ -863,12 +1535,27 @@ pub struct OscillatorBatch {
     frequency_lerp: f32x8,
     midi_id: u8,
     phase: f32x8,
+    wrapped: f32x8,
     time: f32x8,
     release_time: Option<f32x8>,
     release_start_level: f32x8,
+    pitch_release_start_level: f32x8,
+    multi_stage: MultiStageEnvelope,
+    /// Envelope time multiplier from rate scaling.
+    time_scale: f32x8,
+    /// Attack time multiplier from velocity.
+    attack_scale: f32x8,
+    /// Feedback added by velocity.
+    feedback_offset: f32x8,
+    noise: [NoiseGenerator; 8],
     previous_wave: [f32x8; 2],
     previous_output: f32x8,
+    /// Envelope level from the last step, read by the editor's activity lights.
+    pub envelope_level: f32x8,
     pub gain: f32x8,
+    /// Pitch offset in semitones applied on top of each oscillator's own pitch envelope, used
+    /// for the voice's global pitch envelope.
+    pub pitch_offset: f32x8,
 }
 
 impl OscillatorBatch {
@@ -876,52 +1563,111 @@ impl OscillatorBatch {
         let frequency = OscillatorBatch::get_pitch(midi_id, params);
         let keyscaling = f32x8::splat(2.0f32)
             .pow_f32x8(f32x8::splat(midi_id as f32 - 69.0) * -params.keyscaling / 12.0);
+        // Up to 8 times shorter (or longer for negative amounts) at full velocity
+        let attack_scale = exp2_taylor5_x8(-params.velocity_attack * velocity * 3.0);
+        let breakpoint_scaling = f32x8::from(array::from_fn(|i| {
+            breakpoint_gain(
+                midi_id,
+                params.breakpoint.as_array_ref()[i],
+                params.left_depth.as_array_ref()[i],
+                params.left_curve[i],
+                params.right_depth.as_array_ref()[i],
+                params.right_curve[i],
+            )
+        }));
         Self {
             frequency,
             target_frequency: frequency,
             frequency_lerp: f32x8::splat(1.0),
             midi_id,
             phase: 0.0 + params.phase_rand * fastrand::f32(),
+            wrapped: f32x8::splat(0.0),
             time: f32x8::splat(0.0),
             release_time: None,
             release_start_level: f32x8::splat(0.0),
+            pitch_release_start_level: f32x8::splat(0.0),
+            multi_stage: MultiStageEnvelope::new(params, attack_scale),
+            time_scale: exp2_taylor5_x8(
+                (f32x8::splat(RATE_SCALING_PIVOT - midi_id as f32) / 12.0 * params.rate_scaling)
+                    .min(f32x8::splat(0.0)),
+            ),
+            attack_scale,
+            // Squared like the feedback parameter itself
+            feedback_offset: params.velocity_feedback * params.velocity_feedback.abs() * velocity,
+            noise: array::from_fn(|_| NoiseGenerator::new(fastrand::u32(..))),
             previous_wave: [f32x8::splat(0.0); 2],
             previous_output: f32x8::splat(0.0),
+            envelope_level: f32x8::splat(0.0),
             gain: (params.velocity_sensitivity * velocity + 1.0
                 - params.velocity_sensitivity.max(f32x8::splat(0.0)))
-                * keyscaling,
+                * keyscaling
+                * breakpoint_scaling,
+            pitch_offset: f32x8::splat(0.0),
         }
     }
     pub fn envelope(&self, params: &OscParamsBatch) -> f32x8 {
+        // Scaling every envelope time is the same as scaling the rate the envelope runs at
+        let sample_rate = params.sample_rate * self.time_scale;
         if let Some(released_time) = self.time_since_release() {
             Self::release_envelope(
-                params.sample_rate,
+                sample_rate,
                 released_time,
                 params.release,
+                params.release_curve,
                 self.release_start_level,
                 params.release_level,
             )
         } else {
             Self::ads_envelope(
-                params.sample_rate,
+                sample_rate,
                 self.time,
                 params.delay,
                 params.attack_level,
-                params.attack,
+                params.attack * self.attack_scale,
+                params.attack_curve,
                 params.hold,
                 params.decay,
+                params.decay_curve,
                 params.sustain,
             )
         }
     }
+    /// Pitch envelope level from 0 to 1, scaled by `pitch_env_amount` to get semitones.
+    pub fn pitch_envelope(&self, params: &OscParamsBatch) -> f32x8 {
+        if let Some(released_time) = self.time_since_release() {
+            Self::release_envelope(
+                params.sample_rate,
+                released_time,
+                params.pitch_env_release,
+                params.pitch_env_release_curve,
+                self.pitch_release_start_level,
+                f32x8::splat(0.0),
+            )
+        } else {
+            Self::ads_envelope(
+                params.sample_rate,
+                self.time,
+                f32x8::splat(0.0),
+                f32x8::splat(0.0),
+                params.pitch_env_attack,
+                params.pitch_env_attack_curve,
+                f32x8::splat(0.0),
+                params.pitch_env_decay,
+                params.pitch_env_decay_curve,
+                params.pitch_env_sustain,
+            )
+        }
+    }
     pub fn ads_envelope(
         sample_rate: f32x8,
         time: f32x8,
         delay: f32x8,
         attack_level: f32x8,
         attack: f32x8,
+        attack_curve: f32x8,
         hold: f32x8,
         decay: f32x8,
+        decay_curve: f32x8,
         sustain: f32x8,
     ) -> f32x8 {
         // let time = time. as f32 / sample_rate;
@@ -937,12 +1683,16 @@ impl OscillatorBatch {
         let attack_level = lerpx8(
             attack_level,
             f32x8::splat(1.0),
-            (time - delay).fast_max(0.0.into()) / attack,
+            ((time - delay).fast_max(0.0.into()) / attack)
+                .fast_min(1.0.into())
+                .pow_f32x8(attack_curve),
         ) & time.cmp_lt(delay + attack);
         let hold_level =
             f32x8::splat(1.0) & time.cmp_lt(delay + attack + hold) & time.cmp_ge(delay + attack);
-        let decay_curve = 1.0 - ((time - delay - attack - hold) / decay);
-        let decay_level = (decay_curve * decay_curve
+        let decay_progress = (1.0 - ((time - delay - attack - hold) / decay))
+            .fast_max(0.0.into())
+            .fast_min(1.0.into());
+        let decay_level = (decay_progress.pow_f32x8(decay_curve)
             * (1.0 - sustain)
             + sustain)
             & time.cmp_lt(delay + attack + hold + decay)
@@ -955,6 +1705,7 @@ impl OscillatorBatch {
         sample_rate: f32x8,
         time: f32x8,
         release: f32x8,
+        release_curve: f32x8,
         release_start_level: f32x8,
         release_end_level: f32x8,
     ) -> f32x8 {
@@ -965,16 +1716,18 @@ impl OscillatorBatch {
         lerpx8(
             release_end_level,
             release_start_level,
-            t * t,
+            t.pow_f32x8(release_curve),
         )
     }
     pub fn get_pitch(midi_id: u8, params: &OscParamsBatch) -> f32x8 {
-        (exp2_taylor5_x8(
+        let tracked = exp2_taylor5_x8(
             (midi_id as f32 + params.coarse + params.fine / 100.0 - 69.0)
                 / (12.0 / params.octave_stretch),
         ) * 440.0
-            * params.frequency_mult
-            + params.hz_detune)
+            * params.frequency_mult;
+        // Pitch bend and global coarse tuning are added to `coarse`, so fixed oscillators ignore
+        // them along with the note. Glide has nothing to do since the frequency never changes.
+        (params.fixed_frequency_mask().blend(params.fixed_frequency, tracked) + params.hz_detune)
             .fast_max(f32x8::splat(0.0))
     }
     pub fn update_pitch(&mut self, params: &OscParamsBatch) {
@@ -988,9 +1741,39 @@ impl OscillatorBatch {
     pub fn get_lerped_frequency(&self) -> f32x8 {
         super::dsp::interpolation::lerpx8(self.frequency, self.target_frequency, self.frequency_lerp)
     }
-    pub fn step(&mut self, params: &OscParamsBatch, pm: f32x8) -> f32x8 {
+    pub fn step(
+        &mut self,
+        params: &OscParamsBatch,
+        input: &MatrixInput,
+        wavetables: &Wavetables,
+    ) -> f32x8 {
+        let phase_delta = self.advance(params);
+        self.step_wave(params, input, wavetables, phase_delta, ALL_LANES)
+    }
+    /// Moves the time and glide on by a sample and returns the phase delta, the part of a step
+    /// that doesn't depend on the modulation matrix.
+    fn advance(&mut self, params: &OscParamsBatch) -> f32x8 {
         self.time = self.time + 1.0;
         self.frequency_lerp = (self.frequency_lerp + 1.0 / (params.portamento_time + 0.00001) / params.sample_rate).fast_min(f32x8::splat(1.0));
+        let pitch_mod = exp2_taylor5_x8(
+            (self.pitch_envelope(params) * params.pitch_env_amount + self.pitch_offset) / 12.0,
+        );
+        OscillatorBatch::calculate_delta(
+            self.get_lerped_frequency() * pitch_mod,
+            params.sample_rate,
+        )
+    }
+    /// The phase and waveform part of a step. Waveforms generated per oscillator are only
+    /// generated for the oscillators in the `lanes` bitmask, the others are left as sines.
+    fn step_wave(
+        &mut self,
+        params: &OscParamsBatch,
+        input: &MatrixInput,
+        wavetables: &Wavetables,
+        phase_delta: f32x8,
+        lanes: i32,
+    ) -> f32x8 {
+        self.phase = input.sync.blend(f32x8::splat(0.0), self.phase);
         // Feedback implementation from the Surge XT FM2/FM3/Sine oscillators, which in turn were based on the DX7 feedback
         let prev = (self.previous_wave[0] + self.previous_wave[1]) / 2.0;
         // let feedback = if params.feedback.is_sign_negative() {
@@ -999,19 +1782,25 @@ impl OscillatorBatch {
         //     prev
         // } * params.feedback.abs();
         let feedback = {
-            let negative_feedback = (prev * prev) & params.feedback.cmp_lt(0.0);
-            let positive_feedback = prev & params.feedback.cmp_ge(0.0);
-            (negative_feedback + positive_feedback) * params.feedback.abs()
+            let amount = (params.feedback + self.feedback_offset)
+                .fast_max(f32x8::splat(-1.0))
+                .fast_min(f32x8::splat(1.0));
+            let negative_feedback = (prev * prev) & amount.cmp_lt(0.0);
+            let positive_feedback = prev & amount.cmp_ge(0.0);
+            (negative_feedback + positive_feedback) * amount.abs()
         };
         let phase = {
-            let phase = self.phase + feedback + pm;
+            let phase = self.phase + feedback + input.phase;
             let mut phase = phase.to_array();
             let phaseshape_amount = params.phaseshaper_amount * 0.01;
-            for (phase, amount, waveshaper) in izip!(
+            for (i, (phase, amount, waveshaper)) in izip!(
                 phase.iter_mut(),
                 phaseshape_amount.as_array_ref(),
                 &params.phaseshaper
-            ) {
+            )
+            .enumerate()
+            .filter(|(i, _)| lanes & (1 << i) != 0)
+            {
                 let inner_phase = (phase.fract() + 1.0).fract();
                 *phase = waveshaper.phaseshape(inner_phase, *amount);
             }
@@ -1020,32 +1809,99 @@ impl OscillatorBatch {
         // let phase = self_phase + feedback;
         self.previous_wave[1] = self.previous_wave[0];
         let out = {
-            let mut sine = (phase * std::f32::consts::TAU + params.phase_offset)
+            let mut wave = (phase * std::f32::consts::TAU + params.phase_offset)
                 .sin()
                 .to_array();
+            // Sines stay on the SIMD path above, other waveforms are generated per oscillator
+            if params.waveform.iter().any(|waveform| *waveform != Waveform::Sine) {
+                let phase = phase + params.phase_offset / TAU;
+                for (i, wave) in wave
+                    .iter_mut()
+                    .enumerate()
+                    .filter(|(i, _)| lanes & (1 << i) != 0)
+                {
+                    let phase = (phase.as_array_ref()[i].fract() + 1.0).fract();
+                    let phase_delta = phase_delta.as_array_ref()[i];
+                    match (params.waveform[i], &wavetables[i]) {
+                        (Waveform::Sine, _) => {}
+                        (Waveform::Wavetable, Some(wavetable)) => {
+                            let position = params.wavetable_position.as_array_ref()[i];
+                            *wave = wavetable.sample(phase, position, phase_delta)
+                        }
+                        (Waveform::AudioIn, _) => *wave = input.audio.as_array_ref()[i],
+                        (Waveform::Noise, _) => {
+                            let noise = &mut self.noise[i];
+                            let raw = params.noise_type[i].generate(noise, phase_delta);
+                            *wave = noise.tone(
+                                raw,
+                                params.noise_tone.as_array_ref()[i],
+                                params.sample_rate.as_array_ref()[i],
+                            )
+                        }
+                        (waveform, _) => *wave = waveform.generate(phase, phase_delta),
+                    }
+                }
+            }
             let waveshape_amount = params.waveshaper_amount * 0.01;
-            for (sine, amount, waveshaper) in izip!(
-                sine.iter_mut(),
+            for (_, (wave, amount, waveshaper)) in izip!(
+                wave.iter_mut(),
                 waveshape_amount.as_array_ref(),
                 &params.waveshaper
-            ) {
-                *sine = waveshaper.waveshape(*sine, *amount);
+            )
+            .enumerate()
+            .filter(|(i, _)| lanes & (1 << i) != 0)
+            {
+                *wave = waveshaper.waveshape(*wave, *amount);
             }
-            f32x8::from(sine)
+            f32x8::from(wave)
         };
         self.previous_wave[0] = out;
-        self.add_phase(OscillatorBatch::calculate_delta(
-            self.get_lerped_frequency(),
-            params.sample_rate,
-        ));
-        out * self.gain
+        self.add_phase(phase_delta * (1.0 + input.linear_fm));
+        out * self.gain * input.ring
+    }
+    pub fn step_with_envelope(
+        &mut self,
+        params: &OscParamsBatch,
+        input: &MatrixInput,
+        wavetables: &Wavetables,
+    ) -> f32x8 {
+        let phase_delta = self.advance(params);
+        let envelope = self.step_envelope(params);
+        self.step_output(params, input, wavetables, phase_delta, envelope, ALL_LANES)
+    }
+    /// Moves the envelopes on by a sample and returns their level.
+    fn step_envelope(&mut self, params: &OscParamsBatch) -> f32x8 {
+        let envelope = if params.envelope_mode.contains(&EnvelopeMode::MultiStage) {
+            let multi_stage = self
+                .multi_stage
+                .step(params, self.time_scale, self.release_time.is_some());
+            params
+                .multi_stage_mask()
+                .blend(multi_stage, self.envelope(params))
+        } else {
+            self.envelope(params)
+        };
+        self.envelope_level = envelope;
+        envelope
     }
-    pub fn step_with_envelope(&mut self, params: &OscParamsBatch, pm: f32x8) -> f32x8 {
-        self.previous_output = self.step(params, pm) * self.envelope(params);
+    /// The rest of [`OscillatorBatch::step_with_envelope()`] once the time and envelopes moved on.
+    fn step_output(
+        &mut self,
+        params: &OscParamsBatch,
+        input: &MatrixInput,
+        wavetables: &Wavetables,
+        phase_delta: f32x8,
+        envelope: f32x8,
+        lanes: i32,
+    ) -> f32x8 {
+        let out = self.step_wave(params, input, wavetables, phase_delta, lanes);
+        self.previous_output = out * envelope;
         self.previous_output * params.output_gain
     }
     pub fn release(&mut self, params: &OscParamsBatch) {
         self.release_start_level = self.envelope(params);
+        self.pitch_release_start_level = self.pitch_envelope(params);
+        self.multi_stage.release(params);
         self.release_time = Some(self.time);
     }
     pub fn time_since_release(&self) -> Option<f32x8> {
@@ -1057,8 +1913,11 @@ impl OscillatorBatch {
     }
     pub fn is_done(&self, params: &OscParamsBatch) -> bool {
         if let Some(released_time) = self.time_since_release() {
-            (released_time / params.sample_rate)
-                .cmp_ge(params.release)
+            let released = (released_time / (params.sample_rate * self.time_scale))
+                .cmp_ge(params.release);
+            params
+                .multi_stage_mask()
+                .blend(self.multi_stage.finished(), released)
                 .all()
         } else {
             false
@@ -1072,7 +1931,12 @@ impl OscillatorBatch {
         // if self.phase >= 1.0 {
         //     self.phase -= 1.0;
         // }
-        self.phase -= f32x8::splat(1.0) & self.phase.cmp_ge(1.0);
+        let wrapped_up = self.phase.cmp_ge(1.0);
+        // Through-zero FM can run the phase backwards
+        let wrapped_down = self.phase.cmp_lt(0.0);
+        self.phase -= f32x8::splat(1.0) & wrapped_up;
+        self.phase += f32x8::splat(1.0) & wrapped_down;
+        self.wrapped = wrapped_up | wrapped_down;
     }
     pub fn calculate_sine(&mut self, phase_delta: f32x8) -> f32x8 {
         let sine = (self.phase * std::f32::consts::TAU).sin();
@@ -1084,6 +1948,170 @@ impl OscillatorBatch {
     pub fn previous(&self) -> f32x8 {
         self.previous_output * self.gain
     }
+    /// Steps the batch with the modulation matrix applied, in order when it's zero-delay.
+    pub fn step_matrix(
+        &mut self,
+        params: &OscParamsBatch,
+        mod_matrix: &ModMatrix,
+        wavetables: &Wavetables,
+        sidechain: f32,
+    ) -> f32x8 {
+        let order = match &mod_matrix.order {
+            Some(order) => order,
+            None => {
+                let input = mod_matrix.input(self.previous(), self.wrapped(), sidechain);
+                return self.step_with_envelope(params, &input, wavetables);
+            }
+        };
+        // The time and envelopes don't depend on the matrix, so they only move on once. Then
+        // every level runs the phase and waveform from the same starting state, and the
+        // oscillators in that level take their input from it, reading the outputs of the levels
+        // before them and the previous sample for connections in a cycle. The oscillators don't
+        // affect each other within a step, so the last run with every level's input gives each
+        // oscillator the result of its own level, the runs before it only need their own level.
+        let previous = self.previous();
+        let phase_delta = self.advance(params);
+        let envelope = self.step_envelope(params);
+        let start = *self;
+        let mut current = previous;
+        let mut input = MatrixInput::default();
+        let mut out = f32x8::splat(0.0);
+        let levels = order.levels();
+        for (index, level) in levels.iter().enumerate() {
+            *self = start;
+            let sources = order.sources(previous, current);
+            let level_input = mod_matrix.input_from(sources, start.wrapped(), sidechain);
+            input = input.blend(*level, &level_input);
+            let lanes = if index == levels.len() - 1 {
+                ALL_LANES
+            } else {
+                level.move_mask()
+            };
+            out = self.step_output(params, &input, wavetables, phase_delta, envelope, lanes);
+            current = level.blend(self.previous(), current);
+        }
+        out
+    }
+    /// Mask of the oscillators whose phase wrapped around on the last sample, for hard sync.
+    pub fn wrapped(&self) -> f32x8 {
+        self.wrapped
+    }
+}
+
+/// Per-oscillator state of the multi-stage envelope. Stage `n` moves from the previous level to
+/// the level of point `n` over that point's time, so the envelope is finished once it has passed
+/// the last point.
+#[derive(Debug, Clone, Copy)]
+pub struct MultiStageEnvelope {
+    stage: [usize; 8],
+    stage_time: [f32; 8],
+    start_level: [f32; 8],
+    level: [f32; 8],
+    /// Time multiplier for the first stage, from velocity.
+    attack_scale: [f32; 8],
+}
+
+impl MultiStageEnvelope {
+    pub fn new(params: &OscParamsBatch, attack_scale: f32x8) -> Self {
+        Self {
+            stage: [0; 8],
+            stage_time: [0.0; 8],
+            start_level: params.attack_level.to_array(),
+            level: params.attack_level.to_array(),
+            attack_scale: attack_scale.to_array(),
+        }
+    }
+    /// Advances the lanes which use the multi-stage envelope by one sample and returns the
+    /// envelope level of every lane. Point times are multiplied by `time_scale`.
+    pub fn step(&mut self, params: &OscParamsBatch, time_scale: f32x8, released: bool) -> f32x8 {
+        let delta = 1.0 / (params.sample_rate * time_scale);
+        for lane in 0..8 {
+            if params.envelope_mode[lane] == EnvelopeMode::MultiStage {
+                self.stage_time[lane] += delta.as_array_ref()[lane];
+                self.step_lane(params, lane, released);
+            }
+        }
+        f32x8::from(self.level)
+    }
+    fn step_lane(&mut self, params: &OscParamsBatch, lane: usize, released: bool) {
+        let sustain = Self::sustain_point(params, lane);
+        let loop_start = params.multi_stage_loop[lane].filter(|&point| point <= sustain);
+        // Zero-length stages are passed within the same sample. The pass count is bounded so a
+        // loop made entirely of zero-length stages can't stall the audio thread.
+        for _ in 0..=MULTI_STAGE_POINTS {
+            let stage = self.stage[lane];
+            if stage >= MULTI_STAGE_POINTS {
+                return;
+            }
+            let target = params.multi_stage_levels[stage].as_array_ref()[lane];
+            let time = params.multi_stage_times[stage].as_array_ref()[lane]
+                * if stage == 0 { self.attack_scale[lane] } else { 1.0 };
+            let start = self.start_level[lane];
+            if self.stage_time[lane] < time {
+                let t = self.stage_time[lane] / time;
+                self.level[lane] = if released {
+                    let curve = params.release_curve.as_array_ref()[lane];
+                    lerp(target, start, (1.0 - t).powf(curve))
+                } else if target >= start {
+                    let curve = params.attack_curve.as_array_ref()[lane];
+                    lerp(start, target, t.powf(curve))
+                } else {
+                    let curve = params.decay_curve.as_array_ref()[lane];
+                    lerp(target, start, (1.0 - t).powf(curve))
+                };
+                return;
+            }
+            self.level[lane] = target;
+            self.start_level[lane] = target;
+            if stage == sustain && !released {
+                if let Some(loop_start) = loop_start {
+                    self.stage_time[lane] -= time;
+                    self.stage[lane] = loop_start;
+                    continue;
+                }
+                self.stage_time[lane] = time;
+                return;
+            }
+            self.stage_time[lane] -= time;
+            self.stage[lane] += 1;
+        }
+    }
+    /// Skips every lane ahead to the stage after its sustain point, starting from its current
+    /// level.
+    pub fn release(&mut self, params: &OscParamsBatch) {
+        for lane in 0..8 {
+            let sustain = Self::sustain_point(params, lane);
+            if self.stage[lane] <= sustain {
+                self.stage[lane] = sustain + 1;
+                self.stage_time[lane] = 0.0;
+                self.start_level[lane] = self.level[lane];
+            }
+        }
+    }
+    /// Returns released lanes to their sustain point, for when a legato note takes over a voice.
+    pub fn resume(&mut self, params: &OscParamsBatch) {
+        for lane in 0..8 {
+            let sustain = Self::sustain_point(params, lane);
+            if self.stage[lane] > sustain {
+                self.stage[lane] = sustain;
+                self.stage_time[lane] = 0.0;
+                self.start_level[lane] = self.level[lane];
+            }
+        }
+    }
+    /// The point a lane holds at until release. Sustaining on the last point would leave no
+    /// stage to release through, so the point before it is used instead.
+    fn sustain_point(params: &OscParamsBatch, lane: usize) -> usize {
+        params.multi_stage_sustain[lane].min(MULTI_STAGE_POINTS - 2)
+    }
+    /// Lanes which have passed the last point.
+    pub fn finished(&self) -> f32x8 {
+        f32x8::from(
+            self.stage
+                .map(|stage| if stage >= MULTI_STAGE_POINTS { 1.0 } else { 0.0 }),
+        )
+        .cmp_eq(1.0)
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -1242,3 +2270,80 @@ impl PolyFoam {
         self.last_oscillator_output
     }
 }
+
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_multi_stage_release_from_last_point() {
+        let mut levels = [1.0; MULTI_STAGE_POINTS];
+        levels[MULTI_STAGE_POINTS - 1] = 0.0;
+        let osc = OscParams {
+            sample_rate: 1000.0,
+            attack_curve: 1.0,
+            decay_curve: 1.0,
+            release_curve: 1.0,
+            envelope_mode: EnvelopeMode::MultiStage,
+            multi_stage_times: [0.01; MULTI_STAGE_POINTS],
+            multi_stage_levels: levels,
+            multi_stage_sustain: MULTI_STAGE_POINTS - 1,
+            ..Default::default()
+        };
+        let params = OscParamsBatch::from([osc; 8]);
+        let time_scale = f32x8::splat(1.0);
+        let mut envelope = MultiStageEnvelope::new(&params, time_scale);
+        for _ in 0..200 {
+            envelope.step(&params, time_scale, false);
+        }
+        assert_eq!(envelope.step(&params, time_scale, false).as_array_ref()[0], 1.0);
+
+        // Releasing fades out over the last point's time rather than cutting off
+        envelope.release(&params);
+        let level = envelope.step(&params, time_scale, true).as_array_ref()[0];
+        assert!(level > 0.5 && level < 1.0);
+        assert_eq!(envelope.finished().move_mask(), 0);
+        for _ in 0..20 {
+            envelope.step(&params, time_scale, true);
+        }
+        assert_eq!(envelope.finished().move_mask(), 0xff);
+        assert_eq!(envelope.step(&params, time_scale, true).as_array_ref()[0], 0.0);
+    }
+
+    #[test]
+    fn test_zero_delay_feedback() {
+        let osc = OscParams {
+            output_gain: 1.0,
+            sample_rate: 48000.0,
+            frequency_mult: 1.0,
+            octave_stretch: 1.0,
+            attack_level: 1.0,
+            attack: 0.001,
+            decay: 0.001,
+            sustain: 1.0,
+            release: 0.1,
+            attack_curve: 1.0,
+            decay_curve: 1.0,
+            release_curve: 1.0,
+            ..Default::default()
+        };
+        let params = OscParamsBatch::from([osc; 8]);
+        // Only self-modulation, which keeps the 1-sample delay either way
+        let amounts = array::from_fn(|target| {
+            array::from_fn(|source| if target == source { 1.0 } else { 0.0 })
+        });
+        let modes = [[MatrixMode::Phase; 8]; 8];
+        let delayed = ModMatrix::new(amounts, modes, [0.0; 8], false);
+        let zero_delay = ModMatrix::new(amounts, modes, [0.0; 8], true);
+        let wavetables = Wavetables::default();
+        let mut a = OscillatorBatch::new(60, &params, 1.0);
+        let mut b = a;
+        let mut heard = false;
+        for _ in 0..1000 {
+            let a = a.step_matrix(&params, &delayed, &wavetables, 0.0);
+            let b = b.step_matrix(&params, &zero_delay, &wavetables, 0.0);
+            assert_eq!(a.to_array(), b.to_array());
+            heard |= a.reduce_add() != 0.0;
+        }
+        assert!(heard);
+    }
+}
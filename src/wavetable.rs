@@ -0,0 +1,446 @@
+use std::{
+    io::Cursor,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use realfft::{num_complex::Complex, RealFftPlanner};
+use serde::{Deserialize, Serialize};
+
+use crate::dsp::interpolation::{catmull_rom, lerp};
+
+/// Frame size used for multi-frame files without a `clm` chunk, matching Serum's default.
+const DEFAULT_FRAME_SIZE: usize = 2048;
+/// Files up to this length without a `clm` chunk are treated as a single cycle.
+const MAX_SINGLE_CYCLE_SIZE: usize = 4096;
+/// Alphabet of the base64 that embedded samples are saved as.
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+/// Upper limit on frames per wavetable, so a long sample doesn't produce a huge table.
+const MAX_FRAMES: usize = 256;
+const MIN_FRAME_SIZE: usize = 4;
+/// Shortest mipmap table. Levels with few harmonics are oversampled up to this length so the
+/// interpolation stays smooth.
+const MIN_TABLE_SIZE: usize = 64;
+
+/// Source of [`WavetableBank::version`]s. Restoring a saved state replaces the whole bank, so
+/// versions need to be unique across banks rather than just incrementing.
+static NEXT_VERSION: AtomicU64 = AtomicU64::new(1);
+
+/// One table per oscillator.
+pub type Wavetables = [Option<Arc<Wavetable>>; 8];
+
+/// The raw samples of a wavetable as loaded from disk, which the band-limited [`Wavetable`] is
+/// built from. The plugin state only stores the file's path and loads the file again when it's
+/// restored, unless the samples are embedded.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "SavedSource", into = "SavedSource")]
+pub struct WavetableSource {
+    /// File name the table was loaded from, shown in the editor.
+    pub name: String,
+    pub path: PathBuf,
+    /// Whether the samples are saved with the plugin state, so the patch works without the file.
+    pub embed: bool,
+    pub frame_size: usize,
+    pub samples: Vec<f32>,
+}
+impl WavetableSource {
+    /// Loads the first channel of a WAV file. The frame size is read from Serum's `clm` chunk if
+    /// there is one, otherwise short files are treated as a single cycle.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        let bytes = std::fs::read(path).map_err(|e| e.to_string())?;
+        let mut reader = hound::WavReader::new(Cursor::new(&bytes)).map_err(|e| e.to_string())?;
+        let spec = reader.spec();
+        let channels = spec.channels.max(1) as usize;
+        let samples: Vec<f32> = match spec.sample_format {
+            hound::SampleFormat::Float => reader
+                .samples::<f32>()
+                .step_by(channels)
+                .collect::<Result<_, _>>()
+                .map_err(|e| e.to_string())?,
+            hound::SampleFormat::Int => {
+                let scale = 1.0 / (1u32 << (spec.bits_per_sample - 1)) as f32;
+                reader
+                    .samples::<i32>()
+                    .step_by(channels)
+                    .map(|sample| sample.map(|sample| sample as f32 * scale))
+                    .collect::<Result<_, _>>()
+                    .map_err(|e| e.to_string())?
+            }
+        };
+        if samples.is_empty() {
+            return Err("File contains no samples".to_string());
+        }
+
+        let frame_size = match clm_frame_size(&bytes) {
+            Some(frame_size) => frame_size,
+            None if samples.len() <= MAX_SINGLE_CYCLE_SIZE => samples.len(),
+            None => DEFAULT_FRAME_SIZE,
+        };
+        if frame_size < MIN_FRAME_SIZE {
+            return Err(format!("Frame size of {frame_size} samples is too small"));
+        }
+        if samples.len() < frame_size {
+            return Err(format!(
+                "File is shorter than one {frame_size} sample frame"
+            ));
+        }
+        let frames = (samples.len() / frame_size).min(MAX_FRAMES);
+        let mut samples = samples;
+        samples.truncate(frames * frame_size);
+
+        Ok(Self {
+            name: path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            path: std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf()),
+            embed: false,
+            frame_size,
+            samples,
+        })
+    }
+    /// Whether the source holds at least one usable frame. Saved states aren't trusted blindly,
+    /// and a file that's gone leaves no samples.
+    pub fn is_valid(&self) -> bool {
+        self.frame_size >= MIN_FRAME_SIZE && self.samples.len() >= self.frame_size
+    }
+}
+
+/// How a [`WavetableSource`] is saved. Embedded samples are stored as base64 of their
+/// little-endian bytes, which is a fraction of the size of a list of numbers.
+#[derive(Serialize, Deserialize)]
+struct SavedSource {
+    name: String,
+    #[serde(default)]
+    path: PathBuf,
+    frame_size: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    embedded: Option<String>,
+    /// States from before the path was saved held every sample as a number.
+    #[serde(default, skip_serializing)]
+    samples: Option<Vec<f32>>,
+}
+impl From<WavetableSource> for SavedSource {
+    fn from(source: WavetableSource) -> Self {
+        Self {
+            embedded: source.embed.then(|| encode_samples(&source.samples)),
+            name: source.name,
+            path: source.path,
+            frame_size: source.frame_size,
+            samples: None,
+        }
+    }
+}
+impl From<SavedSource> for WavetableSource {
+    fn from(saved: SavedSource) -> Self {
+        let embed = saved.embedded.is_some() || saved.samples.is_some();
+        let samples = match (saved.embedded, saved.samples) {
+            (Some(text), _) => decode_samples(&text).unwrap_or_default(),
+            (None, Some(samples)) => samples,
+            (None, None) => match Self::load(&saved.path) {
+                Ok(source) => {
+                    return Self {
+                        name: saved.name,
+                        ..source
+                    }
+                }
+                // Kept without samples so the editor can show which file is missing
+                Err(_) => Vec::new(),
+            },
+        };
+        Self {
+            name: saved.name,
+            path: saved.path,
+            embed,
+            frame_size: saved.frame_size,
+            samples,
+        }
+    }
+}
+
+fn encode_samples(samples: &[f32]) -> String {
+    let bytes: Vec<u8> = samples
+        .iter()
+        .flat_map(|sample| sample.to_le_bytes())
+        .collect();
+    let mut text = String::with_capacity((bytes.len() + 2) / 3 * 4);
+    for chunk in bytes.chunks(3) {
+        let mut group = [0; 4];
+        group[1..=chunk.len()].copy_from_slice(chunk);
+        let bits = u32::from_be_bytes(group);
+        for i in 0..4 {
+            text.push(if i <= chunk.len() {
+                BASE64[(bits >> (18 - 6 * i) & 63) as usize] as char
+            } else {
+                '='
+            });
+        }
+    }
+    text
+}
+
+fn decode_samples(text: &str) -> Option<Vec<f32>> {
+    let mut bytes = Vec::with_capacity(text.len() / 4 * 3);
+    for chunk in text.trim_end_matches('=').as_bytes().chunks(4) {
+        let mut bits = 0;
+        for (i, &c) in chunk.iter().enumerate() {
+            let value = match c {
+                b'A'..=b'Z' => c - b'A',
+                b'a'..=b'z' => c - b'a' + 26,
+                b'0'..=b'9' => c - b'0' + 52,
+                b'+' => 62,
+                b'/' => 63,
+                _ => return None,
+            };
+            bits |= (value as u32) << (18 - 6 * i);
+        }
+        bytes.extend_from_slice(&bits.to_be_bytes()[1..chunk.len()]);
+    }
+    if bytes.len() % 4 != 0 {
+        return None;
+    }
+    Some(
+        bytes
+            .chunks_exact(4)
+            .map(|sample| f32::from_le_bytes([sample[0], sample[1], sample[2], sample[3]]))
+            .collect(),
+    )
+}
+
+/// Reads the frame size from the `clm` chunk Serum writes into its wavetables, which starts with
+/// `<!>` followed by the frame size as text.
+fn clm_frame_size(bytes: &[u8]) -> Option<usize> {
+    if bytes.len() < 12 || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+        return None;
+    }
+    let mut offset = 12;
+    while offset + 8 <= bytes.len() {
+        let id = &bytes[offset..offset + 4];
+        let size = u32::from_le_bytes(bytes[offset + 4..offset + 8].try_into().ok()?) as usize;
+        let data = bytes.get(offset + 8..(offset + 8 + size).min(bytes.len()))?;
+        if id == b"clm " {
+            let text = data.strip_prefix(b"<!>")?;
+            let digits = text.iter().take_while(|c| c.is_ascii_digit()).count();
+            return std::str::from_utf8(&text[..digits])
+                .ok()?
+                .parse()
+                .ok()
+                .filter(|size| *size > 0);
+        }
+        // Chunks are padded to an even length
+        offset += 8 + size + (size & 1);
+    }
+    None
+}
+
+/// A band-limited wavetable. Every frame is stored as a set of mipmaps, where level `n` holds
+/// at most `frame_size / 2 >> n` harmonics, so higher notes can read from a table that doesn't
+/// alias.
+#[derive(Debug, Clone)]
+pub struct Wavetable {
+    /// Indexed by frame, then mipmap level.
+    frames: Vec<Vec<Vec<f32>>>,
+    /// Harmonics in the first mipmap level.
+    harmonics: usize,
+}
+impl Wavetable {
+    pub fn new(source: &WavetableSource) -> Self {
+        let frame_size = source.frame_size;
+        let harmonics = (frame_size / 2).max(1);
+        let mut planner = RealFftPlanner::<f32>::new();
+        let forward = planner.plan_fft_forward(frame_size);
+        let mut input = forward.make_input_vec();
+        let mut spectrum = forward.make_output_vec();
+
+        let frames = source
+            .samples
+            .chunks_exact(frame_size)
+            .map(|frame| {
+                input.copy_from_slice(frame);
+                forward.process(&mut input, &mut spectrum).unwrap();
+
+                let mut levels = Vec::new();
+                let mut level = 0;
+                while harmonics >> level > 0 {
+                    let length = (frame_size >> level).max(MIN_TABLE_SIZE);
+                    // Harmonics at or above the table's Nyquist frequency can't be represented
+                    let kept = (harmonics >> level).min(length / 2 - 1);
+                    let inverse = planner.plan_fft_inverse(length);
+                    let mut level_spectrum = inverse.make_input_vec();
+                    level_spectrum[0] = Complex::new(spectrum[0].re, 0.0);
+                    level_spectrum[1..=kept].copy_from_slice(&spectrum[1..=kept]);
+                    let mut table = inverse.make_output_vec();
+                    inverse.process(&mut level_spectrum, &mut table).unwrap();
+                    let scale = 1.0 / frame_size as f32;
+                    table.iter_mut().for_each(|sample| *sample *= scale);
+                    levels.push(table);
+                    level += 1;
+                }
+                levels
+            })
+            .collect();
+
+        Self { frames, harmonics }
+    }
+
+    /// `phase` should be between 0 and 1, `position` between 0 and 1 picks the frame and
+    /// `phase_delta` chooses the mipmap level.
+    pub fn sample(&self, phase: f32, position: f32, phase_delta: f32) -> f32 {
+        let levels = self.frames[0].len();
+        let level = (self.harmonics as f32 * 2.0 * phase_delta.abs())
+            .log2()
+            .ceil()
+            .max(0.0) as usize;
+        let level = level.min(levels - 1);
+
+        let position = position.clamp(0.0, 1.0) * (self.frames.len() - 1) as f32;
+        let frame = position as usize;
+        let next_frame = (frame + 1).min(self.frames.len() - 1);
+        lerp(
+            Self::read(&self.frames[frame][level], phase),
+            Self::read(&self.frames[next_frame][level], phase),
+            position.fract(),
+        )
+    }
+
+    fn read(table: &[f32], phase: f32) -> f32 {
+        let length = table.len();
+        let position = phase * length as f32;
+        let index = position as usize;
+        catmull_rom(
+            (
+                table[(index + length - 1) % length],
+                table[index % length],
+                table[(index + 1) % length],
+                table[(index + 2) % length],
+            ),
+            position.fract(),
+        )
+    }
+}
+
+/// The wavetables loaded into each oscillator, persisted with the plugin state.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct WavetableBank {
+    pub sources: [Option<WavetableSource>; 8],
+    /// Band-limited tables built from `sources`.
+    #[serde(skip)]
+    pub tables: Wavetables,
+    /// Changes whenever `tables` changes, so the audio thread knows to pick up the new ones.
+    #[serde(skip)]
+    pub version: u64,
+    /// Replaced tables the audio thread may still be using, with the version that replaced
+    /// them. Holding on to them here means the audio thread never frees a table.
+    #[serde(skip)]
+    retired: Vec<(u64, Arc<Wavetable>)>,
+    /// The newest version the audio thread has picked up, see [`WavetableBank::acknowledge`].
+    #[serde(skip)]
+    acknowledged: AtomicU64,
+}
+impl WavetableBank {
+    pub fn set(&mut self, index: usize, source: Option<WavetableSource>) {
+        let source = source.filter(WavetableSource::is_valid);
+        let table = source
+            .as_ref()
+            .map(|source| Arc::new(Wavetable::new(source)));
+        let replaced = std::mem::replace(&mut self.tables[index], table);
+        self.sources[index] = source;
+        self.version = NEXT_VERSION.fetch_add(1, Ordering::Relaxed);
+        self.retire(replaced);
+    }
+    /// Exchanges the tables of two oscillators, used when swapping the oscillators themselves.
+    pub fn swap(&mut self, a: usize, b: usize) {
+        self.sources.swap(a, b);
+        self.tables.swap(a, b);
+        self.version = NEXT_VERSION.fetch_add(1, Ordering::Relaxed);
+    }
+    /// Rebuilds every table after the sources were restored from a saved state.
+    pub fn rebuild(&mut self) {
+        let tables = std::array::from_fn(|index| {
+            self.sources[index]
+                .as_ref()
+                .filter(|source| source.is_valid())
+                .map(|source| Arc::new(Wavetable::new(source)))
+        });
+        let replaced = std::mem::replace(&mut self.tables, tables);
+        self.version = NEXT_VERSION.fetch_add(1, Ordering::Relaxed);
+        for table in replaced {
+            self.retire(table);
+        }
+    }
+    /// Called by the audio thread once it has switched to `version`. Tables replaced before
+    /// that are freed by the next change to the bank.
+    pub fn acknowledge(&self, version: u64) {
+        self.acknowledged.store(version, Ordering::Relaxed);
+    }
+
+    /// Keeps a replaced table until the audio thread has moved on from it, and frees the ones it
+    /// already has.
+    fn retire(&mut self, table: Option<Arc<Wavetable>>) {
+        let acknowledged = self.acknowledged.load(Ordering::Relaxed);
+        self.retired
+            .retain(|(replaced_in, _)| *replaced_in > acknowledged);
+        if let Some(table) = table {
+            self.retired.push((self.version, table));
+        }
+    }
+}
+
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_retired_tables() {
+        let source = WavetableSource {
+            name: "saw".to_string(),
+            path: PathBuf::new(),
+            embed: false,
+            frame_size: 64,
+            samples: (0..64).map(|i| i as f32 / 32.0 - 1.0).collect(),
+        };
+        let mut bank = WavetableBank::default();
+        bank.set(0, Some(source.clone()));
+        // The audio thread picks up the first table
+        let playing = bank.tables[0].clone().unwrap();
+        bank.acknowledge(bank.version);
+
+        // Replacing it keeps it alive until the audio thread has switched
+        bank.set(0, Some(source.clone()));
+        bank.set(1, Some(source));
+        assert_eq!(Arc::strong_count(&playing), 2);
+        bank.acknowledge(bank.version);
+        bank.set(1, None);
+        assert_eq!(Arc::strong_count(&playing), 1);
+    }
+
+    #[test]
+    fn test_embedded_samples() {
+        for length in 0..5 {
+            let samples: Vec<f32> = (0..length).map(|i| i as f32 * -0.37 + 0.1).collect();
+            assert_eq!(decode_samples(&encode_samples(&samples)), Some(samples));
+        }
+        assert_eq!(encode_samples(&[1.0]), "AACAPw==");
+        assert_eq!(decode_samples("AAC*Pw=="), None);
+
+        let source = WavetableSource {
+            name: "table.wav".to_string(),
+            path: PathBuf::from("/nowhere/table.wav"),
+            embed: true,
+            frame_size: 4,
+            samples: vec![0.0, 1.0, 0.0, -1.0],
+        };
+        let saved = WavetableSource::from(SavedSource::from(source.clone()));
+        assert_eq!(saved.samples, source.samples);
+        // Without embedding only the path is saved, and a missing file leaves no samples
+        let saved = WavetableSource::from(SavedSource::from(WavetableSource {
+            embed: false,
+            ..source
+        }));
+        assert!(saved.samples.is_empty() && !saved.is_valid());
+        assert_eq!(saved.name, "table.wav");
+    }
+}